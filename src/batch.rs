@@ -0,0 +1,205 @@
+use std::{collections::HashSet, io::Write, path::{Component, Path, PathBuf}};
+
+use crate::*;
+
+/// Extensions a filled template can end in that are taken as the file's, rather than as part of its name
+const EXPORT_EXTENSIONS: [&str; 4] = ["wav", "flac", "ogg", "opus"];
+
+/// Which renders a batch makes
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchVariations {
+	/// `count` renders with seeds counting up from `first_seed`
+	Seeds { count: usize, first_seed: u64 },
+	/// One render per value of a parameter, all with the same seed
+	Sweep { parameter: String, values: Vec<f64>, seed: u64 },
+}
+
+impl Default for BatchVariations {
+	fn default() -> Self {
+		BatchVariations::Seeds { count: 1, first_seed: 0 }
+	}
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchOptions {
+	pub variations: BatchVariations,
+	/// Parameter values for every render, on top of the definition's defaults
+	pub parameters: Vec<(String, f64)>,
+	/// File name of each render. `{name}`, `{index}`, `{seed}`, `{param}` for the swept value, and any parameter by its name,
+	/// with `{index:02}` padding a value with zeros to a width. The export's extension is added unless the name already ends in an export extension.
+	/// Other dots, like in `{param}` values or the sound's name, are part of the name.
+	pub template: String,
+	pub output_dir: PathBuf,
+	pub export: ExportOptions,
+	/// Normalizes every render on its own, so variations come out equally loud
+	pub loudness: Option<LoudnessTarget>,
+	/// Index of the first render
+	pub first_index: usize,
+}
+
+impl Default for BatchOptions {
+	fn default() -> Self {
+		Self {
+			variations: BatchVariations::default(),
+			parameters: vec![],
+			template: "{name}_{index:02}".into(),
+			output_dir: PathBuf::from("."),
+			export: ExportOptions::default(),
+			loudness: None,
+			first_index: 1,
+		}
+	}
+}
+
+
+/// What a batch wrote, saved next to the renders as `<name>_batch.json`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BatchManifest {
+	pub name: String,
+	pub renders: Vec<BatchRender>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchRender {
+	/// File name, relative to the manifest
+	pub file: String,
+	pub index: usize,
+	pub seed: u64,
+	/// Every parameter's value, defaults included
+	pub parameters: Vec<(String, f64)>,
+	pub frames: usize,
+	pub report: ExportReport,
+}
+
+impl BatchManifest {
+	pub fn to_json(&self) -> String {
+		let renders = self.renders.iter().map(|render| {
+			let parameters = render.parameters.iter().map(|(name, value)| format!("{}: {}", json_string(name), json_number(*value))).collect::<Vec<_>>();
+			let loudness = match &render.report.loudness {
+				Some(loudness) => format!(
+					"{{ \"gain_db\": {}, \"integrated\": {}, \"short_term_max\": {}, \"true_peak\": {}, \"peak_limited\": {} }}",
+					json_number(loudness.gain_db), json_number(loudness.normalized.integrated), json_number(loudness.normalized.short_term_max),
+					json_number(loudness.normalized.true_peak), loudness.peak_limited,
+				),
+				None => "null".into(),
+			};
+			format!(
+				"\t\t{{\n\t\t\t\"file\": {},\n\t\t\t\"index\": {},\n\t\t\t\"seed\": {},\n\t\t\t\"parameters\": {{ {} }},\n\t\t\t\"frames\": {},\n\t\t\t\"peak\": {},\n\t\t\t\"clipped_samples\": {},\n\t\t\t\"loudness\": {}\n\t\t}}",
+				json_string(&render.file), render.index, render.seed, parameters.join(", "), render.frames,
+				json_number(render.report.peak as f64), render.report.clipped_samples, loudness,
+			)
+		}).collect::<Vec<_>>();
+		
+		format!("{{\n\t\"name\": {},\n\t\"renders\": [\n{}\n\t]\n}}\n", json_string(&self.name), renders.join(",\n"))
+	}
+}
+
+
+
+/// Renders every variation of a sound and exports it under a name from the template, then writes the manifest.
+/// Each file records the sound, seed and parameter values it came from in its provenance metadata.
+/// File names are all worked out before anything renders, so a bad template fails without writing files.
+/// Names that would reach outside `output_dir` are rejected.
+pub fn render_batch(definition: &SoundDefinition, settings: &ProjectSettings, options: &BatchOptions) -> Result<BatchManifest, RenderError> {
+	let variations = match &options.variations {
+		BatchVariations::Seeds { count, first_seed } => (0..*count as u64)
+			.map(|i| RenderVariation { seed: first_seed + i, parameters: options.parameters.clone() })
+			.collect::<Vec<_>>(),
+		BatchVariations::Sweep { parameter, values, seed } => values.iter()
+			.map(|value| {
+				let mut parameters = options.parameters.iter().filter(|(name, _)| name != parameter).cloned().collect::<Vec<_>>();
+				parameters.push((parameter.clone(), *value));
+				RenderVariation { seed: *seed, parameters }
+			})
+			.collect(),
+	};
+	let swept = match &options.variations {
+		BatchVariations::Sweep { parameter, .. } => Some(parameter.as_str()),
+		BatchVariations::Seeds { .. } => None,
+	};
+	
+	let manifest_file = format!("{}_batch.json", definition.name);
+	check_file_name(&manifest_file)?;
+	
+	let mut files = Vec::with_capacity(variations.len());
+	let mut used = HashSet::new();
+	for (i, variation) in variations.iter().enumerate() {
+		let parameters = definition.parameter_values(variation)?;
+		let mut file = fill_template(&options.template, definition, options.first_index + i, variation.seed, swept, &parameters)?;
+		let has_extension = Path::new(&file).extension().is_some_and(|extension| EXPORT_EXTENSIONS.iter().any(|known| extension.eq_ignore_ascii_case(known)));
+		if !has_extension { file = format!("{file}.{}", options.export.extension()); }
+		check_file_name(&file)?;
+		if !used.insert(file.clone()) { return Err(RenderError::Template(format!("two renders are named {file}"))) }
+		files.push((file, parameters));
+	}
+	
+	std::fs::create_dir_all(&options.output_dir)?;
+	let renderer = SoundRenderer::new(definition, settings)?;
+	let mut manifest = BatchManifest { name: definition.name.clone(), renders: Vec::with_capacity(variations.len()) };
+	
+	for (i, (variation, (file, parameters))) in variations.iter().zip(files).enumerate() {
+		let track = renderer.render(variation)?;
+		
+		let mut export = options.export.clone();
+		export.metadata_mut().provenance = Some(definition.provenance(variation.seed, &parameters));
+		
+		let path = options.output_dir.join(&file);
+		let report = match options.loudness {
+			Some(target) => export_normalized(&track, &path, settings, &export, target)?,
+			None => export_track(&track, &path, settings, &export)?,
+		};
+		manifest.renders.push(BatchRender { file, index: options.first_index + i, seed: variation.seed, parameters, frames: track.length(), report });
+	}
+	
+	let json = manifest.to_json();
+	write_file_atomically(&options.output_dir.join(manifest_file), |writer| {
+		writer.write_all(json.as_bytes())?;
+		Ok(ExportReport::default())
+	})?;
+	Ok(manifest)
+}
+
+
+/// Only plain file names, so nothing is written outside the output directory
+fn check_file_name(file: &str) -> Result<(), RenderError> {
+	let mut components = Path::new(file).components();
+	// Backslashes are only separators on Windows, but the files may be copied there
+	let plain = matches!((components.next(), components.next()), (Some(Component::Normal(_)), None)) && !file.contains(['/', '\\']);
+	if !plain { return Err(RenderError::Template(format!("{file:?} isn't a plain file name"))) }
+	Ok(())
+}
+
+fn fill_template(template: &str, definition: &SoundDefinition, index: usize, seed: u64, swept: Option<&str>, parameters: &[(String, f64)]) -> Result<String, RenderError> {
+	let mut filled = String::with_capacity(template.len());
+	let mut rest = template;
+	
+	while let Some(open) = rest.find('{') {
+		filled.push_str(&rest[..open]);
+		let Some(close) = rest[open..].find('}') else { return Err(RenderError::Template(format!("unclosed `{{` in {template}"))) };
+		let field = &rest[(open + 1)..(open + close)];
+		rest = &rest[(open + close + 1)..];
+		
+		let (key, width) = match field.split_once(':') {
+			Some((key, width)) => (key, Some(width.trim_start_matches('0').parse::<usize>().map_err(|_| RenderError::Template(format!("bad width in {{{field}}}")))?)),
+			None => (field, None),
+		};
+		let parameter = |name: &str| parameters.iter().find(|(parameter, _)| parameter == name).map(|(_, value)| value.to_string());
+		let value = match key {
+			"name" => definition.name.clone(),
+			"index" => index.to_string(),
+			"seed" => seed.to_string(),
+			"param" => swept.and_then(parameter).unwrap_or_default(),
+			_ => parameter(key).ok_or_else(|| RenderError::Template(format!("no parameter {key} for {{{field}}}")))?,
+		};
+		
+		match width {
+			Some(width) if value.starts_with('-') => filled.push_str(&format!("-{:0>width$}", &value[1..], width = width.saturating_sub(1))),
+			Some(width) => filled.push_str(&format!("{value:0>width$}")),
+			None => filled.push_str(&value),
+		}
+	}
+	
+	filled.push_str(rest);
+	Ok(filled)
+}
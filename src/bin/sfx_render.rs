@@ -0,0 +1,14 @@
+//! The render and batch subcommands of sfx_daw on their own, for build machines without a GPU or audio device
+
+use std::process::ExitCode;
+
+use sfx_daw::{run_cli, CliStatus, USAGE};
+
+
+fn main() -> ExitCode {
+	let args = std::env::args().collect::<Vec<_>>();
+	run_cli(&args).unwrap_or_else(|| {
+		eprint!("{USAGE}");
+		ExitCode::from(CliStatus::Usage as u8)
+	})
+}
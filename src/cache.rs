@@ -0,0 +1,472 @@
+use std::{fs::File, io::{BufWriter, Read, Write}, path::{Path, PathBuf}, sync::atomic::{AtomicU64, Ordering}, time::UNIX_EPOCH};
+
+use symphonia::core::meta::StandardTagKey;
+
+use crate::*;
+
+
+const CACHE_MAGIC: &[u8; 4] = b"SFXC";
+/// Bump when the layout or anything that changes the decoded samples changes, old entries are then ignored
+const CACHE_VERSION: u32 = 3;
+/// Sample data starts here, aligned for mapping the file and viewing the data as f32 directly
+const CACHE_HEADER_SIZE: usize = 64;
+const STAMP_MAGIC: &[u8; 4] = b"SFXS";
+/// Magic, version, source size and modification time, content hash
+const STAMP_SIZE: usize = 44;
+
+/// Every standard tag key, so cached tags get theirs back. Keys missing here, from a newer symphonia, come back as plain tags.
+const STANDARD_TAG_KEYS: [StandardTagKey; 111] = {
+	use StandardTagKey::*;
+	[
+		AcoustidFingerprint, AcoustidId, Album, AlbumArtist, Arranger, Artist, Bpm, Comment, Compilation, Composer, Conductor, ContentGroup, Copyright,
+		Date, Description, DiscNumber, DiscSubtitle, DiscTotal, EncodedBy, Encoder, EncoderSettings, EncodingDate, Engineer, Ensemble, Genre,
+		IdentAsin, IdentBarcode, IdentCatalogNumber, IdentEanUpn, IdentIsrc, IdentPn, IdentPodcast, IdentUpc, Label, Language, License, Lyricist,
+		Lyrics, MediaFormat, MixDj, MixEngineer, Mood, MovementName, MovementNumber, MusicBrainzAlbumArtistId, MusicBrainzAlbumId,
+		MusicBrainzArtistId, MusicBrainzDiscId, MusicBrainzGenreId, MusicBrainzLabelId, MusicBrainzOriginalAlbumId, MusicBrainzOriginalArtistId,
+		MusicBrainzRecordingId, MusicBrainzReleaseGroupId, MusicBrainzReleaseStatus, MusicBrainzReleaseTrackId, MusicBrainzReleaseType,
+		MusicBrainzTrackId, MusicBrainzWorkId, Opus, OriginalAlbum, OriginalArtist, OriginalDate, OriginalFile, OriginalWriter, Owner, Part,
+		PartTotal, Performer, Podcast, PodcastCategory, PodcastDescription, PodcastKeywords, Producer, PurchaseDate, Rating, ReleaseCountry,
+		ReleaseDate, Remixer, ReplayGainAlbumGain, ReplayGainAlbumPeak, ReplayGainTrackGain, ReplayGainTrackPeak, Script, SortAlbum,
+		SortAlbumArtist, SortArtist, SortComposer, SortTrackTitle, TaggingDate, TrackNumber, TrackSubtitle, TrackTitle, TrackTotal, TvEpisode,
+		TvEpisodeTitle, TvNetwork, TvSeason, TvShowTitle, Url, UrlArtist, UrlCopyright, UrlInternetRadio, UrlLabel, UrlOfficial, UrlPayment,
+		UrlPodcast, UrlPurchase, UrlSource, Version, Writer,
+	]
+};
+
+/// Numbers temporary entries, with the process id, so concurrent writers of the same entry never share a file
+static NEXT_TEMP_FILE: AtomicU64 = AtomicU64::new(0);
+
+
+/// Store of loaded tracks, so files only go through symphonia and rubato once.
+///
+/// Entries are named after a hash of the source's bytes and everything that changes the loaded track
+/// (project rate, resampler quality, channel policy, decode error policy, region), and hold the loaded samples, decode issues and metadata.
+/// A source that changes hashes differently and misses, while moved, renamed and duplicated files all find the same entry.
+/// So that only changed files get hashed, each path's size, modification time and hash are kept in a stamp next to the entries.
+///
+/// Entry layout, little-endian: a 64 byte header (magic, version, source size, content hash, rate, channel count, frame count),
+/// then each channel's f32 samples one after the other, then the decode issues and the metadata.
+pub struct AudioCache {
+	dir: PathBuf,
+}
+
+impl AudioCache {
+	pub fn new<P>(dir: P) -> Result<Self, LoadError> where P: AsRef<Path> {
+		std::fs::create_dir_all(&dir)?;
+		Ok(Self { dir: dir.as_ref().to_path_buf() })
+	}
+	
+	pub fn dir(&self) -> &Path {
+		&self.dir
+	}
+	
+	/// Loads every file from the cache, decoding and storing the ones that aren't in it yet
+	pub fn load_audio<P>(&self, files_options: &[(P, LoadOptions)], settings: &ProjectSettings) -> Result<Vec<LoadedTrack>, LoadError> where P: AsRef<Path> {
+		files_options.iter().map(|(path, options)| self.load(path, options, settings)).collect()
+	}
+	
+	pub fn load<P>(&self, path: P, options: &LoadOptions, settings: &ProjectSettings) -> Result<LoadedTrack, LoadError> where P: AsRef<Path> {
+		let path = path.as_ref();
+		if let Some(loaded) = self.lookup(path, options, settings) { return Ok(loaded) }
+		
+		let loaded = load_audio_with_options(&[(path, options.clone())], settings)?.remove(0);
+		// A cache that can't be written to only means decoding again next time
+		let _ = self.store(path, options, settings, &loaded);
+		Ok(loaded)
+	}
+	
+	/// The cached track for a file's current contents, if there is one. Anything wrong with an entry just means decoding again.
+	pub fn lookup<P>(&self, path: P, options: &LoadOptions, settings: &ProjectSettings) -> Option<LoadedTrack> where P: AsRef<Path> {
+		let source = self.source_key(path.as_ref()).ok()?;
+		read_entry(&self.entry_path(&source, options, settings), &source, settings).ok().flatten()
+	}
+	
+	/// Stores a track loaded from a file with these options and settings
+	pub fn store<P>(&self, path: P, options: &LoadOptions, settings: &ProjectSettings, loaded: &LoadedTrack) -> Result<(), LoadError> where P: AsRef<Path> {
+		let source = self.source_key(path.as_ref())?;
+		write_entry(&self.entry_path(&source, options, settings), &source, settings, loaded)
+	}
+	
+	/// Deletes every entry and stamp, including ones for files that changed since
+	pub fn clear(&self) -> Result<(), LoadError> {
+		for entry in std::fs::read_dir(&self.dir)? {
+			let path = entry?.path();
+			if path.extension().is_some_and(|extension| extension == "sfxc" || extension == "sfxs" || extension == "tmp") { std::fs::remove_file(path)?; }
+		}
+		Ok(())
+	}
+	
+	/// Size and content hash of a source. The hash comes from the path's stamp while the size and modification time are the stamped ones,
+	/// otherwise the file is hashed and the stamp updated.
+	fn source_key(&self, path: &Path) -> Result<SourceKey, LoadError> {
+		let path = std::fs::canonicalize(path)?;
+		let mut hasher = ContentHasher::new();
+		hasher.write(path.to_string_lossy().as_bytes());
+		let stamp_path = self.dir.join(format!("{:032x}.sfxs", hasher.finish()));
+		
+		// Stamped before hashing, so a source changed in between only costs a hash the next time
+		let (size, modified) = source_stamp(&path)?;
+		if let (Some((seconds, nanos)), Ok(stamp)) = (modified, std::fs::read(&stamp_path)) {
+			if stamp.len() == STAMP_SIZE
+				&& &stamp[0..4] == STAMP_MAGIC
+				&& u32_at(&stamp, 4) == CACHE_VERSION
+				&& u64_at(&stamp, 8) == size
+				&& u64_at(&stamp, 16) == seconds
+				&& u32_at(&stamp, 24) == nanos
+			{ return Ok(SourceKey { size, content_hash: u128_at(&stamp, 28) }) }
+		}
+		
+		let content_hash = hash_file(&path)?;
+		if let Some((seconds, nanos)) = modified {
+			let mut stamp = [0; STAMP_SIZE];
+			stamp[0..4].copy_from_slice(STAMP_MAGIC);
+			stamp[4..8].copy_from_slice(&CACHE_VERSION.to_le_bytes());
+			stamp[8..16].copy_from_slice(&size.to_le_bytes());
+			stamp[16..24].copy_from_slice(&seconds.to_le_bytes());
+			stamp[24..28].copy_from_slice(&nanos.to_le_bytes());
+			stamp[28..44].copy_from_slice(&content_hash.to_le_bytes());
+			// Saves hashing it again next time, it doesn't matter if this fails
+			let _ = write_replacing(&stamp_path, |writer| writer.write_all(&stamp));
+		}
+		Ok(SourceKey { size, content_hash })
+	}
+	
+	fn entry_path(&self, source: &SourceKey, options: &LoadOptions, settings: &ProjectSettings) -> PathBuf {
+		let key = format!("{} {:032x} {settings:?} {:?} {:?} {:?} {:?}", source.size, source.content_hash, options.channel_policy, options.decode_errors, options.region, options.hint.raw_pcm);
+		let mut hasher = ContentHasher::new();
+		hasher.write(key.as_bytes());
+		self.dir.join(format!("{:032x}.sfxc", hasher.finish()))
+	}
+}
+
+
+/// What identifies a source's contents
+struct SourceKey {
+	size: u64,
+	content_hash: u128,
+}
+
+/// Size and modification time of a source, seconds and nanoseconds since the epoch. The time is `None` where the file system doesn't keep one.
+fn source_stamp(path: &Path) -> Result<(u64, Option<(u64, u32)>), LoadError> {
+	let file_metadata = std::fs::metadata(path)?;
+	let modified = file_metadata.modified().ok()
+		.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+		.map(|time| (time.as_secs(), time.subsec_nanos()));
+	Ok((file_metadata.len(), modified))
+}
+
+fn read_entry(entry_path: &Path, source: &SourceKey, settings: &ProjectSettings) -> Result<Option<LoadedTrack>, LoadError> {
+	let Ok(mut file) = File::open(entry_path) else { return Ok(None) };
+	
+	let mut header = [0; CACHE_HEADER_SIZE];
+	file.read_exact(&mut header)?;
+	if &header[0..4] != CACHE_MAGIC
+		|| u32_at(&header, 4) != CACHE_VERSION
+		|| u64_at(&header, 8) != source.size
+		|| u128_at(&header, 16) != source.content_hash
+		|| u32_at(&header, 32) != settings.rate()
+		|| u32_at(&header, 36) != 2
+	{ return Ok(None) }
+	
+	// A damaged header mustn't make us allocate more than the file holds
+	let frames = u64_at(&header, 40);
+	let available = file.metadata()?.len().saturating_sub(CACHE_HEADER_SIZE as u64);
+	let sample_bytes = frames.checked_mul(2 * 4).filter(|&bytes| bytes <= available);
+	let Some(sample_bytes) = sample_bytes else { return Ok(None) };
+	let frames = frames as usize;
+	
+	// Both channels read straight into one buffer, which the two channels are views of
+	let mut samples = vec![0f32; sample_bytes as usize / 4];
+	file.read_exact(bytemuck::cast_slice_mut(&mut samples))?;
+	for sample in &mut samples { *sample = f32::from_bits(u32::from_le(sample.to_bits())); }
+	let samples = TrackChannel::from(samples);
+	let track = AudioTrack { data: [samples.slice(0..frames), samples.slice(frames..(2 * frames))], sample_rate: settings.rate() };
+	
+	let mut trailer = vec![];
+	file.read_to_end(&mut trailer)?;
+	let mut reader = EntryReader(&trailer);
+	let (Some(decode_issues), Some(metadata)) = (read_issues(&mut reader), read_metadata(&mut reader)) else { return Ok(None) };
+	
+	Ok(Some(LoadedTrack { track, decode_issues, metadata }))
+}
+
+fn write_entry(entry_path: &Path, source: &SourceKey, settings: &ProjectSettings, loaded: &LoadedTrack) -> Result<(), LoadError> {
+	write_replacing(entry_path, |writer| {
+		let mut header = [0; CACHE_HEADER_SIZE];
+		header[0..4].copy_from_slice(CACHE_MAGIC);
+		header[4..8].copy_from_slice(&CACHE_VERSION.to_le_bytes());
+		header[8..16].copy_from_slice(&source.size.to_le_bytes());
+		header[16..32].copy_from_slice(&source.content_hash.to_le_bytes());
+		header[32..36].copy_from_slice(&settings.rate().to_le_bytes());
+		header[36..40].copy_from_slice(&2u32.to_le_bytes());
+		header[40..48].copy_from_slice(&(loaded.track.length() as u64).to_le_bytes());
+		writer.write_all(&header)?;
+		
+		for channel in &loaded.track.data {
+			writer.write_all(&channel.iter().flat_map(|sample| sample.to_le_bytes()).collect::<Vec<_>>())?;
+		}
+		
+		let mut trailer = vec![];
+		write_issues(&mut trailer, &loaded.decode_issues);
+		write_metadata(&mut trailer, &loaded.metadata);
+		writer.write_all(&trailer)
+	})?;
+	Ok(())
+}
+
+/// Writes next to `path` and renames into place, so a crash never leaves a truncated file behind. Named so other writers of the same file can't interfere.
+fn write_replacing<F>(path: &Path, write: F) -> std::io::Result<()> where F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()> {
+	let temp_path = path.with_extension(format!("{}.{}.tmp", std::process::id(), NEXT_TEMP_FILE.fetch_add(1, Ordering::Relaxed)));
+	let result = (|| {
+		let mut writer = BufWriter::new(File::create(&temp_path)?);
+		write(&mut writer)?;
+		writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+		std::fs::rename(&temp_path, path)
+	})();
+	
+	if result.is_err() { let _ = std::fs::remove_file(&temp_path); }
+	result
+}
+
+
+fn write_issues(out: &mut Vec<u8>, issues: &[DecodeIssue]) {
+	put_u32(out, issues.len() as u32);
+	for issue in issues {
+		put_u64(out, issue.timestamp.unwrap_or(u64::MAX));
+		put_u64(out, issue.frame as u64);
+		put_u64(out, issue.filled_frames as u64);
+		put_str(out, &issue.message);
+	}
+}
+
+fn read_issues(reader: &mut EntryReader) -> Option<Vec<DecodeIssue>> {
+	(0..reader.u32()?).map(|_| {
+		let timestamp = reader.u64()?;
+		Some(DecodeIssue {
+			timestamp: (timestamp != u64::MAX).then_some(timestamp),
+			frame: reader.u64()? as usize,
+			filled_frames: reader.u64()? as usize,
+			message: reader.string()?,
+		})
+	}).collect()
+}
+
+/// Options are a presence byte followed by the value
+fn write_metadata(out: &mut Vec<u8>, metadata: &TrackMetadata) {
+	put_u32(out, metadata.tags.len() as u32);
+	for tag in &metadata.tags {
+		put_str(out, &tag.key);
+		put_str(out, &tag.std_key.map(|std_key| format!("{std_key:?}")).unwrap_or_default());
+		put_str(out, &tag.value);
+	}
+	
+	put_u32(out, metadata.markers.len() as u32);
+	for marker in &metadata.markers {
+		put_u32(out, marker.cue_id);
+		put_u64(out, marker.frame as u64);
+		out.push(marker.label.is_some() as u8);
+		if let Some(label) = &marker.label { put_str(out, label); }
+		out.push(marker.length.is_some() as u8);
+		if let Some(length) = marker.length { put_u64(out, length as u64); }
+	}
+	
+	put_u32(out, metadata.loops.len() as u32);
+	for loop_region in &metadata.loops {
+		out.push(loop_region.cue_id.is_some() as u8);
+		if let Some(cue_id) = loop_region.cue_id { put_u32(out, cue_id); }
+		put_u64(out, loop_region.start as u64);
+		put_u64(out, loop_region.end as u64);
+		out.push(match loop_region.kind {
+			LoopKind::Forward => 0,
+			LoopKind::PingPong => 1,
+			LoopKind::Backward => 2,
+		});
+		put_u32(out, loop_region.play_count);
+	}
+	
+	out.push(metadata.root_note.is_some() as u8);
+	if let Some(root_note) = metadata.root_note { out.push(root_note); }
+	
+	out.push(metadata.broadcast.is_some() as u8);
+	if let Some(broadcast) = &metadata.broadcast {
+		for text in [&broadcast.description, &broadcast.originator, &broadcast.originator_reference, &broadcast.origination_date, &broadcast.origination_time, &broadcast.coding_history] {
+			put_str(out, text);
+		}
+		put_u64(out, broadcast.time_reference);
+	}
+	
+	out.push(metadata.provenance.is_some() as u8);
+	if let Some(provenance) = &metadata.provenance { put_str(out, &provenance.to_text()); }
+}
+
+fn read_metadata(reader: &mut EntryReader) -> Option<TrackMetadata> {
+	let tags = (0..reader.u32()?).map(|_| {
+		let key = reader.string()?;
+		let std_key = reader.string()?;
+		Some(MetadataTag {
+			key,
+			std_key: STANDARD_TAG_KEYS.iter().find(|candidate| format!("{candidate:?}") == std_key).copied(),
+			value: reader.string()?,
+		})
+	}).collect::<Option<_>>()?;
+	
+	let markers = (0..reader.u32()?).map(|_| Some(Marker {
+		cue_id: reader.u32()?,
+		frame: reader.u64()? as usize,
+		label: if reader.flag()? {Some(reader.string()?)} else {None},
+		length: if reader.flag()? {Some(reader.u64()? as usize)} else {None},
+	})).collect::<Option<_>>()?;
+	
+	let loops = (0..reader.u32()?).map(|_| Some(LoopRegion {
+		cue_id: if reader.flag()? {Some(reader.u32()?)} else {None},
+		start: reader.u64()? as usize,
+		end: reader.u64()? as usize,
+		kind: match reader.u8()? {
+			1 => LoopKind::PingPong,
+			2 => LoopKind::Backward,
+			_ => LoopKind::Forward,
+		},
+		play_count: reader.u32()?,
+	})).collect::<Option<_>>()?;
+	
+	let root_note = if reader.flag()? {Some(reader.u8()?)} else {None};
+	
+	let broadcast = if reader.flag()? {
+		let [description, originator, originator_reference, origination_date, origination_time, coding_history] = [(); 6].map(|_| reader.string());
+		Some(BroadcastInfo {
+			description: description?,
+			originator: originator?,
+			originator_reference: originator_reference?,
+			origination_date: origination_date?,
+			origination_time: origination_time?,
+			coding_history: coding_history?,
+			time_reference: reader.u64()?,
+		})
+	} else { None };
+	
+	let provenance = if reader.flag()? {Some(Provenance::parse(&reader.string()?))} else {None};
+	
+	Some(TrackMetadata { tags, markers, loops, root_note, broadcast, provenance })
+}
+
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+	out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+	out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, text: &str) {
+	put_u32(out, text.len() as u32);
+	out.extend_from_slice(text.as_bytes());
+}
+
+/// Reads back what the `put_` functions wrote, `None` once it runs out
+struct EntryReader<'a>(&'a [u8]);
+
+impl EntryReader<'_> {
+	fn bytes(&mut self, n: usize) -> Option<&[u8]> {
+		if n > self.0.len() { return None }
+		let (bytes, rest) = self.0.split_at(n);
+		self.0 = rest;
+		Some(bytes)
+	}
+	
+	fn u8(&mut self) -> Option<u8> {
+		self.bytes(1).map(|bytes| bytes[0])
+	}
+	
+	fn flag(&mut self) -> Option<bool> {
+		self.u8().map(|byte| byte != 0)
+	}
+	
+	fn u32(&mut self) -> Option<u32> {
+		self.bytes(4).map(|bytes| u32_at(bytes, 0))
+	}
+	
+	fn u64(&mut self) -> Option<u64> {
+		self.bytes(8).map(|bytes| u64_at(bytes, 0))
+	}
+	
+	fn string(&mut self) -> Option<String> {
+		let n = self.u32()? as usize;
+		self.bytes(n).map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+	}
+}
+
+
+fn hash_file(path: &Path) -> Result<u128, LoadError> {
+	let mut file = File::open(path)?;
+	let mut hasher = ContentHasher::new();
+	let mut buffer = vec![0; 1024 * 1024];
+	
+	loop {
+		let n = file.read(&mut buffer)?;
+		if n == 0 { break }
+		hasher.write(&buffer[..n]);
+	}
+	
+	Ok(hasher.finish())
+}
+
+/// Two lanes of FNV-1a over 64 bit words. Not cryptographic, but stable across builds and platforms unlike `DefaultHasher`.
+struct ContentHasher {
+	lanes: [u64; 2],
+	length: u64,
+	/// Bytes left over from the last write that didn't fill a word
+	tail: Vec<u8>,
+}
+
+impl ContentHasher {
+	const PRIME: u64 = 0x0000_0100_0000_01b3;
+	
+	fn new() -> Self {
+		Self { lanes: [0xcbf2_9ce4_8422_2325, 0x84222325_cbf29ce4], length: 0, tail: Vec::with_capacity(8) }
+	}
+	
+	fn write(&mut self, mut bytes: &[u8]) {
+		self.length += bytes.len() as u64;
+		
+		if !self.tail.is_empty() {
+			let n = (8 - self.tail.len()).min(bytes.len());
+			self.tail.extend_from_slice(&bytes[..n]);
+			bytes = &bytes[n..];
+			if self.tail.len() < 8 { return }
+			let word = u64::from_le_bytes(self.tail[..].try_into().unwrap());
+			self.tail.clear();
+			self.mix(word);
+		}
+		
+		let mut words = bytes.chunks_exact(8);
+		for word in &mut words { self.mix(u64::from_le_bytes(word.try_into().unwrap())); }
+		self.tail.extend_from_slice(words.remainder());
+	}
+	
+	fn mix(&mut self, word: u64) {
+		self.lanes[0] = (self.lanes[0] ^ word).wrapping_mul(Self::PRIME);
+		self.lanes[1] = (self.lanes[1] ^ word.rotate_left(32)).wrapping_mul(Self::PRIME).rotate_left(29);
+	}
+	
+	fn finish(mut self) -> u128 {
+		let mut tail = [0; 8];
+		tail[..self.tail.len()].copy_from_slice(&self.tail);
+		self.mix(u64::from_le_bytes(tail));
+		self.mix(self.length);
+		(self.lanes[0] as u128) << 64 | self.lanes[1] as u128
+	}
+}
+
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(bytes[offset..(offset + 4)].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+	u64::from_le_bytes(bytes[offset..(offset + 8)].try_into().unwrap())
+}
+
+fn u128_at(bytes: &[u8], offset: usize) -> u128 {
+	u128::from_le_bytes(bytes[offset..(offset + 16)].try_into().unwrap())
+}
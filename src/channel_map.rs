@@ -0,0 +1,251 @@
+use crate::*;
+
+
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+
+/// How the channels of a source file are turned into the two channels of an `AudioTrack<2>`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ChannelPolicy {
+	/// Duplicate mono, pass stereo through and downmix anything wider
+	#[default]
+	Auto,
+	/// Copy the first channel to both outputs
+	DuplicateMono,
+	/// Fold all channels down to stereo with ITU-R BS.775 coefficients, dropping LFE
+	Downmix,
+	/// Use the given source channel indices as left and right
+	Select([usize; 2]),
+}
+
+
+/// Gain matrix from source channels to the left and right output channels
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMap {
+	pub gains: [Vec<f32>; 2],
+}
+
+impl ChannelMap {
+	pub fn new(channels: Channels, policy: ChannelPolicy) -> Result<Self, LoadError> {
+		let count = channels.count();
+		if count == 0 { return Err(LoadError::UnsupportedFormat("Source has no channels".into())) }
+		
+		let policy = match policy {
+			ChannelPolicy::Auto => match count {
+				1 => ChannelPolicy::DuplicateMono,
+				2 => ChannelPolicy::Select([0, 1]),
+				_ => ChannelPolicy::Downmix,
+			}
+			ChannelPolicy::Downmix if count == 1 => ChannelPolicy::DuplicateMono,
+			policy => policy,
+		};
+		
+		let mut gains = [vec![0.0; count], vec![0.0; count]];
+		
+		match policy {
+			ChannelPolicy::Auto => unreachable!(),
+			
+			ChannelPolicy::DuplicateMono => {
+				gains[0][0] = 1.0;
+				gains[1][0] = 1.0;
+			}
+			
+			ChannelPolicy::Select(selected) => {
+				for (output, &source) in selected.iter().enumerate() {
+					if source >= count { return Err(LoadError::InvalidChannel { channel: source, channels: count }) }
+					gains[output][source] = 1.0;
+				}
+			}
+			
+			ChannelPolicy::Downmix => {
+				// Source channels are stored in the order of their position bits
+				for (source, channel) in channels.iter().enumerate() {
+					let [left, right] = downmix_gains(channel);
+					gains[0][source] = left;
+					gains[1][source] = right;
+				}
+			}
+		}
+		
+		Ok(Self { gains })
+	}
+	
+	pub fn source_channels(&self) -> usize {
+		self.gains[0].len()
+	}
+}
+
+
+
+/// Gain of each channel when summing stereo to mono
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MonoLaw {
+	/// -6 dB, sounds that are the same in both channels keep their level and nothing can clip
+	#[default]
+	Average,
+	/// -3 dB, uncorrelated channels like reverb or ambience keep their loudness
+	EqualPower,
+	/// 0 dB, a plain sum
+	Sum,
+}
+
+impl MonoLaw {
+	pub fn gain(self) -> f32 {
+		match self {
+			MonoLaw::Average => 0.5,
+			MonoLaw::EqualPower => MINUS_3DB,
+			MonoLaw::Sum => 1.0,
+		}
+	}
+}
+
+
+/// Gains from the channels of one track to the channels of another, a row for each output channel and a column for each input channel
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMatrix {
+	pub gains: Vec<Vec<f32>>,
+}
+
+impl ChannelMatrix {
+	/// Converts between the speaker layouts `export_wav` writes for each channel count.
+	/// Channels the output has are passed through. Anything wider folds down with the same ITU-R BS.775 gains as loading,
+	/// sides into the rears if there are rears, and LFE is dropped. Mono upmixes to the centre, or to both fronts at full level without one.
+	/// Returns `None` for channel counts without a standard layout.
+	pub fn conversion(from: usize, to: usize, law: MonoLaw) -> Option<Self> {
+		let from_layout = Channels::from_bits(speaker_mask(from)).filter(|layout| !layout.is_empty())?;
+		let to_layout = Channels::from_bits(speaker_mask(to)).filter(|layout| !layout.is_empty())?;
+		
+		if to_layout == Channels::FRONT_CENTRE {
+			if from == 1 { return Some(Self { gains: vec![vec![1.0]] }) }
+			let stereo = Self::conversion(from, 2, law)?;
+			return Some(Self { gains: vec![stereo.gains[0].iter().zip(&stereo.gains[1]).map(|(left, right)| (left + right) * law.gain()).collect()] })
+		}
+		
+		let output = |position: Channels| to_layout.iter().position(|channel| channel == position);
+		let mut gains = vec![vec![0.0; from]; to];
+		for (input, position) in from_layout.iter().enumerate() {
+			let mut add = |position: Channels, gain: f32| if let Some(output) = output(position) { gains[output][input] += gain; };
+			match position {
+				_ if output(position).is_some() => add(position, 1.0),
+				Channels::FRONT_CENTRE if from == 1 => {
+					add(Channels::FRONT_LEFT, 1.0);
+					add(Channels::FRONT_RIGHT, 1.0);
+				}
+				Channels::SIDE_LEFT if output(Channels::REAR_LEFT).is_some() => add(Channels::REAR_LEFT, MINUS_3DB),
+				Channels::SIDE_RIGHT if output(Channels::REAR_RIGHT).is_some() => add(Channels::REAR_RIGHT, MINUS_3DB),
+				_ => {
+					let [left, right] = downmix_gains(position);
+					add(Channels::FRONT_LEFT, left);
+					add(Channels::FRONT_RIGHT, right);
+				}
+			}
+		}
+		Some(Self { gains })
+	}
+}
+
+
+impl<const N: usize> AudioTrack<N> {
+	/// Converts to another channel count with `ChannelMatrix::conversion`, summing to mono with the default law
+	pub fn convert_channels<const M: usize>(&self) -> Result<AudioTrack<M>, TrackError> {
+		self.apply_matrix(&Self::conversion(M, MonoLaw::default())?)
+	}
+	
+	/// Sums all channels to one, wider layouts fold down to stereo first
+	pub fn to_mono(&self, law: MonoLaw) -> Result<AudioTrack<1>, TrackError> {
+		self.apply_matrix(&Self::conversion(1, law)?)
+	}
+	
+	fn conversion(to: usize, law: MonoLaw) -> Result<ChannelMatrix, TrackError> {
+		// Blames whichever side has no layout
+		let channels = if speaker_mask(N) == 0 {N} else {to};
+		ChannelMatrix::conversion(N, to, law).ok_or(TrackError::UnsupportedLayout { channels })
+	}
+	
+	/// Mixes the channels through a matrix. Outputs that take one input at unity are views of it, the rest are new buffers.
+	pub fn apply_matrix<const M: usize>(&self, matrix: &ChannelMatrix) -> Result<AudioTrack<M>, TrackError> {
+		if matrix.gains.len() != M || matrix.gains.iter().any(|row| row.len() != N) {
+			return Err(TrackError::MatrixShape { expected: (M, N), found: (matrix.gains.len(), matrix.gains.first().map_or(0, Vec::len)) })
+		}
+		
+		Ok(AudioTrack {
+			data: core::array::from_fn(|output| {
+				let row = &matrix.gains[output];
+				let inputs = row.iter().enumerate().filter(|(_, &gain)| gain != 0.0).collect::<Vec<_>>();
+				match inputs[..] {
+					[] => TrackChannel::silence(self.length()),
+					[(input, 1.0)] => self.data[input].clone(),
+					_ => {
+						let mut mixed = vec![0.0; self.length()];
+						for (input, &gain) in inputs {
+							for (mixed, sample) in mixed.iter_mut().zip(self.data[input].iter()) { *mixed += gain * sample; }
+						}
+						TrackChannel::from(mixed)
+					}
+				}
+			}),
+			sample_rate: self.sample_rate,
+		})
+	}
+	
+	/// One channel as a mono track, without copying
+	pub fn channel(&self, channel: usize) -> Result<AudioTrack<1>, TrackError> {
+		self.check_channel(channel)?;
+		Ok(self.select_channels([channel]))
+	}
+	
+	/// Every channel as a mono track of its own, without copying
+	pub fn split_channels(&self) -> [AudioTrack<1>; N] {
+		core::array::from_fn(|c| self.select_channels([c]))
+	}
+	
+	/// Puts mono tracks together as the channels of one track, without copying. They have to match in rate and length.
+	pub fn join_channels(channels: [&AudioTrack<1>; N]) -> Result<Self, TrackError> {
+		let Some(first) = channels.first() else { return Ok(Self::new(0, 0)) };
+		for channel in &channels[1..] {
+			first.check_rate(channel)?;
+			if channel.length() != first.length() { return Err(TrackError::LengthMismatch { expected: first.length(), found: channel.length() }) }
+		}
+		Ok(Self { data: channels.map(|channel| channel.data[0].clone()), sample_rate: first.sample_rate })
+	}
+	
+	/// Output channel `i` is input channel `order[i]`, without copying
+	pub fn reorder_channels(&self, order: [usize; N]) -> Result<Self, TrackError> {
+		for channel in order { self.check_channel(channel)?; }
+		Ok(self.select_channels(order))
+	}
+	
+	pub fn swap_channels(&self, a: usize, b: usize) -> Result<Self, TrackError> {
+		let mut order = core::array::from_fn(|c| c);
+		self.check_channel(a)?;
+		self.check_channel(b)?;
+		order.swap(a, b);
+		Ok(self.select_channels(order))
+	}
+	
+	fn check_channel(&self, channel: usize) -> Result<(), TrackError> {
+		if channel >= N { return Err(TrackError::InvalidChannel { channel, channels: N }) }
+		Ok(())
+	}
+}
+
+
+
+fn downmix_gains(channel: Channels) -> [f32; 2] {
+	match channel {
+		Channels::FRONT_LEFT => [1.0, 0.0],
+		Channels::FRONT_RIGHT => [0.0, 1.0],
+		Channels::FRONT_CENTRE => [MINUS_3DB, MINUS_3DB],
+		Channels::LFE1 | Channels::LFE2 => [0.0, 0.0],
+		
+		Channels::FRONT_LEFT_CENTRE | Channels::FRONT_LEFT_WIDE | Channels::FRONT_LEFT_HIGH => [MINUS_3DB, 0.0],
+		Channels::FRONT_RIGHT_CENTRE | Channels::FRONT_RIGHT_WIDE | Channels::FRONT_RIGHT_HIGH => [0.0, MINUS_3DB],
+		Channels::REAR_LEFT | Channels::SIDE_LEFT | Channels::REAR_LEFT_CENTRE => [MINUS_3DB, 0.0],
+		Channels::REAR_RIGHT | Channels::SIDE_RIGHT | Channels::REAR_RIGHT_CENTRE => [0.0, MINUS_3DB],
+		Channels::TOP_FRONT_LEFT | Channels::TOP_REAR_LEFT => [MINUS_3DB, 0.0],
+		Channels::TOP_FRONT_RIGHT | Channels::TOP_REAR_RIGHT => [0.0, MINUS_3DB],
+		
+		// Centred channels other than the front centre
+		_ => [0.5, 0.5],
+	}
+}
@@ -0,0 +1,246 @@
+use std::{path::PathBuf, process::ExitCode};
+
+use crate::*;
+
+
+/// Help text of the render and batch subcommands
+pub const USAGE: &str = "\
+Usage:
+  sfx_daw render <sound> [options] [-o <file>] [--seed <n>]
+  sfx_daw batch <sound> [options] [--count <n>] [--seed <n>] [--sweep <param>=<v1>,<v2>,...] [--template <template>] [--out-dir <dir>]
+  sfx_daw help
+
+Renders a sound definition offline, without a window or audio device.
+
+Options:
+  --param <name>=<value>   Sets a parameter, can be repeated
+  --format <format>        wav (24 bit), wav16, wav32f, flac, flac16, ogg or opus. Defaults to the output's extension, else wav
+  --rate <hz>              Project rate, 44100, 48000 (default), 88200, 96000 or 192000
+  --draft                  Fast resampling of sources instead of the high quality sinc
+  --normalize <target>     ebu (-23 LUFS), atsc (-24 LUFS), streaming (-16 LUFS), a loudness in LUFS, or a true peak like -1dBTP
+  --fail-on-clip           Fail instead of exporting clipped samples
+
+Exit status:
+  0 success, 2 bad arguments, 3 invalid sound definition, 4 a source failed to load, 5 export failed, 6 clipping with --fail-on-clip
+";
+
+
+/// Exit status of the renderer, so build scripts can tell failures apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CliStatus {
+	Success = 0,
+	Usage = 2,
+	Definition = 3,
+	Load = 4,
+	Export = 5,
+	Clipped = 6,
+}
+
+impl From<&RenderError> for CliStatus {
+	fn from(e: &RenderError) -> Self {
+		match e {
+			RenderError::Definition { .. } => CliStatus::Definition,
+			RenderError::UnknownParameter(_) | RenderError::Template(_) => CliStatus::Usage,
+			RenderError::Load(_) => CliStatus::Load,
+			RenderError::Export(ExportError::Clipped { .. }) => CliStatus::Clipped,
+			RenderError::Io(_) | RenderError::Export(_) => CliStatus::Export,
+		}
+	}
+}
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct CliArgs {
+	sound: PathBuf,
+	output: Option<PathBuf>,
+	out_dir: Option<PathBuf>,
+	seed: u64,
+	count: usize,
+	sweep: Option<(String, Vec<f64>)>,
+	template: Option<String>,
+	parameters: Vec<(String, f64)>,
+	format: Option<String>,
+	export: ExportOptions,
+	settings: ProjectSettings,
+	loudness: Option<LoudnessTarget>,
+	fail_on_clip: bool,
+}
+
+
+
+/// Runs a command line subcommand, or returns `None` when the arguments don't start with one and the GUI should open
+pub fn run_cli(args: &[String]) -> Option<ExitCode> {
+	let command = args.get(1)?.as_str();
+	if !matches!(command, "render" | "batch" | "help" | "--help" | "-h") { return None }
+	
+	let status = match command {
+		"render" | "batch" => match parse_args(&args[2..]) {
+			Ok(cli_args) => run(command, &cli_args),
+			Err(message) => {
+				eprintln!("sfx_daw: {message}\n\n{USAGE}");
+				CliStatus::Usage
+			}
+		},
+		_ => {
+			print!("{USAGE}");
+			CliStatus::Success
+		}
+	};
+	Some(ExitCode::from(status as u8))
+}
+
+
+fn run(command: &str, args: &CliArgs) -> CliStatus {
+	let definition = match SoundDefinition::from_file(&args.sound) {
+		Ok(definition) => definition,
+		Err(e) => {
+			eprintln!("sfx_daw: {}: {e}", args.sound.display());
+			return CliStatus::Definition
+		}
+	};
+	
+	let result = match command {
+		"render" => render(&definition, args),
+		_ => batch(&definition, args),
+	};
+	match result {
+		Ok(()) => CliStatus::Success,
+		Err(e) => {
+			eprintln!("sfx_daw: {e}");
+			CliStatus::from(&e)
+		}
+	}
+}
+
+fn render(definition: &SoundDefinition, args: &CliArgs) -> Result<(), RenderError> {
+	let variation = RenderVariation { seed: args.seed, parameters: args.parameters.clone() };
+	let parameters = definition.parameter_values(&variation)?;
+	
+	let mut export = args.export.clone();
+	let path = args.output.clone().unwrap_or_else(|| PathBuf::from(format!("{}.{}", definition.name, export.extension())));
+	export.metadata_mut().provenance = Some(definition.provenance(args.seed, &parameters));
+	
+	let track = SoundRenderer::new(definition, &args.settings)?.render(&variation)?;
+	let report = match args.loudness {
+		Some(target) => export_normalized(&track, &path, &args.settings, &export, target)?,
+		None => export_track(&track, &path, &args.settings, &export)?,
+	};
+	println!("{}", summary(&path.to_string_lossy(), track.length(), &args.settings, &report));
+	Ok(())
+}
+
+fn batch(definition: &SoundDefinition, args: &CliArgs) -> Result<(), RenderError> {
+	let mut options = BatchOptions {
+		variations: match &args.sweep {
+			Some((parameter, values)) => BatchVariations::Sweep { parameter: parameter.clone(), values: values.clone(), seed: args.seed },
+			None => BatchVariations::Seeds { count: args.count, first_seed: args.seed },
+		},
+		parameters: args.parameters.clone(),
+		export: args.export.clone(),
+		loudness: args.loudness,
+		..Default::default()
+	};
+	if let Some(template) = &args.template { options.template = template.clone(); }
+	if let Some(out_dir) = &args.out_dir { options.output_dir = out_dir.clone(); }
+	
+	let manifest = render_batch(definition, &args.settings, &options)?;
+	for render in &manifest.renders {
+		let path = options.output_dir.join(&render.file);
+		println!("{}", summary(&path.to_string_lossy(), render.frames, &args.settings, &render.report));
+	}
+	Ok(())
+}
+
+
+/// One line per written file: seconds, peak or loudness, and clipping
+fn summary(file: &str, frames: usize, settings: &ProjectSettings, report: &ExportReport) -> String {
+	let mut summary = format!("{file}: {:.3}s, peak {:.1} dBFS", frames as f64 / settings.rate() as f64, 20.0 * (report.peak as f64).log10());
+	if let Some(loudness) = &report.loudness {
+		summary += &format!(", {:.1} LUFS, {:.1} dBTP after {:+.1} dB", loudness.normalized.integrated, loudness.normalized.true_peak, loudness.gain_db);
+		if loudness.peak_limited { summary += " (held back by the true peak limit)"; }
+	}
+	if report.clipped_samples > 0 { summary += &format!(", {} clipped samples", report.clipped_samples); }
+	summary
+}
+
+/// Export options from `--format`, or from the extension of the output or template
+fn export_options(args: &CliArgs) -> Result<ExportOptions, String> {
+	let output = args.output.clone().or_else(|| args.template.as_ref().map(PathBuf::from));
+	let extension = output.as_ref().and_then(|output| output.extension()).map(|extension| extension.to_string_lossy().to_lowercase());
+	let format = args.format.as_deref().or(extension.as_deref()).unwrap_or("wav");
+	let clip_detection = if args.fail_on_clip {ClipDetection::Fail} else {ClipDetection::Report};
+	
+	let wav = |format| ExportOptions::Wav(WavExportOptions { format, clip_detection, ..Default::default() });
+	let flac = |format| ExportOptions::Flac(FlacExportOptions { format, clip_detection, ..Default::default() });
+	Ok(match format {
+		"wav" | "wav24" => wav(ExportFormat::Pcm24),
+		"wav16" => wav(ExportFormat::Pcm16),
+		"wav32f" => wav(ExportFormat::Float32),
+		"flac" | "flac24" => flac(ExportFormat::Pcm24),
+		"flac16" => flac(ExportFormat::Pcm16),
+		#[cfg(feature = "codecs")]
+		"ogg" => ExportOptions::Vorbis(VorbisExportOptions { clip_detection, ..Default::default() }),
+		#[cfg(feature = "codecs")]
+		"opus" => ExportOptions::Opus(OpusExportOptions { clip_detection, ..Default::default() }),
+		#[cfg(not(feature = "codecs"))]
+		"ogg" | "opus" => return Err(format!("{format} export needs the codecs feature")),
+		_ => return Err(format!("unknown format {format}")),
+	})
+}
+
+
+fn parse_args(args: &[String]) -> Result<CliArgs, String> {
+	let mut cli_args = CliArgs { count: 1, settings: ProjectSettings { resampler: ResamplerQuality::HighQuality, ..Default::default() }, ..Default::default() };
+	let mut sound = None;
+	let mut args = args.iter();
+	
+	while let Some(arg) = args.next() {
+		let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+		match arg.as_str() {
+			"-o" | "--output" => cli_args.output = Some(value()?.into()),
+			"--out-dir" => cli_args.out_dir = Some(value()?.into()),
+			"--seed" => cli_args.seed = parse_number(value()?)?,
+			"--count" => cli_args.count = parse_number(value()?)?,
+			"--template" => cli_args.template = Some(value()?.clone()),
+			"--format" => cli_args.format = Some(value()?.to_lowercase()),
+			"--param" => {
+				let (name, number) = value()?.split_once('=').ok_or("--param takes <name>=<value>")?;
+				cli_args.parameters.push((name.into(), parse_number(number)?));
+			}
+			"--sweep" => {
+				let (name, numbers) = value()?.split_once('=').ok_or("--sweep takes <param>=<v1>,<v2>,...")?;
+				cli_args.sweep = Some((name.into(), numbers.split(',').map(parse_number).collect::<Result<_, _>>()?));
+			}
+			"--rate" => {
+				let hz = value()?;
+				cli_args.settings.sample_rate = parse_number(hz).ok().and_then(ProjectRate::from_hz).ok_or_else(|| format!("unsupported rate {hz}"))?;
+			}
+			"--draft" => cli_args.settings.resampler = ResamplerQuality::Draft,
+			"--normalize" => cli_args.loudness = Some(parse_loudness(value()?)?),
+			"--fail-on-clip" => cli_args.fail_on_clip = true,
+			_ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option {arg}")),
+			_ if sound.is_none() => sound = Some(PathBuf::from(arg)),
+			_ => return Err(format!("unexpected argument {arg}")),
+		}
+	}
+	
+	cli_args.sound = sound.ok_or("no sound definition given")?;
+	cli_args.export = export_options(&cli_args)?;
+	Ok(cli_args)
+}
+
+fn parse_loudness(text: &str) -> Result<LoudnessTarget, String> {
+	Ok(match text.to_lowercase().as_str() {
+		"ebu" | "r128" => LoudnessTarget::EBU_R128,
+		"atsc" | "a85" => LoudnessTarget::ATSC_A85,
+		"streaming" => LoudnessTarget::STREAMING,
+		text => match text.strip_suffix("dbtp") {
+			Some(peak) => LoudnessTarget::TruePeak(parse_number(peak)?),
+			None => LoudnessTarget::Integrated { lufs: parse_number(text.trim_end_matches("lufs"))?, max_true_peak: Some(-1.0) },
+		},
+	})
+}
+
+fn parse_number<T>(text: &str) -> Result<T, String> where T: std::str::FromStr {
+	text.trim().parse().map_err(|_| format!("`{text}` isn't a valid number"))
+}
@@ -0,0 +1,234 @@
+use std::{collections::HashSet, path::Path};
+
+use symphonia::core::meta::StandardTagKey;
+
+use crate::*;
+
+
+/// CD frames per second, the unit of `INDEX` times
+const CUE_FRAMES_PER_SECOND: f64 = 75.0;
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueSheet {
+	pub title: Option<String>,
+	pub performer: Option<String>,
+	pub tracks: Vec<CueTrack>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueTrack {
+	pub number: u32,
+	pub title: Option<String>,
+	pub performer: Option<String>,
+	/// File named by the last `FILE` line before the track's `INDEX 01`, relative to the sheet. That can come after the `TRACK` line,
+	/// in sheets that keep each pregap at the end of the previous file.
+	pub file: Option<String>,
+	/// Position of `INDEX 01` in its file, in seconds. Audio in the pregap before it belongs to the previous track if that's in the same file.
+	/// Audio before the first `INDEX 01` of a file, a hidden track or a pregap stored at the start of the file, isn't part of any track.
+	pub start: f64,
+}
+
+impl CueSheet {
+	pub fn read<P>(path: P) -> Result<Self, LoadError> where P: AsRef<Path> {
+		let bytes = std::fs::read(path)?;
+		// Sheets from older rippers are often Latin-1 rather than UTF-8
+		let text = match String::from_utf8(bytes) {
+			Ok(text) => text,
+			Err(e) => e.into_bytes().iter().map(|&byte| byte as char).collect(),
+		};
+		Self::parse(&text)
+	}
+	
+	pub fn parse(text: &str) -> Result<Self, LoadError> {
+		let mut sheet = CueSheet::default();
+		let mut file = None;
+		
+		for (line_number, line) in text.trim_start_matches('\u{feff}').lines().enumerate() {
+			let error = |message: &str| LoadError::CueSheet(format!("Line {}: {message}", line_number + 1));
+			let fields = split_fields(line);
+			let Some(command) = fields.first() else { continue };
+			
+			match (command.to_ascii_uppercase().as_str(), sheet.tracks.last_mut()) {
+				("FILE", _) => file = Some(fields.get(1).ok_or(error("FILE without a name"))?.clone()),
+				
+				("TRACK", _) => {
+					let number = fields.get(1).and_then(|number| number.parse().ok()).ok_or(error("Invalid track number"))?;
+					sheet.tracks.push(CueTrack { number, file: file.clone(), start: f64::NAN, ..Default::default() });
+				}
+				
+				("TITLE", None) => sheet.title = fields.get(1).cloned(),
+				("TITLE", Some(track)) => track.title = fields.get(1).cloned(),
+				("PERFORMER", None) => sheet.performer = fields.get(1).cloned(),
+				("PERFORMER", Some(track)) => track.performer = fields.get(1).cloned(),
+				
+				("INDEX", Some(track)) => {
+					if fields.get(1).and_then(|index| index.parse::<u32>().ok()) != Some(1) { continue }
+					track.start = fields.get(2).and_then(|time| parse_time(time)).ok_or(error("Invalid INDEX time"))?;
+					track.file = file.clone();
+				}
+				
+				// REM comments, flags, ISRC codes, pregaps and postgaps
+				_ => (),
+			}
+		}
+		
+		if let Some(track) = sheet.tracks.iter().find(|track| track.start.is_nan()) {
+			return Err(LoadError::CueSheet(format!("Track {} has no INDEX 01", track.number)))
+		}
+		if sheet.tracks.is_empty() { return Err(LoadError::CueSheet("No tracks".into())) }
+		
+		Ok(sheet)
+	}
+	
+	/// Chapters from Vorbis comments, `CHAPTERxxx=hh:mm:ss.sss` with the title in `CHAPTERxxxNAME`, as tracks numbered from 1 in order of their start
+	pub fn from_chapters(tags: &[MetadataTag]) -> Option<Self> {
+		let mut tracks = tags.iter().filter_map(|tag| {
+			let number = tag.key.get(..7).filter(|prefix| prefix.eq_ignore_ascii_case("CHAPTER")).map(|_| &tag.key[7..])?;
+			if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) { return None }
+			let title = tags.iter().find(|name| name.key.eq_ignore_ascii_case(&format!("CHAPTER{number}NAME"))).map(|name| name.value.clone());
+			Some(CueTrack { title, start: parse_chapter_time(&tag.value)?, ..Default::default() })
+		}).collect::<Vec<_>>();
+		
+		tracks.sort_by(|a, b| a.start.total_cmp(&b.start));
+		for (i, track) in tracks.iter_mut().enumerate() { track.number = i as u32 + 1; }
+		(!tracks.is_empty()).then_some(Self { tracks, ..Default::default() })
+	}
+	
+	/// Tracks of one file of a sheet that spans several
+	pub fn tracks_in_file(&self, file: Option<&str>) -> Self {
+		Self {
+			tracks: self.tracks.iter().filter(|track| track.file.as_deref() == file).cloned().collect(),
+			..self.clone()
+		}
+	}
+}
+
+
+/// Splits a line into fields, keeping quoted strings together
+fn split_fields(line: &str) -> Vec<String> {
+	let mut fields = vec![];
+	let mut chars = line.trim().chars().peekable();
+	
+	while let Some(&c) = chars.peek() {
+		if c.is_whitespace() {
+			chars.next();
+		} else if c == '"' {
+			chars.next();
+			fields.push(chars.by_ref().take_while(|&c| c != '"').collect());
+		} else {
+			fields.push(chars.by_ref().take_while(|c| !c.is_whitespace()).collect());
+		}
+	}
+	
+	fields
+}
+
+/// mm:ss:ff, where minutes can go past 59 and ff counts CD frames
+fn parse_time(time: &str) -> Option<f64> {
+	let mut parts = time.split(':').map(|part| part.parse::<u32>().ok());
+	let (minutes, seconds, frames) = (parts.next()??, parts.next()??, parts.next()??);
+	if parts.next().is_some() || seconds >= 60 || frames >= 75 { return None }
+	Some((minutes * 60 + seconds) as f64 + frames as f64 / CUE_FRAMES_PER_SECOND)
+}
+
+/// hh:mm:ss.sss, where the hours and minutes can be left out
+fn parse_chapter_time(time: &str) -> Option<f64> {
+	let mut parts = time.trim().rsplit(':');
+	let seconds = parts.next()?.parse::<f64>().ok().filter(|seconds| seconds.is_finite() && *seconds >= 0.0)?;
+	parts.enumerate().try_fold(seconds, |total, (i, part)| {
+		if i >= 2 { return None }
+		Some(total + part.parse::<u32>().ok()? as f64 * 60f64.powi(i as i32 + 1))
+	})
+}
+
+
+
+/// Loads every track of a `.cue` sheet, decoding each file it refers to once and splitting it at the track starts.
+/// Each track ends where the next one in its file starts, or at the end of the file. Audio before the first track start in a file is left out.
+/// Tracks come back in the order of the sheet.
+pub fn load_cue_sheet<P>(path: P, options: &LoadOptions, settings: &ProjectSettings) -> Result<Vec<LoadedTrack>, LoadError> where P: AsRef<Path> {
+	let path = path.as_ref();
+	let sheet = CueSheet::read(path)?;
+	
+	let mut seen = HashSet::new();
+	let files = sheet.tracks.iter().map(|track| track.file.clone()).filter(|file| seen.insert(file.clone())).collect::<Vec<_>>();
+	
+	let mut loaded_tracks = sheet.tracks.iter().map(|_| None).collect::<Vec<_>>();
+	for file in files {
+		let file_path = path.with_file_name(file.as_deref().ok_or(LoadError::CueSheet("Track before the first FILE".into()))?);
+		let loaded = load_audio_split(AudioSource::from(file_path), options, Some(&sheet.tracks_in_file(file.as_deref())), settings)?;
+		let positions = sheet.tracks.iter().enumerate().filter(|(_, track)| track.file == file).map(|(i, _)| i);
+		for (i, loaded) in positions.zip(loaded) { loaded_tracks[i] = Some(loaded); }
+	}
+	
+	Ok(loaded_tracks.into_iter().flatten().collect())
+}
+
+/// Loads one long recording as separate tracks, split at the starts of the tracks in `cue_sheet`.
+/// Without a sheet, the one embedded in the file is used (see `TrackDecoder::embedded_cue_sheet`), and files without one load as a single track.
+/// Each track gets the title, performer and number from the sheet as tags.
+pub fn load_audio_split(source: AudioSource, options: &LoadOptions, cue_sheet: Option<&CueSheet>, settings: &ProjectSettings) -> Result<Vec<LoadedTrack>, LoadError> {
+	if cue_sheet.is_some_and(|cue_sheet| cue_sheet.tracks.is_empty()) { return Err(LoadError::CueSheet("No tracks".into())) }
+	
+	let mut decoder = TrackDecoder::open(source, options)?;
+	
+	let embedded_cue_sheet;
+	let cue_sheet = match cue_sheet {
+		Some(cue_sheet) => cue_sheet,
+		None => {
+			embedded_cue_sheet = decoder.embedded_cue_sheet()?;
+			match &embedded_cue_sheet {
+				Some(cue_sheet) => cue_sheet,
+				None => &CueSheet { tracks: vec![CueTrack { number: 1, ..Default::default() }], ..Default::default() },
+			}
+		}
+	};
+	
+	if cue_sheet.tracks.iter().any(|track| track.start.is_nan() || track.start < 0.0) { return Err(LoadError::CueSheet("Track starts before the file".into())) }
+	
+	let rate = decoder.rate();
+	let mut samples = [vec![], vec![]];
+	for channel in &mut samples { channel.reserve(decoder.n_frames().unwrap_or(0) as usize); }
+	while decoder.decode_next(&mut samples)? {}
+	
+	let total_frames = samples[0].len();
+	let starts = cue_sheet.tracks.iter().map(|track| (track.start * rate as f64).round() as usize).collect::<Vec<_>>();
+	if starts.windows(2).any(|pair| pair[0] >= pair[1]) || starts.last().is_some_and(|&start| start >= total_frames) {
+		return Err(LoadError::CueSheet("Track starts are out of order or past the end of the file".into()))
+	}
+	
+	// Audio before the first track is split off as a hidden track and thrown away
+	let hidden_track = starts[0] > 0;
+	let mut track_ends = starts[1..].to_vec();
+	track_ends.push(total_frames);
+	if hidden_track { track_ends.insert(0, starts[0]); }
+	
+	let resample_ratio = settings.resample_ratio(rate);
+	let mut resampled_tracks = resample_run(DecodedRun { rate, samples, track_ends }, settings)?;
+	if hidden_track { resampled_tracks.remove(0); }
+	
+	let metadata = decoder.metadata.rescaled(resample_ratio);
+	let decode_issues = decoder.issues.iter().map(|issue| issue.rescaled(resample_ratio)).collect::<Vec<_>>();
+	
+	Ok(resampled_tracks.into_iter().zip(&cue_sheet.tracks).zip(&starts).map(|((track, cue_track), &start)| {
+		// Same rounding as the split in resample_run
+		let start = (start as f64 * resample_ratio) as usize;
+		let frames = start..(start + track.length());
+		
+		let mut metadata = metadata.region(frames.clone());
+		if let Some(title) = &cue_track.title { metadata.set_tag(StandardTagKey::TrackTitle, "TITLE", title); }
+		if let Some(performer) = cue_track.performer.as_ref().or(cue_sheet.performer.as_ref()) { metadata.set_tag(StandardTagKey::Artist, "PERFORMER", performer); }
+		if let Some(album) = &cue_sheet.title { metadata.set_tag(StandardTagKey::Album, "ALBUM", album); }
+		metadata.set_tag(StandardTagKey::TrackNumber, "TRACKNUMBER", &cue_track.number.to_string());
+		
+		LoadedTrack {
+			decode_issues: decode_issues.iter()
+				.filter(|issue| frames.contains(&issue.frame))
+				.map(|issue| DecodeIssue { frame: issue.frame - frames.start, ..issue.clone() })
+				.collect(),
+			metadata,
+			track,
+		}
+	}).collect())
+}
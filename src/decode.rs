@@ -0,0 +1,321 @@
+use std::{io::ErrorKind, sync::OnceLock};
+
+use symphonia::core::codecs::{CodecRegistry, CODEC_TYPE_OPUS};
+
+use crate::*;
+
+
+/// Frames to decode before a seek target in Opus streams, the decoder state needs about 80 ms to converge after a reset (RFC 7845 section 4.6)
+pub const OPUS_SEEK_PREROLL: u64 = 3840;
+/// Consecutive container errors tolerated in lenient mode before the rest of the file is given up on
+const MAX_CONSECUTIVE_ERRORS: usize = 64;
+
+
+/// What to do with packets the decoder rejects
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecodeErrorPolicy {
+	/// Stop loading and return `LoadError::Decode`
+	#[default]
+	Fail,
+	/// Drop the packet, shortening the track
+	Skip,
+	/// Replace the packet with silence of the same duration
+	ZeroFill,
+}
+
+/// A packet that was skipped or zero-filled in lenient mode
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodeIssue {
+	/// Packet timestamp in the track's time base, `None` when the container itself was damaged
+	pub timestamp: Option<u64>,
+	/// Position of the issue in the decoded track
+	pub frame: usize,
+	/// Frames of silence inserted, 0 if the packet was skipped
+	pub filled_frames: usize,
+	pub message: String,
+}
+
+impl DecodeIssue {
+	/// Converts positions from source frames to frames at another rate
+	pub fn rescaled(&self, ratio: f64) -> Self {
+		Self {
+			frame: (self.frame as f64 * ratio) as usize,
+			filled_frames: (self.filled_frames as f64 * ratio) as usize,
+			..self.clone()
+		}
+	}
+}
+
+
+/// Decodes the default audio track of a source packet by packet into stereo f32 at the source rate
+pub struct TrackDecoder {
+	format: Box<dyn FormatReader>,
+	decoder: Box<dyn Decoder>,
+	track_id: u32,
+	time_base: Option<TimeBase>,
+	/// Frames the codec drops at the start that timestamps still count, the Opus pre-skip
+	start_offset: u64,
+	/// Frames to decode and throw away before a seek target, for codecs that need them to converge
+	seek_preroll: u64,
+	rate: u32,
+	n_frames: Option<u64>,
+	channel_policy: ChannelPolicy,
+	channel_map: Option<ChannelMap>,
+	decode_errors: DecodeErrorPolicy,
+	consecutive_errors: usize,
+	/// Source frame just after the last decoded frame
+	frame: usize,
+	/// Decoded frames to drop after a seek landed before the requested position
+	skip: usize,
+	pub issues: Vec<DecodeIssue>,
+	/// Positions in source frames
+	pub metadata: TrackMetadata,
+}
+
+impl TrackDecoder {
+	pub fn open(source: AudioSource, options: &LoadOptions) -> Result<Self, LoadError> {
+		let format = FormatOptions { enable_gapless: true, ..Default::default() };
+		let mut opened = open_source(source, &options.hint, &format)?;
+		let track = opened.format.default_track()
+			.filter(|track| track.codec_params.sample_rate.is_some())
+			.or_else(|| opened.format.tracks().iter().find(|track| track.codec_params.sample_rate.is_some()))
+			.ok_or(LoadError::MissingTrack)?
+			.clone();
+		
+		let decoder = codecs().make(&track.codec_params, &DecoderOptions::default())?;
+		let channel_map = track.codec_params.channels.map(|channels| ChannelMap::new(channels, options.channel_policy)).transpose()?;
+		
+		let mut metadata = opened.metadata;
+		metadata.add_all_tags(opened.format.metadata());
+		metadata.add_loops_from_tags();
+		metadata.add_markers_from_tags();
+		
+		Ok(Self {
+			format: opened.format,
+			decoder,
+			track_id: track.id,
+			time_base: track.codec_params.time_base,
+			start_offset: if track.codec_params.codec == CODEC_TYPE_OPUS {track.codec_params.delay.unwrap_or(0) as u64} else {0},
+			seek_preroll: if track.codec_params.codec == CODEC_TYPE_OPUS {OPUS_SEEK_PREROLL} else {0},
+			rate: track.codec_params.sample_rate.ok_or(LoadError::MissingSampleRate)?,
+			n_frames: track.codec_params.n_frames,
+			channel_policy: options.channel_policy,
+			channel_map,
+			decode_errors: options.decode_errors,
+			consecutive_errors: 0,
+			frame: 0,
+			skip: 0,
+			issues: vec![],
+			metadata,
+		})
+	}
+	
+	pub fn rate(&self) -> u32 {
+		self.rate
+	}
+	
+	/// Frame count reported by the container, not always present or accurate
+	pub fn n_frames(&self) -> Option<u64> {
+		self.n_frames
+	}
+	
+	/// Source frame just after the last decoded frame
+	pub fn frame(&self) -> usize {
+		self.frame
+	}
+	
+	/// Cue sheet stored in the file itself, from a `CUESHEET` tag, a FLAC cuesheet block or else `CHAPTERxxx` Vorbis comments.
+	/// FLAC cuesheets don't say which index a point is, so tracks start at their first index, pregap included.
+	/// MP4 and ID3 chapters aren't read, symphonia doesn't hand them over.
+	pub fn embedded_cue_sheet(&self) -> Result<Option<CueSheet>, LoadError> {
+		if let Some(tag) = self.metadata.tags.iter().find(|tag| tag.key.eq_ignore_ascii_case("CUESHEET")) {
+			return CueSheet::parse(&tag.value).map(Some)
+		}
+		
+		let tracks = self.format.cues().iter()
+			// 170 and 255 are the lead-out of CD and non-CD cuesheets
+			.filter(|cue| cue.index != 170 && cue.index != 255)
+			.map(|cue| CueTrack {
+				number: cue.index,
+				start: self.timestamp_to_seconds(cue.start_ts + cue.points.first().map_or(0, |point| point.start_offset_ts)),
+				..Default::default()
+			})
+			.collect::<Vec<_>>();
+		
+		if !tracks.is_empty() { return Ok(Some(CueSheet { tracks, ..Default::default() })) }
+		Ok(CueSheet::from_chapters(&self.metadata.tags))
+	}
+	
+	/// Decodes the next packet of the track and appends its frames to `dest`. Returns false once the track has ended.
+	pub fn decode_next(&mut self, dest: &mut [Vec<f32>; 2]) -> Result<bool, LoadError> {
+		loop {
+			let packet = match self.format.next_packet() {
+				Ok(packet) => packet,
+				Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+					// Chained Ogg streams and some MP4 files only hand over their tags along the way
+					self.metadata.add_all_tags(self.format.metadata());
+					self.metadata.add_loops_from_tags();
+					self.metadata.add_markers_from_tags();
+					return Ok(false)
+				}
+				Err(SymphoniaError::DecodeError(message)) if self.decode_errors != DecodeErrorPolicy::Fail => {
+					// Damaged container data, the reader resyncs on the next call
+					self.record_issue(None, 0, message);
+					self.consecutive_errors += 1;
+					if self.consecutive_errors > MAX_CONSECUTIVE_ERRORS { return Ok(false) }
+					continue
+				}
+				Err(SymphoniaError::DecodeError(message)) => return Err(self.decode_error(None, message)),
+				Err(SymphoniaError::ResetRequired) => return Err(self.decode_error(None, "Reader reset required")),
+				Err(e) => return Err(e.into()),
+			};
+			
+			if packet.track_id() != self.track_id { continue }
+			
+			let start = dest[0].len();
+			
+			match self.decoder.decode(&packet) {
+				Ok(audio_buf) => {
+					self.consecutive_errors = 0;
+					let channel_map = update_channel_map(&mut self.channel_map, &audio_buf, self.channel_policy)?;
+					
+					for channel in dest.iter_mut() { channel.resize(start + audio_buf.frames(), 0.0); }
+					let [left, right] = dest;
+					copy_audio_buffer(&audio_buf, channel_map, [&mut left[start..], &mut right[start..]]);
+				}
+				
+				Err(SymphoniaError::DecodeError(message)) => match self.decode_errors {
+					DecodeErrorPolicy::Fail => return Err(self.decode_error(Some(packet.ts), message)),
+					DecodeErrorPolicy::Skip => {
+						self.record_issue(Some(packet.ts), 0, message);
+						continue
+					}
+					DecodeErrorPolicy::ZeroFill => {
+						let frames = self.timestamp_to_frames(packet.dur);
+						self.record_issue(Some(packet.ts), frames, message);
+						for channel in dest.iter_mut() { channel.resize(start + frames, 0.0); }
+					}
+				}
+				
+				Err(SymphoniaError::ResetRequired) => return Err(self.decode_error(Some(packet.ts), "Decoder reset required")),
+				Err(e) => return Err(e.into()),
+			}
+			
+			// Drop whatever is still before a seek target
+			let skip = self.skip.min(dest[0].len() - start);
+			for channel in dest.iter_mut() { channel.drain(start..(start + skip)); }
+			self.skip -= skip;
+			self.frame += dest[0].len() - start;
+			
+			return Ok(true)
+		}
+	}
+	
+	/// Seeks to a source frame. Decoding resumes exactly at that frame even if the container can only seek coarsely.
+	pub fn seek(&mut self, source_frame: u64) -> Result<(), LoadError> {
+		let target = source_frame + self.start_offset;
+		let position = target.saturating_sub(self.seek_preroll);
+		let preroll = (target - position) as usize;
+		let time = Time::new(position / self.rate as u64, (position % self.rate as u64) as f64 / self.rate as f64);
+		
+		let seeked_to = self.format.seek(SeekMode::Accurate, SeekTo::Time { time, track_id: Some(self.track_id) })?;
+		
+		self.decoder.reset();
+		self.skip = self.timestamp_to_frames(seeked_to.required_ts.saturating_sub(seeked_to.actual_ts)) + preroll;
+		self.frame = (self.timestamp_to_frames(seeked_to.required_ts) + preroll).saturating_sub(self.start_offset as usize);
+		
+		Ok(())
+	}
+	
+	
+	
+	fn record_issue(&mut self, timestamp: Option<u64>, filled_frames: usize, message: &str) {
+		self.issues.push(DecodeIssue {
+			timestamp,
+			frame: self.frame,
+			filled_frames,
+			message: message.into(),
+		});
+	}
+	
+	/// Error for the packet at `timestamp`, or for damaged container data at the current position without one
+	fn decode_error(&self, timestamp: Option<u64>, message: &str) -> LoadError {
+		LoadError::Decode {
+			timestamp,
+			seconds: timestamp.map_or(self.frame as f64 / self.rate as f64, |ts| self.timestamp_to_seconds(ts)),
+			message: message.into(),
+		}
+	}
+	
+	fn timestamp_to_seconds(&self, ts: u64) -> f64 {
+		match self.time_base {
+			Some(time_base) => {
+				let time = time_base.calc_time(ts);
+				time.seconds as f64 + time.frac
+			}
+			None => ts as f64 / self.rate as f64,
+		}
+	}
+	
+	fn timestamp_to_frames(&self, ts: u64) -> usize {
+		(self.timestamp_to_seconds(ts) * self.rate as f64).round() as usize
+	}
+}
+
+
+
+/// symphonia's codecs plus the Opus decoder it lacks, with the `codecs` feature
+pub fn codecs() -> &'static CodecRegistry {
+	static CODECS: OnceLock<CodecRegistry> = OnceLock::new();
+	CODECS.get_or_init(|| {
+		let mut registry = CodecRegistry::new();
+		symphonia::default::register_enabled_codecs(&mut registry);
+		#[cfg(feature = "codecs")]
+		registry.register_all::<OpusDecoder>();
+		registry
+	})
+}
+
+/// Returns the channel map for a decoded buffer, (re)building it when the codec didn't report a layout up front or the buffer doesn't match it
+pub fn update_channel_map<'a>(channel_map: &'a mut Option<ChannelMap>, audio_buf: &AudioBufferRef, policy: ChannelPolicy) -> Result<&'a ChannelMap, LoadError> {
+	let channels = audio_buf.spec().channels;
+	match channel_map {
+		Some(map) if map.source_channels() == channels.count() => (),
+		_ => *channel_map = Some(ChannelMap::new(channels, policy)?),
+	}
+	Ok(channel_map.as_ref().unwrap())
+}
+
+/// Converts a decoded buffer to f32, maps its channels to stereo and writes the result to the start of `dest`. Returns the number of frames written.
+pub fn copy_audio_buffer(audio_buf: &AudioBufferRef, channel_map: &ChannelMap, dest: [&mut [f32]; 2]) -> usize {
+	match audio_buf {
+		AudioBufferRef::F32(buf) => map_channels(buf, channel_map, dest),
+		AudioBufferRef::F64(buf) => map_channels(buf, channel_map, dest),
+		AudioBufferRef::S8 (buf) => map_channels(buf, channel_map, dest),
+		AudioBufferRef::S16(buf) => map_channels(buf, channel_map, dest),
+		AudioBufferRef::S24(buf) => map_channels(buf, channel_map, dest),
+		AudioBufferRef::S32(buf) => map_channels(buf, channel_map, dest),
+		AudioBufferRef::U8 (buf) => map_channels(buf, channel_map, dest),
+		AudioBufferRef::U16(buf) => map_channels(buf, channel_map, dest),
+		AudioBufferRef::U24(buf) => map_channels(buf, channel_map, dest),
+		AudioBufferRef::U32(buf) => map_channels(buf, channel_map, dest),
+	}
+}
+
+fn map_channels<S>(buf: &AudioBuffer<S>, channel_map: &ChannelMap, dest: [&mut [f32]; 2]) -> usize where S: Sample + IntoSample<f32> {
+	let frames = buf.frames();
+	
+	for (gains, d) in channel_map.gains.iter().zip(dest) {
+		let d = &mut d[..frames];
+		d.fill(0.0);
+		
+		for (channel, &gain) in gains.iter().enumerate() {
+			if gain == 0.0 { continue }
+			
+			let samples = buf.chan(channel);
+			for i in 0..frames { d[i] += gain * IntoSample::<f32>::into_sample(samples[i]); }
+		}
+	}
+	
+	frames
+}
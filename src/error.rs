@@ -0,0 +1,249 @@
+use std::{fmt, ops::Range};
+
+use crate::*;
+
+
+/// Tracks that can't be combined, or an edit that doesn't fit the track
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrackError {
+	/// Frames at different rates were mixed or copied, one of the tracks has to be resampled first
+	RateMismatch { expected: u32, found: u32 },
+	/// Frame range that doesn't fit in a track of `length` frames
+	OutOfRange { range: Range<usize>, length: usize },
+	/// Channels joined into one track, or ranges copied onto each other, have different lengths
+	LengthMismatch { expected: usize, found: usize },
+	InvalidChannel { channel: usize, channels: usize },
+	/// No standard speaker layout for this many channels
+	UnsupportedLayout { channels: usize },
+	/// A channel matrix has to have a row for each output channel and a column for each input channel
+	MatrixShape { expected: (usize, usize), found: (usize, usize) },
+	/// Custom fade curve without a start and an end point
+	FadeTable { points: usize },
+}
+
+impl fmt::Display for TrackError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			TrackError::RateMismatch { expected, found } => write!(f, "Track at {found} Hz combined with one at {expected} Hz"),
+			TrackError::OutOfRange { range, length } => write!(f, "Frames {range:?} out of range for a track of {length} frames"),
+			TrackError::LengthMismatch { expected, found } => write!(f, "{found} frames where {expected} were expected"),
+			TrackError::InvalidChannel { channel, channels } => write!(f, "Channel {channel} selected but the track only has {channels} channels"),
+			TrackError::UnsupportedLayout { channels } => write!(f, "No speaker layout for {channels} channels"),
+			TrackError::MatrixShape { expected, found } => write!(f, "Channel matrix is {}x{}, expected {}x{}", found.0, found.1, expected.0, expected.1),
+			TrackError::FadeTable { points } => write!(f, "Fade curve table has {points} points, it needs at least 2"),
+		}
+	}
+}
+
+impl std::error::Error for TrackError {}
+
+
+
+#[derive(Debug)]
+pub enum LoadError {
+	Io(std::io::Error),
+	/// No registered format reader or codec can handle the source
+	UnsupportedFormat(String),
+	/// The container holds no audio track
+	MissingTrack,
+	MissingSampleRate,
+	/// The source decoded to zero frames
+	Empty,
+	InvalidChannel { channel: usize, channels: usize },
+	/// Packet at `timestamp` (in the track's time base) could not be decoded, or with no timestamp the container was damaged `seconds` into the track
+	Decode { timestamp: Option<u64>, seconds: f64, message: String },
+	/// Source data is damaged somewhere outside the packets, found while probing or seeking
+	Damaged(String),
+	Seek(String),
+	Resampler(String),
+	/// Region to load is empty or starts before 0, in seconds
+	InvalidRegion { start: f64, end: f64 },
+	CueSheet(String),
+	/// Loading was cancelled before this file finished
+	Cancelled,
+	Track(TrackError),
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			LoadError::Io(e) => write!(f, "IO error: {e}"),
+			LoadError::UnsupportedFormat(message) => write!(f, "Unsupported format: {message}"),
+			LoadError::MissingTrack => write!(f, "No audio track found"),
+			LoadError::MissingSampleRate => write!(f, "No sample rate"),
+			LoadError::Empty => write!(f, "No audio frames decoded"),
+			LoadError::InvalidChannel { channel, channels } => write!(f, "Channel {channel} selected but source only has {channels} channels"),
+			LoadError::Decode { timestamp: Some(timestamp), seconds, message } => write!(f, "Decode error at {seconds:.3}s (timestamp {timestamp}): {message}"),
+			LoadError::Decode { timestamp: None, seconds, message } => write!(f, "Damaged container at {seconds:.3}s: {message}"),
+			LoadError::Damaged(message) => write!(f, "Damaged source: {message}"),
+			LoadError::Seek(message) => write!(f, "Seek failed: {message}"),
+			LoadError::Resampler(message) => write!(f, "Resampler error: {message}"),
+			LoadError::InvalidRegion { start, end } => write!(f, "Invalid region {start}s to {end}s"),
+			LoadError::CueSheet(message) => write!(f, "Invalid cue sheet: {message}"),
+			LoadError::Cancelled => write!(f, "Loading cancelled"),
+			LoadError::Track(e) => write!(f, "{e}"),
+		}
+	}
+}
+
+impl std::error::Error for LoadError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			LoadError::Io(e) => Some(e),
+			LoadError::Track(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<std::io::Error> for LoadError {
+	fn from(e: std::io::Error) -> Self {
+		LoadError::Io(e)
+	}
+}
+
+impl From<SymphoniaError> for LoadError {
+	fn from(e: SymphoniaError) -> Self {
+		match e {
+			SymphoniaError::IoError(e) => LoadError::Io(e),
+			SymphoniaError::Unsupported(message) | SymphoniaError::LimitError(message) => LoadError::UnsupportedFormat(message.into()),
+			SymphoniaError::SeekError(kind) => LoadError::Seek(format!("{kind:?}")),
+			// Errors while decoding become `LoadError::Decode` where the position is known
+			SymphoniaError::DecodeError(message) => LoadError::Damaged(message.into()),
+			SymphoniaError::ResetRequired => LoadError::Damaged("Decoder reset required".into()),
+		}
+	}
+}
+
+impl From<TrackError> for LoadError {
+	fn from(e: TrackError) -> Self {
+		LoadError::Track(e)
+	}
+}
+
+impl From<rubato::ResampleError> for LoadError {
+	fn from(e: rubato::ResampleError) -> Self {
+		LoadError::Resampler(e.to_string())
+	}
+}
+
+impl From<rubato::ResamplerConstructionError> for LoadError {
+	fn from(e: rubato::ResamplerConstructionError) -> Self {
+		LoadError::Resampler(e.to_string())
+	}
+}
+
+
+
+#[derive(Debug)]
+pub enum ExportError {
+	Io(std::io::Error),
+	/// Sample over full scale, with `ClipDetection::Fail`
+	Clipped { frame: usize, channel: usize },
+	/// Too much audio for a plain RIFF file, which is limited to 4 GiB
+	TooLarge { bytes: u64 },
+	/// The format can't hold this kind of audio, like float samples in FLAC
+	Unsupported(String),
+	Encoder(String),
+	/// The request itself is wrong, like two sprites with the same name
+	InvalidInput(String),
+}
+
+impl fmt::Display for ExportError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ExportError::Io(e) => write!(f, "IO error: {e}"),
+			ExportError::Clipped { frame, channel } => write!(f, "Clipping at frame {frame} in channel {channel}"),
+			ExportError::TooLarge { bytes } => write!(f, "{bytes} bytes is too large for a RIFF file, use RF64"),
+			ExportError::Unsupported(message) => write!(f, "Unsupported: {message}"),
+			ExportError::Encoder(message) => write!(f, "Encoder error: {message}"),
+			ExportError::InvalidInput(message) => write!(f, "Invalid export: {message}"),
+		}
+	}
+}
+
+impl std::error::Error for ExportError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ExportError::Io(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<std::io::Error> for ExportError {
+	fn from(e: std::io::Error) -> Self {
+		ExportError::Io(e)
+	}
+}
+
+impl From<TrackError> for ExportError {
+	fn from(e: TrackError) -> Self {
+		ExportError::InvalidInput(e.to_string())
+	}
+}
+
+#[cfg(feature = "codecs")]
+impl From<vorbis_rs::VorbisError> for ExportError {
+	fn from(e: vorbis_rs::VorbisError) -> Self {
+		ExportError::Encoder(e.to_string())
+	}
+}
+
+
+
+#[derive(Debug)]
+pub enum RenderError {
+	Io(std::io::Error),
+	/// A source of the sound failed to load
+	Load(LoadError),
+	Export(ExportError),
+	/// Sound definition that doesn't parse, `line` counts from 1
+	Definition { line: usize, message: String },
+	/// Render asked for a parameter the sound definition doesn't have
+	UnknownParameter(String),
+	/// File name template that can't be filled in, or that gives two renders the same name
+	Template(String),
+}
+
+impl fmt::Display for RenderError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RenderError::Io(e) => write!(f, "IO error: {e}"),
+			RenderError::Load(e) => write!(f, "Loading a source failed: {e}"),
+			RenderError::Export(e) => write!(f, "Export failed: {e}"),
+			RenderError::Definition { line, message } => write!(f, "Invalid sound definition at line {line}: {message}"),
+			RenderError::UnknownParameter(name) => write!(f, "Unknown parameter {name}"),
+			RenderError::Template(message) => write!(f, "Invalid file name template: {message}"),
+		}
+	}
+}
+
+impl std::error::Error for RenderError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			RenderError::Io(e) => Some(e),
+			RenderError::Load(e) => Some(e),
+			RenderError::Export(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<std::io::Error> for RenderError {
+	fn from(e: std::io::Error) -> Self {
+		RenderError::Io(e)
+	}
+}
+
+impl From<LoadError> for RenderError {
+	fn from(e: LoadError) -> Self {
+		RenderError::Load(e)
+	}
+}
+
+impl From<ExportError> for RenderError {
+	fn from(e: ExportError) -> Self {
+		RenderError::Export(e)
+	}
+}
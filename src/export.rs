@@ -0,0 +1,422 @@
+use std::{fs::{File, OpenOptions}, io::{BufWriter, ErrorKind, Write}, path::{Path, PathBuf}, sync::atomic::{AtomicU64, Ordering}};
+
+use crate::*;
+
+
+/// Frames converted and written at a time
+const EXPORT_BLOCK_SIZE: usize = 4096;
+/// Error feedback filter for `Dither::NoiseShaped`, Wannamaker's 3 tap F-weighted curve
+const NOISE_SHAPING_COEFFICIENTS: [f32; 3] = [1.623, -0.982, 0.109];
+const KSDATAFORMAT_SUBTYPE_SUFFIX: [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xaa, 0x00, 0x38, 0x9b, 0x71];
+
+/// Numbers temporary export files, with the process id, so concurrent exports never share one
+static NEXT_TEMP_FILE: AtomicU64 = AtomicU64::new(0);
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+	Pcm16,
+	#[default]
+	Pcm24,
+	/// Written as is, never dithered and able to hold samples over full scale
+	Float32,
+}
+
+impl ExportFormat {
+	pub fn bits(self) -> u16 {
+		match self {
+			ExportFormat::Pcm16 => 16,
+			ExportFormat::Pcm24 => 24,
+			ExportFormat::Float32 => 32,
+		}
+	}
+	
+	pub fn bytes(self) -> usize {
+		self.bits() as usize / 8
+	}
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dither {
+	/// Plain rounding, the quantization error stays correlated with the signal
+	None,
+	/// Triangular noise of ±1 LSB, turns the error into a flat noise floor
+	#[default]
+	Triangular,
+	/// Triangular dither with the error fed back so the noise moves up to frequencies hearing is least sensitive to
+	NoiseShaped,
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClipDetection {
+	Off,
+	/// Count clipped samples in the export report
+	#[default]
+	Report,
+	/// Stop at the first clipped sample with `ExportError::Clipped`
+	Fail,
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WavContainer {
+	/// RIFF, or RF64 for files past the 4 GiB limit
+	#[default]
+	Auto,
+	Riff,
+	Rf64,
+}
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WavExportOptions {
+	pub format: ExportFormat,
+	pub dither: Dither,
+	pub clip_detection: ClipDetection,
+	pub container: WavContainer,
+	/// Seed of the dither noise, the same track and seed always export to the same bytes
+	pub dither_seed: u64,
+	/// Tags, markers, loops, description and provenance, written to `LIST`, `bext`, `cue ` and `smpl` chunks
+	pub metadata: TrackMetadata,
+}
+
+
+/// File format of an export, with the options for its encoder
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportOptions {
+	Wav(WavExportOptions),
+	Flac(FlacExportOptions),
+	#[cfg(feature = "codecs")]
+	Vorbis(VorbisExportOptions),
+	#[cfg(feature = "codecs")]
+	Opus(OpusExportOptions),
+}
+
+impl Default for ExportOptions {
+	fn default() -> Self {
+		ExportOptions::Wav(WavExportOptions::default())
+	}
+}
+
+impl ExportOptions {
+	pub fn extension(&self) -> &'static str {
+		match self {
+			ExportOptions::Wav(_) => "wav",
+			ExportOptions::Flac(_) => "flac",
+			#[cfg(feature = "codecs")]
+			ExportOptions::Vorbis(_) => "ogg",
+			#[cfg(feature = "codecs")]
+			ExportOptions::Opus(_) => "opus",
+		}
+	}
+	
+	/// Rate of the exported file, Opus always runs at 48 kHz
+	pub fn file_rate(&self, settings: &ProjectSettings) -> u32 {
+		match self {
+			#[cfg(feature = "codecs")]
+			ExportOptions::Opus(_) => OPUS_RATE,
+			_ => settings.rate(),
+		}
+	}
+	
+	/// Metadata written to the file, in frames of the project rate
+	pub fn metadata_mut(&mut self) -> &mut TrackMetadata {
+		match self {
+			ExportOptions::Wav(options) => &mut options.metadata,
+			ExportOptions::Flac(options) => &mut options.metadata,
+			#[cfg(feature = "codecs")]
+			ExportOptions::Vorbis(options) => &mut options.metadata,
+			#[cfg(feature = "codecs")]
+			ExportOptions::Opus(options) => &mut options.metadata,
+		}
+	}
+}
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExportReport {
+	/// Samples over full scale, counted per channel. Integer formats clamp them, float keeps them as they are.
+	pub clipped_samples: usize,
+	pub first_clipped_frame: Option<usize>,
+	/// Highest absolute sample value before conversion
+	pub peak: f32,
+	/// Loudness before and after normalizing, for exports through `export_normalized`
+	pub loudness: Option<LoudnessReport>,
+}
+
+impl ExportReport {
+	/// Tracks the peak and counts the sample if it clipped, or fails the export if that's the policy
+	pub fn record(&mut self, frame: usize, channel: usize, sample: f32, clipped: bool, clip_detection: ClipDetection) -> Result<(), ExportError> {
+		self.peak = self.peak.max(sample.abs());
+		if !clipped || clip_detection == ClipDetection::Off { return Ok(()) }
+		if clip_detection == ClipDetection::Fail { return Err(ExportError::Clipped { frame, channel }) }
+		
+		self.clipped_samples += 1;
+		self.first_clipped_frame.get_or_insert(frame);
+		Ok(())
+	}
+}
+
+
+/// Converts float samples of one channel to integers, with dither state that carries over from block to block
+pub struct Quantizer {
+	format: ExportFormat,
+	dither: Dither,
+	rng: u64,
+	error_history: [f32; 3],
+}
+
+impl Quantizer {
+	pub fn new(format: ExportFormat, dither: Dither, seed: u64) -> Self {
+		// Zero is a fixed point of xorshift
+		Self { format, dither, rng: seed ^ 0x9e37_79b9_7f4a_7c15, error_history: [0.0; 3] }
+	}
+	
+	/// Quantized sample, and whether it had to be clamped to fit
+	pub fn quantize(&mut self, sample: f32) -> (i32, bool) {
+		let scale = (1 << (self.format.bits() - 1)) as f32;
+		let max = scale - 1.0;
+		
+		let mut value = sample * scale;
+		if self.dither == Dither::NoiseShaped {
+			value -= NOISE_SHAPING_COEFFICIENTS.iter().zip(&self.error_history).map(|(coefficient, error)| coefficient * error).sum::<f32>();
+		}
+		
+		let noise = match self.dither {
+			Dither::None => 0.0,
+			Dither::Triangular | Dither::NoiseShaped => self.next_uniform() - self.next_uniform(),
+		};
+		let quantized = (value + noise).round();
+		
+		if self.dither == Dither::NoiseShaped {
+			self.error_history.rotate_right(1);
+			// Measured before clamping, so a clipped stretch can't wind the filter up
+			self.error_history[0] = quantized - value;
+		}
+		
+		let clamped = quantized.clamp(-scale, max);
+		(clamped as i32, clamped != quantized)
+	}
+	
+	/// Uniform in [0, 1), from xorshift64*
+	fn next_uniform(&mut self) -> f32 {
+		self.rng ^= self.rng >> 12;
+		self.rng ^= self.rng << 25;
+		self.rng ^= self.rng >> 27;
+		(self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40) as f32 / (1 << 24) as f32
+	}
+}
+
+
+
+/// Writes a track to a WAV file
+pub fn export_wav<P, const N: usize>(track: &AudioTrack<N>, path: P, settings: &ProjectSettings, options: &WavExportOptions) -> Result<ExportReport, ExportError> where P: AsRef<Path> {
+	write_file_atomically(path.as_ref(), |writer| write_wav(track, writer, settings, options))
+}
+
+/// Writes a track to a file in any of the export formats
+pub fn export_track<P, const N: usize>(track: &AudioTrack<N>, path: P, settings: &ProjectSettings, options: &ExportOptions) -> Result<ExportReport, ExportError> where P: AsRef<Path> {
+	match options {
+		ExportOptions::Wav(options) => export_wav(track, path, settings, options),
+		ExportOptions::Flac(options) => export_flac(track, path, settings, options),
+		#[cfg(feature = "codecs")]
+		ExportOptions::Vorbis(options) => export_ogg_vorbis(track, path, settings, options),
+		#[cfg(feature = "codecs")]
+		ExportOptions::Opus(options) => export_ogg_opus(track, path, settings, options),
+	}
+}
+
+/// Runs `write` on a file next to `path` and renames it into place once it succeeded, so a failed export leaves nothing behind
+pub fn write_file_atomically<F>(path: &Path, write: F) -> Result<ExportReport, ExportError> where F: FnOnce(&mut BufWriter<File>) -> Result<ExportReport, ExportError> {
+	let (temp_path, file) = create_temp_file(path)?;
+	
+	let result = (|| {
+		let mut writer = BufWriter::new(file);
+		let report = write(&mut writer)?;
+		writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+		std::fs::rename(&temp_path, path)?;
+		Ok(report)
+	})();
+	
+	if result.is_err() { let _ = std::fs::remove_file(&temp_path); }
+	result
+}
+
+/// Creates a file that didn't exist yet next to `path`. Its name is `path`'s with a dot in front and more after, so it's never `path` itself.
+fn create_temp_file(path: &Path) -> std::io::Result<(PathBuf, File)> {
+	let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+	loop {
+		let temp_path = path.with_file_name(format!(".{name}.{}.{}.tmp", std::process::id(), NEXT_TEMP_FILE.fetch_add(1, Ordering::Relaxed)));
+		match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+			Ok(file) => return Ok((temp_path, file)),
+			Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+			Err(e) => return Err(e),
+		}
+	}
+}
+
+/// Writes a track as a WAV stream at the project rate, resampled to it if it's at another. Sizes are known up front, so the writer doesn't need to seek.
+pub fn write_wav<W, const N: usize>(track: &AudioTrack<N>, writer: &mut W, settings: &ProjectSettings, options: &WavExportOptions) -> Result<ExportReport, ExportError> where W: Write {
+	// Marker and loop positions are in the track's frames, so they move with the samples
+	let metadata = options.metadata.rescaled(settings.resample_ratio(track.sample_rate));
+	let track = &settings.at_project_rate(track).map_err(|e| ExportError::Encoder(e.to_string()))?;
+	let format = options.format;
+	let frames = track.length();
+	let block_align = N * format.bytes();
+	let data_size = (frames * block_align) as u64;
+	
+	let fmt_chunk = fmt_chunk(N, settings.rate(), format);
+	let float = format == ExportFormat::Float32;
+	let metadata_chunks = metadata.riff_chunks(settings.rate());
+	let metadata_size = metadata_chunks.iter().map(|(_, body)| 8 + body.len() as u64 + body.len() as u64 % 2).sum::<u64>();
+	// Everything after the RIFF size field, except the ds64 chunk
+	let riff_size = 4 + (8 + fmt_chunk.len() as u64) + if float {12} else {0} + metadata_size + 8 + data_size + data_size % 2;
+	
+	let rf64 = match options.container {
+		WavContainer::Auto => riff_size > u32::MAX as u64,
+		WavContainer::Riff if riff_size > u32::MAX as u64 => return Err(ExportError::TooLarge { bytes: riff_size + 8 }),
+		WavContainer::Riff => false,
+		WavContainer::Rf64 => true,
+	};
+	
+	// RF64 puts u32::MAX in every size field that overflows and keeps the real sizes in ds64
+	if rf64 {
+		writer.write_all(b"RF64")?;
+		writer.write_all(&u32::MAX.to_le_bytes())?;
+		writer.write_all(b"WAVE")?;
+		
+		let mut ds64 = Vec::with_capacity(28);
+		ds64.extend_from_slice(&(riff_size + 36).to_le_bytes());
+		ds64.extend_from_slice(&data_size.to_le_bytes());
+		ds64.extend_from_slice(&(frames as u64).to_le_bytes());
+		ds64.extend_from_slice(&0u32.to_le_bytes());
+		write_chunk(writer, b"ds64", &ds64)?;
+	} else {
+		writer.write_all(b"RIFF")?;
+		writer.write_all(&(riff_size as u32).to_le_bytes())?;
+		writer.write_all(b"WAVE")?;
+	}
+	
+	write_chunk(writer, b"fmt ", &fmt_chunk)?;
+	// Non-PCM formats need the frame count in a fact chunk
+	if float { write_chunk(writer, b"fact", &(frames.min(u32::MAX as usize) as u32).to_le_bytes())?; }
+	for (id, body) in &metadata_chunks { write_chunk(writer, id, body)?; }
+	
+	writer.write_all(b"data")?;
+	writer.write_all(&(if rf64 {u32::MAX} else {data_size as u32}).to_le_bytes())?;
+	let report = write_samples(track, writer, options)?;
+	if data_size % 2 == 1 { writer.write_all(&[0])?; }
+	
+	Ok(report)
+}
+
+
+/// Quoted JSON string, for the manifests written next to exports
+pub fn json_string(text: &str) -> String {
+	let mut json = String::with_capacity(text.len() + 2);
+	json.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => json.push_str("\\\""),
+			'\\' => json.push_str("\\\\"),
+			'\n' => json.push_str("\\n"),
+			'\r' => json.push_str("\\r"),
+			'\t' => json.push_str("\\t"),
+			c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+			c => json.push(c),
+		}
+	}
+	json.push('"');
+	json
+}
+
+/// JSON number, with `null` for the infinities and NaN that JSON can't hold (like the loudness of silence)
+pub fn json_number(value: f64) -> String {
+	if value.is_finite() {value.to_string()} else {"null".into()}
+}
+
+
+fn write_samples<W, const N: usize>(track: &AudioTrack<N>, writer: &mut W, options: &WavExportOptions) -> Result<ExportReport, ExportError> where W: Write {
+	let format = options.format;
+	let mut quantizers: [Quantizer; N] = core::array::from_fn(|c| Quantizer::new(format, options.dither, options.dither_seed.wrapping_add(c as u64)));
+	let mut report = ExportReport::default();
+	let mut bytes = Vec::with_capacity(EXPORT_BLOCK_SIZE * N * format.bytes());
+	
+	for block_start in (0..track.length()).step_by(EXPORT_BLOCK_SIZE) {
+		let block_end = (block_start + EXPORT_BLOCK_SIZE).min(track.length());
+		bytes.clear();
+		
+		for frame in block_start..block_end {
+			for (c, (channel, quantizer)) in track.data.iter().zip(&mut quantizers).enumerate() {
+				let sample = channel[frame];
+				let clipped = match format {
+					ExportFormat::Float32 => {
+						bytes.extend_from_slice(&sample.to_le_bytes());
+						sample.abs() > 1.0
+					}
+					ExportFormat::Pcm16 | ExportFormat::Pcm24 => {
+						let (value, clipped) = quantizer.quantize(sample);
+						bytes.extend_from_slice(&value.to_le_bytes()[..format.bytes()]);
+						clipped
+					}
+				};
+				report.record(frame, c, sample, clipped, options.clip_detection)?;
+			}
+		}
+		
+		writer.write_all(&bytes)?;
+	}
+	
+	Ok(report)
+}
+
+/// Plain PCM or IEEE float for mono and stereo, WAVE_FORMAT_EXTENSIBLE with a speaker mask for anything wider
+fn fmt_chunk(channels: usize, rate: u32, format: ExportFormat) -> Vec<u8> {
+	let format_tag: u16 = if format == ExportFormat::Float32 {3} else {1};
+	let block_align = (channels * format.bytes()) as u16;
+	let extensible = channels > 2;
+	
+	let mut chunk = Vec::with_capacity(40);
+	chunk.extend_from_slice(&(if extensible {0xfffe} else {format_tag}).to_le_bytes());
+	chunk.extend_from_slice(&(channels as u16).to_le_bytes());
+	chunk.extend_from_slice(&rate.to_le_bytes());
+	chunk.extend_from_slice(&(rate * block_align as u32).to_le_bytes());
+	chunk.extend_from_slice(&block_align.to_le_bytes());
+	chunk.extend_from_slice(&format.bits().to_le_bytes());
+	
+	if extensible {
+		chunk.extend_from_slice(&22u16.to_le_bytes());
+		chunk.extend_from_slice(&format.bits().to_le_bytes());
+		chunk.extend_from_slice(&speaker_mask(channels).to_le_bytes());
+		chunk.extend_from_slice(&format_tag.to_le_bytes());
+		chunk.extend_from_slice(&KSDATAFORMAT_SUBTYPE_SUFFIX);
+	} else if format_tag != 1 {
+		chunk.extend_from_slice(&0u16.to_le_bytes());
+	}
+	
+	chunk
+}
+
+/// Standard layouts for the usual channel counts, unassigned for the rest
+pub fn speaker_mask(channels: usize) -> u32 {
+	match channels {
+		1 => 0x4,
+		2 => 0x3,
+		3 => 0x7,
+		4 => 0x33,
+		5 => 0x37,
+		6 => 0x3f,
+		8 => 0x63f,
+		_ => 0,
+	}
+}
+
+fn write_chunk<W>(writer: &mut W, id: &[u8; 4], body: &[u8]) -> std::io::Result<()> where W: Write {
+	writer.write_all(id)?;
+	writer.write_all(&(body.len() as u32).to_le_bytes())?;
+	writer.write_all(body)?;
+	if body.len() % 2 == 1 { writer.write_all(&[0])?; }
+	Ok(())
+}
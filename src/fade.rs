@@ -0,0 +1,54 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use crate::*;
+
+
+/// Range a logarithmic fade covers before dropping to silence
+const LOG_FADE_RANGE_DB: f32 = 60.0;
+
+
+/// Shape of a fade, as the gain of a fade in at each point along it. Fades out and the outgoing side of a crossfade use the same shape backwards.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum FadeCurve {
+	/// Gain rises evenly. Crossfades keep the amplitude constant, so they suit correlated material like two takes of the same sound.
+	Linear,
+	/// Quarter of a sine wave. Crossfades keep the power constant, so they suit unrelated material.
+	#[default]
+	EqualPower,
+	/// Gain rises evenly in decibels from -60 dB, sounding even to the ear
+	Logarithmic,
+	/// Half a cosine wave, starting and ending gently
+	SCurve,
+	/// Gains at evenly spaced points from the start to the end of the fade, interpolated linearly between them
+	Table(Vec<f32>),
+}
+
+impl FadeCurve {
+	/// Linear gain `position` of the way through a fade in, with `position` from 0 to 1
+	pub fn gain(&self, position: f32) -> f32 {
+		let x = position.clamp(0.0, 1.0);
+		match self {
+			FadeCurve::Linear => x,
+			FadeCurve::EqualPower => (x * FRAC_PI_2).sin(),
+			FadeCurve::Logarithmic => if x == 0.0 {0.0} else {10f32.powf(LOG_FADE_RANGE_DB * (x - 1.0) / 20.0)},
+			FadeCurve::SCurve => 0.5 - 0.5 * (x * PI).cos(),
+			FadeCurve::Table(points) => {
+				let Some(&last) = points.last() else { return x };
+				let scaled = x * (points.len() - 1) as f32;
+				let i = scaled as usize;
+				match points.get(i + 1) {
+					Some(&next) => points[i] + (next - points[i]) * (scaled - i as f32),
+					None => last,
+				}
+			}
+		}
+	}
+	
+	/// Custom tables need a start and an end point
+	pub fn check(&self) -> Result<(), TrackError> {
+		match self {
+			FadeCurve::Table(points) if points.len() < 2 => Err(TrackError::FadeTable { points: points.len() }),
+			_ => Ok(()),
+		}
+	}
+}
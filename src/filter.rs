@@ -1,8 +1,7 @@
 
 
 // use rustfft::FftPlanner;
-
-use crate::*;
+// use crate::*;
 
 
 // pub fn low_pass(track: &AudioTrack<2>, cutoff: f64) -> AudioTrack<2> {
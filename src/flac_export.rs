@@ -0,0 +1,623 @@
+use std::{io::Write, path::Path};
+
+use crate::*;
+
+
+/// FLAC can't describe more channels than this
+const FLAC_MAX_CHANNELS: usize = 8;
+/// Highest Rice parameter of the 4 bit and 5 bit parameter codings, the all-ones values are escape codes
+const RICE_MAX_PARAMETER: u32 = 14;
+const RICE2_MAX_PARAMETER: u32 = 30;
+const VENDOR_STRING: &str = concat!("sfx_daw ", env!("CARGO_PKG_VERSION"));
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlacExportOptions {
+	/// `Pcm16` or `Pcm24`, FLAC has no float samples
+	pub format: ExportFormat,
+	/// 0 to 8 like the reference encoder, higher levels search harder for smaller files and all decode at the same speed
+	pub compression_level: u8,
+	pub dither: Dither,
+	pub clip_detection: ClipDetection,
+	pub dither_seed: u64,
+	/// Vorbis comments as key and value, like `("TITLE", "Door creak")`
+	pub comments: Vec<(String, String)>,
+	/// Tags, markers, the first loop, description and provenance, written as Vorbis comments before `comments`
+	pub metadata: TrackMetadata,
+}
+
+impl Default for FlacExportOptions {
+	fn default() -> Self {
+		Self {
+			format: ExportFormat::Pcm24,
+			compression_level: 5,
+			dither: Dither::default(),
+			clip_detection: ClipDetection::default(),
+			dither_seed: 0,
+			comments: vec![],
+			metadata: TrackMetadata::default(),
+		}
+	}
+}
+
+
+/// Encoder settings that a compression level stands for
+#[derive(Clone, Copy, Debug)]
+struct FlacLevel {
+	block_size: usize,
+	/// 0 only tries the fixed predictors
+	max_lpc_order: usize,
+	/// Try every LPC order up to the maximum instead of only the highest
+	exhaustive_lpc_order: bool,
+	stereo_decorrelation: bool,
+	max_partition_order: u32,
+}
+
+impl FlacLevel {
+	fn new(compression_level: u8) -> Self {
+		let (block_size, max_lpc_order, exhaustive_lpc_order, stereo_decorrelation, max_partition_order) = match compression_level {
+			0 => (1152, 0, false, false, 3),
+			1 => (1152, 0, false, true, 3),
+			2 => (1152, 0, false, true, 3),
+			3 => (4096, 6, false, false, 4),
+			4 => (4096, 8, false, true, 4),
+			5 => (4096, 8, false, true, 5),
+			6 => (4096, 8, true, true, 6),
+			7 => (4096, 12, false, true, 6),
+			_ => (4096, 12, true, true, 6),
+		};
+		Self { block_size, max_lpc_order, exhaustive_lpc_order, stereo_decorrelation, max_partition_order }
+	}
+}
+
+
+
+/// Writes a track to a FLAC file
+pub fn export_flac<P, const N: usize>(track: &AudioTrack<N>, path: P, settings: &ProjectSettings, options: &FlacExportOptions) -> Result<ExportReport, ExportError> where P: AsRef<Path> {
+	write_file_atomically(path.as_ref(), |writer| write_flac(track, writer, settings, options))
+}
+
+/// Writes a track as a native FLAC stream at the project rate, resampled to it if it's at another
+pub fn write_flac<W, const N: usize>(track: &AudioTrack<N>, writer: &mut W, settings: &ProjectSettings, options: &FlacExportOptions) -> Result<ExportReport, ExportError> where W: Write {
+	if options.format == ExportFormat::Float32 { return Err(ExportError::Unsupported("FLAC can't store float samples".into())) }
+	if N == 0 || N > FLAC_MAX_CHANNELS { return Err(ExportError::Unsupported(format!("FLAC can't store {N} channels"))) }
+	// Marker and loop positions are in the track's frames, so they move with the samples
+	let metadata = options.metadata.rescaled(settings.resample_ratio(track.sample_rate));
+	let track = &settings.at_project_rate(track).map_err(|e| ExportError::Encoder(e.to_string()))?;
+	
+	let bits = options.format.bits() as u32;
+	let level = FlacLevel::new(options.compression_level);
+	let (samples, report) = quantize_track(track, options)?;
+	
+	let mut frames = vec![];
+	let mut frame_sizes: Option<(usize, usize)> = None;
+	for (frame_number, block_start) in (0..track.length()).step_by(level.block_size).enumerate() {
+		let block_end = (block_start + level.block_size).min(track.length());
+		let block: [&[i32]; N] = core::array::from_fn(|c| &samples[c][block_start..block_end]);
+		
+		let start = frames.len();
+		encode_frame(&mut frames, &block, frame_number as u32, bits, settings.rate(), &level);
+		let size = frames.len() - start;
+		frame_sizes = Some(frame_sizes.map_or((size, size), |(min, max)| (min.min(size), max.max(size))));
+	}
+	
+	let mut md5 = Md5::new();
+	let mut frame_bytes = Vec::with_capacity(N * 3);
+	for frame in 0..track.length() {
+		frame_bytes.clear();
+		for channel in &samples { frame_bytes.extend_from_slice(&channel[frame].to_le_bytes()[..(bits as usize / 8)]); }
+		md5.update(&frame_bytes);
+	}
+	
+	writer.write_all(b"fLaC")?;
+	
+	// The last block doesn't count towards the minimum block size, unless it's the only one
+	let block_size = level.block_size.min(track.length()).max(16) as u64;
+	// Zero frame sizes mean unknown
+	let (min_frame_size, max_frame_size) = frame_sizes.unwrap_or((0, 0));
+	
+	let mut stream_info = BitWriter::new();
+	stream_info.write(block_size, 16);
+	stream_info.write(block_size, 16);
+	stream_info.write(min_frame_size as u64, 24);
+	stream_info.write(max_frame_size as u64, 24);
+	stream_info.write(settings.rate() as u64, 20);
+	stream_info.write(N as u64 - 1, 3);
+	stream_info.write(bits as u64 - 1, 5);
+	stream_info.write(track.length() as u64, 36);
+	stream_info.bytes.extend_from_slice(&md5.finish());
+	write_metadata_block(writer, 0, false, &stream_info.bytes)?;
+	
+	let comments = [metadata.vorbis_comments(1.0), options.comments.clone()].concat();
+	let mut vorbis_comment = vec![];
+	vorbis_comment.extend_from_slice(&(VENDOR_STRING.len() as u32).to_le_bytes());
+	vorbis_comment.extend_from_slice(VENDOR_STRING.as_bytes());
+	vorbis_comment.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+	for (key, value) in &comments {
+		let comment = format!("{key}={value}");
+		vorbis_comment.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+		vorbis_comment.extend_from_slice(comment.as_bytes());
+	}
+	write_metadata_block(writer, 4, true, &vorbis_comment)?;
+	
+	writer.write_all(&frames)?;
+	Ok(report)
+}
+
+
+fn quantize_track<const N: usize>(track: &AudioTrack<N>, options: &FlacExportOptions) -> Result<([Vec<i32>; N], ExportReport), ExportError> {
+	let mut report = ExportReport::default();
+	let mut samples: [Vec<i32>; N] = core::array::from_fn(|_| Vec::with_capacity(track.length()));
+	let mut quantizers: [Quantizer; N] = core::array::from_fn(|c| Quantizer::new(options.format, options.dither, options.dither_seed.wrapping_add(c as u64)));
+	
+	// Frame by frame so a failing clip check stops at the same frame as the other formats
+	for frame in 0..track.length() {
+		for c in 0..N {
+			let sample = track.data[c][frame];
+			let (value, clipped) = quantizers[c].quantize(sample);
+			report.record(frame, c, sample, clipped, options.clip_detection)?;
+			samples[c].push(value);
+		}
+	}
+	
+	Ok((samples, report))
+}
+
+fn write_metadata_block<W>(writer: &mut W, block_type: u8, last: bool, body: &[u8]) -> std::io::Result<()> where W: Write {
+	writer.write_all(&[block_type | if last {0x80} else {0}])?;
+	writer.write_all(&(body.len() as u32).to_be_bytes()[1..])?;
+	writer.write_all(body)
+}
+
+
+
+fn encode_frame<const N: usize>(out: &mut Vec<u8>, block: &[&[i32]; N], frame_number: u32, bits: u32, rate: u32, level: &FlacLevel) {
+	let block_size = block[0].len();
+	
+	// Independent channels, or one of left/side, side/right and mid/side for stereo, whichever comes out smallest
+	let mut assignment = N as u64 - 1;
+	let mut subframes: Vec<BitWriter> = block.iter().map(|samples| encode_subframe(samples, bits, level)).collect();
+	
+	if N == 2 && level.stereo_decorrelation {
+		let side = block[0].iter().zip(block[1]).map(|(&left, &right)| left - right).collect::<Vec<_>>();
+		let mid = block[0].iter().zip(block[1]).map(|(&left, &right)| (left + right) >> 1).collect::<Vec<_>>();
+		let side_subframe = encode_subframe(&side, bits + 1, level);
+		let mid_subframe = encode_subframe(&mid, bits, level);
+		
+		let candidates = [
+			(subframes[0].len() + subframes[1].len(), 1),
+			(subframes[0].len() + side_subframe.len(), 8),
+			(side_subframe.len() + subframes[1].len(), 9),
+			(mid_subframe.len() + side_subframe.len(), 10),
+		];
+		assignment = candidates.iter().min_by_key(|(len, _)| *len).unwrap().1;
+		
+		match assignment {
+			8 => subframes[1] = side_subframe,
+			9 => subframes[0] = side_subframe,
+			10 => subframes = vec![mid_subframe, side_subframe],
+			_ => (),
+		}
+	}
+	
+	let mut header = BitWriter::new();
+	header.write(0b11111111111110, 14);
+	header.write(0, 1);
+	// Fixed block size stream
+	header.write(0, 1);
+	
+	let (block_size_code, block_size_tail) = match block_size {
+		192 => (1, None),
+		576 | 1152 | 2304 | 4608 => (2 + (block_size / 576).trailing_zeros() as u64, None),
+		256 | 512 | 1024 | 2048 | 4096 | 8192 | 16384 | 32768 => (8 + (block_size / 256).trailing_zeros() as u64, None),
+		1..=256 => (6, Some((block_size as u64 - 1, 8))),
+		_ => (7, Some((block_size as u64 - 1, 16))),
+	};
+	header.write(block_size_code, 4);
+	
+	let rate_code = match rate {
+		88200 => 1,
+		176400 => 2,
+		192000 => 3,
+		8000 => 4,
+		16000 => 5,
+		22050 => 6,
+		24000 => 7,
+		32000 => 8,
+		44100 => 9,
+		48000 => 10,
+		96000 => 11,
+		// Take the rate from STREAMINFO
+		_ => 0,
+	};
+	header.write(rate_code, 4);
+	header.write(assignment, 4);
+	header.write(if bits == 16 {0b100} else {0b110}, 3);
+	header.write(0, 1);
+	header.write_utf8(frame_number);
+	if let Some((value, bits)) = block_size_tail { header.write(value, bits); }
+	header.bytes.push(crc8(&header.bytes));
+	
+	let frame_start = out.len();
+	out.extend_from_slice(&header.bytes);
+	
+	let mut body = BitWriter::new();
+	for subframe in subframes { body.append(subframe); }
+	body.align();
+	out.extend_from_slice(&body.bytes);
+	
+	let crc = crc16(&out[frame_start..]);
+	out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Smallest of the constant, verbatim, fixed and LPC encodings of one channel of a block
+fn encode_subframe(samples: &[i32], bits: u32, level: &FlacLevel) -> BitWriter {
+	let mut subframe = BitWriter::new();
+	
+	if samples.iter().all(|&sample| sample == samples[0]) {
+		subframe.write(0b0000000, 7);
+		subframe.write(0, 1);
+		subframe.write_signed(samples[0] as i64, bits);
+		return subframe
+	}
+	
+	// Samples that all end in zero bits, like 16 bit audio in a 24 bit export, store those bits once
+	let wasted_bits = samples.iter().fold(0, |acc, &sample| acc | sample).trailing_zeros();
+	let shifted;
+	let samples = if wasted_bits > 0 {
+		shifted = samples.iter().map(|&sample| sample >> wasted_bits).collect::<Vec<_>>();
+		&shifted[..]
+	} else { samples };
+	let bits = bits - wasted_bits;
+	
+	let write_header = |subframe: &mut BitWriter, subframe_type: u64| {
+		subframe.write(subframe_type, 7);
+		if wasted_bits > 0 {
+			subframe.write(1, 1);
+			subframe.write_unary(wasted_bits - 1);
+		} else {
+			subframe.write(0, 1);
+		}
+	};
+	
+	write_header(&mut subframe, 0b0000001);
+	for &sample in samples { subframe.write_signed(sample as i64, bits); }
+	
+	let mut try_predictor = |subframe_type: u64, order: usize, coefficients: Option<(&[i32], u32, u32)>, residual: Vec<i64>| {
+		let Some(residual) = ResidualCoding::new(&residual, order, level.max_partition_order) else { return };
+		let coefficient_bits = coefficients.map_or(0, |(coefficients, precision, _)| 4 + 5 + precision as usize * coefficients.len());
+		if 8 + order * bits as usize + coefficient_bits + residual.bits >= subframe.len() { return }
+		
+		let mut candidate = BitWriter::new();
+		write_header(&mut candidate, subframe_type);
+		for &sample in &samples[..order] { candidate.write_signed(sample as i64, bits); }
+		if let Some((coefficients, precision, shift)) = coefficients {
+			candidate.write(precision as u64 - 1, 4);
+			candidate.write(shift as u64, 5);
+			for &coefficient in coefficients { candidate.write_signed(coefficient as i64, precision); }
+		}
+		residual.write(&mut candidate);
+		subframe = candidate;
+	};
+	
+	for order in 0..=4.min(samples.len() - 1) {
+		try_predictor(0b0001000 | order as u64, order, None, fixed_residual(samples, order));
+	}
+	
+	let lpc = if level.max_lpc_order > 0 && samples.len() > level.max_lpc_order { lpc_coefficients(samples, level.max_lpc_order) } else { vec![] };
+	if !lpc.is_empty() {
+		let precision = lpc_precision(samples.len());
+		let orders = if level.exhaustive_lpc_order { 1..=lpc.len() } else { lpc.len()..=lpc.len() };
+		
+		for order in orders {
+			let Some((coefficients, shift)) = quantize_coefficients(&lpc[order - 1], precision) else { continue };
+			let Some(residual) = lpc_residual(samples, &coefficients, shift) else { continue };
+			try_predictor(0b0100000 | (order as u64 - 1), order, Some((&coefficients, precision, shift)), residual);
+		}
+	}
+	
+	subframe
+}
+
+
+/// Residual of fixed polynomial predictors, order samples of warm-up excluded
+fn fixed_residual(samples: &[i32], order: usize) -> Vec<i64> {
+	let s = |i: usize| samples[i] as i64;
+	(order..samples.len()).map(|i| match order {
+		0 => s(i),
+		1 => s(i) - s(i - 1),
+		2 => s(i) - 2 * s(i - 1) + s(i - 2),
+		3 => s(i) - 3 * s(i - 1) + 3 * s(i - 2) - s(i - 3),
+		_ => s(i) - 4 * s(i - 1) + 6 * s(i - 2) - 4 * s(i - 3) + s(i - 4),
+	}).collect()
+}
+
+/// Predictor coefficients for every order up to `max_order`, from the autocorrelation of the Tukey windowed block.
+/// Coefficient j multiplies the sample j + 1 frames back.
+fn lpc_coefficients(samples: &[i32], max_order: usize) -> Vec<Vec<f64>> {
+	let n = samples.len();
+	let taper = (n / 4).max(1);
+	let windowed = samples.iter().enumerate().map(|(i, &sample)| {
+		let edge = i.min(n - 1 - i);
+		let window = if edge < taper { 0.5 - 0.5 * (std::f64::consts::PI * edge as f64 / taper as f64).cos() } else { 1.0 };
+		sample as f64 * window
+	}).collect::<Vec<_>>();
+	
+	let autocorrelation = (0..=max_order).map(|lag| windowed[lag..].iter().zip(&windowed).map(|(a, b)| a * b).sum::<f64>()).collect::<Vec<_>>();
+	
+	// Levinson-Durbin recursion
+	let mut orders = vec![];
+	let mut coefficients = vec![0.0; max_order];
+	let mut error = autocorrelation[0];
+	for i in 0..max_order {
+		if error <= 0.0 { break }
+		let reflection = (autocorrelation[i + 1] - (0..i).map(|j| coefficients[j] * autocorrelation[i - j]).sum::<f64>()) / error;
+		let previous = coefficients.clone();
+		coefficients[i] = reflection;
+		for j in 0..i { coefficients[j] = previous[j] - reflection * previous[i - 1 - j]; }
+		error *= 1.0 - reflection * reflection;
+		orders.push(coefficients[..=i].to_vec());
+	}
+	
+	orders
+}
+
+/// Coefficient precision the reference encoder uses for a block size
+fn lpc_precision(block_size: usize) -> u32 {
+	match block_size {
+		0..=192 => 7,
+		193..=384 => 8,
+		385..=576 => 9,
+		577..=1152 => 10,
+		1153..=2304 => 11,
+		2305..=4608 => 12,
+		_ => 13,
+	}
+}
+
+/// Integer coefficients and shift, with the rounding error of each carried into the next
+fn quantize_coefficients(coefficients: &[f64], precision: u32) -> Option<(Vec<i32>, u32)> {
+	let max = coefficients.iter().fold(0.0f64, |max, coefficient| max.max(coefficient.abs()));
+	if !(max > 0.0 && max.is_finite()) { return None }
+	
+	let limit = (1 << (precision - 1)) as f64;
+	let shift = ((precision as i32 - 1) - (max.log2().floor() as i32 + 1)).clamp(0, 15) as u32;
+	
+	let mut error = 0.0;
+	let quantized = coefficients.iter().map(|&coefficient| {
+		let value = coefficient * (1 << shift) as f64 + error;
+		let rounded = value.round().clamp(-limit, limit - 1.0);
+		error = value - rounded;
+		rounded as i32
+	}).collect();
+	
+	Some((quantized, shift))
+}
+
+/// `None` if a residual doesn't fit the 32 bits decoders work with
+fn lpc_residual(samples: &[i32], coefficients: &[i32], shift: u32) -> Option<Vec<i64>> {
+	let order = coefficients.len();
+	(order..samples.len()).map(|i| {
+		let prediction = coefficients.iter().enumerate().map(|(j, &coefficient)| coefficient as i64 * samples[i - 1 - j] as i64).sum::<i64>() >> shift;
+		let residual = samples[i] as i64 - prediction;
+		(residual >= i32::MIN as i64 && residual <= i32::MAX as i64).then_some(residual)
+	}).collect()
+}
+
+
+
+/// Partitioned Rice coding of a residual, with the partition order and parameters picked to minimise its size
+struct ResidualCoding<'a> {
+	residual: &'a [i64],
+	predictor_order: usize,
+	partition_order: u32,
+	parameters: Vec<u32>,
+	/// Size in bits, assuming the 5 bit parameters of the escape-free RICE2 coding
+	bits: usize,
+}
+
+impl<'a> ResidualCoding<'a> {
+	fn new(residual: &'a [i64], predictor_order: usize, max_partition_order: u32) -> Option<Self> {
+		let block_size = residual.len() + predictor_order;
+		let zigzag = |value: i64| ((value << 1) ^ (value >> 63)) as u64;
+		
+		let mut best: Option<Self> = None;
+		for partition_order in 0..=max_partition_order {
+			let partition_size = block_size >> partition_order;
+			if !block_size.is_multiple_of(1 << partition_order) || partition_size <= predictor_order { break }
+			
+			let mut parameters = vec![];
+			let mut bits = 2 + 4;
+			let mut start = 0;
+			for partition in 0..(1 << partition_order) {
+				let len = if partition == 0 { partition_size - predictor_order } else { partition_size };
+				let partition = &residual[start..(start + len)];
+				start += len;
+				
+				// Estimate from the sum, then count exactly around the estimate
+				let sum = partition.iter().map(|&value| zigzag(value)).sum::<u64>();
+				let estimate = (0..=RICE2_MAX_PARAMETER).min_by_key(|&parameter| len as u64 * (parameter as u64 + 1) + (sum >> parameter)).unwrap();
+				let (parameter, partition_bits) = (estimate.saturating_sub(1)..=(estimate + 1).min(RICE2_MAX_PARAMETER))
+					.map(|parameter| (parameter, len as u64 * (parameter as u64 + 1) + partition.iter().map(|&value| zigzag(value) >> parameter).sum::<u64>()))
+					.min_by_key(|&(_, bits)| bits)
+					.unwrap();
+				parameters.push(parameter);
+				bits += 5 + partition_bits as usize;
+			}
+			
+			if best.as_ref().is_none_or(|best| bits < best.bits) {
+				best = Some(Self { residual, predictor_order, partition_order, parameters, bits });
+			}
+		}
+		
+		best
+	}
+	
+	fn write(&self, out: &mut BitWriter) {
+		let rice2 = self.parameters.iter().any(|&parameter| parameter > RICE_MAX_PARAMETER);
+		let parameter_bits = if rice2 {5} else {4};
+		out.write(rice2 as u64, 2);
+		out.write(self.partition_order as u64, 4);
+		
+		let partition_size = (self.residual.len() + self.predictor_order) >> self.partition_order;
+		let mut start = 0;
+		for (partition, &parameter) in self.parameters.iter().enumerate() {
+			let len = if partition == 0 { partition_size - self.predictor_order } else { partition_size };
+			out.write(parameter as u64, parameter_bits);
+			for &value in &self.residual[start..(start + len)] {
+				let value = ((value << 1) ^ (value >> 63)) as u64;
+				out.write_unary((value >> parameter) as u32);
+				out.write(value, parameter);
+			}
+			start += len;
+		}
+	}
+}
+
+
+
+/// MSB-first bit packing
+struct BitWriter {
+	bytes: Vec<u8>,
+	accumulator: u64,
+	pending_bits: u32,
+}
+
+impl BitWriter {
+	fn new() -> Self {
+		Self { bytes: vec![], accumulator: 0, pending_bits: 0 }
+	}
+	
+	/// Length in bits
+	fn len(&self) -> usize {
+		self.bytes.len() * 8 + self.pending_bits as usize
+	}
+	
+	/// Lowest `bits` bits of `value`, at most 56
+	fn write(&mut self, value: u64, bits: u32) {
+		if bits == 0 { return }
+		self.accumulator = (self.accumulator << bits) | (value & ((1 << bits) - 1));
+		self.pending_bits += bits;
+		while self.pending_bits >= 8 {
+			self.pending_bits -= 8;
+			self.bytes.push((self.accumulator >> self.pending_bits) as u8);
+		}
+	}
+	
+	fn write_signed(&mut self, value: i64, bits: u32) {
+		self.write(value as u64, bits);
+	}
+	
+	/// `value` zero bits and a one
+	fn write_unary(&mut self, mut value: u32) {
+		while value >= 32 {
+			self.write(0, 32);
+			value -= 32;
+		}
+		self.write(1, value + 1);
+	}
+	
+	/// Frame numbers use the UTF-8 scheme, extended to 31 bits
+	fn write_utf8(&mut self, value: u32) {
+		if value < 0x80 { return self.write(value as u64, 8) }
+		
+		let continuation_bytes = match value {
+			0..=0x7ff => 1,
+			0x800..=0xffff => 2,
+			0x10000..=0x1fffff => 3,
+			0x200000..=0x3ffffff => 4,
+			_ => 5,
+		};
+		let lead_marker = (0xff00u32 >> (continuation_bytes + 1)) as u8;
+		self.write((lead_marker | (value >> (6 * continuation_bytes)) as u8) as u64, 8);
+		for i in (0..continuation_bytes).rev() {
+			self.write(0x80 | ((value >> (6 * i)) & 0x3f) as u64, 8);
+		}
+	}
+	
+	fn append(&mut self, other: BitWriter) {
+		for &byte in &other.bytes { self.write(byte as u64, 8); }
+		self.write(other.accumulator, other.pending_bits);
+	}
+	
+	fn align(&mut self) {
+		if self.pending_bits > 0 { self.write(0, 8 - self.pending_bits); }
+	}
+}
+
+
+fn crc8(bytes: &[u8]) -> u8 {
+	bytes.iter().fold(0, |mut crc, &byte| {
+		crc ^= byte;
+		for _ in 0..8 { crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 }; }
+		crc
+	})
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+	bytes.iter().fold(0, |mut crc, &byte| {
+		crc ^= (byte as u16) << 8;
+		for _ in 0..8 { crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 }; }
+		crc
+	})
+}
+
+
+/// MD5 of the interleaved samples for STREAMINFO, which decoders can check the decoded audio against
+struct Md5 {
+	state: [u32; 4],
+	buffer: Vec<u8>,
+	length: u64,
+}
+
+impl Md5 {
+	const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+	
+	fn new() -> Self {
+		Self { state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476], buffer: Vec::with_capacity(64), length: 0 }
+	}
+	
+	fn update(&mut self, mut bytes: &[u8]) {
+		self.length += bytes.len() as u64;
+		while !bytes.is_empty() {
+			let n = (64 - self.buffer.len()).min(bytes.len());
+			self.buffer.extend_from_slice(&bytes[..n]);
+			bytes = &bytes[n..];
+			if self.buffer.len() == 64 {
+				let block: [u8; 64] = self.buffer[..].try_into().unwrap();
+				self.process(&block);
+				self.buffer.clear();
+			}
+		}
+	}
+	
+	fn finish(mut self) -> [u8; 16] {
+		let bit_length = self.length.wrapping_mul(8);
+		self.update(&[0x80]);
+		while self.buffer.len() != 56 { self.update(&[0]); }
+		self.update(&bit_length.to_le_bytes());
+		
+		let mut digest = [0; 16];
+		for (bytes, word) in digest.chunks_exact_mut(4).zip(self.state) { bytes.copy_from_slice(&word.to_le_bytes()); }
+		digest
+	}
+	
+	fn process(&mut self, block: &[u8; 64]) {
+		let words: [u32; 16] = core::array::from_fn(|i| u32::from_le_bytes(block[(i * 4)..(i * 4 + 4)].try_into().unwrap()));
+		let [mut a, mut b, mut c, mut d] = self.state;
+		
+		for i in 0..64 {
+			let (f, g) = match i / 16 {
+				0 => ((b & c) | (!b & d), i),
+				1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+				2 => (b ^ c ^ d, (3 * i + 5) % 16),
+				_ => (c ^ (b | !d), (7 * i) % 16),
+			};
+			let constant = ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32;
+			let rotated = a.wrapping_add(f).wrapping_add(constant).wrapping_add(words[g]).rotate_left(Self::SHIFTS[(i / 16) * 4 + i % 4]);
+			(a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+		}
+		
+		for (state, value) in self.state.iter_mut().zip([a, b, c, d]) { *state = state.wrapping_add(value); }
+	}
+}
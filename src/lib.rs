@@ -0,0 +1,41 @@
+//! Audio core of sfx_daw: loading, decoding and resampling sources, sound definitions and offline rendering,
+//! loudness measurement and export. Playback and the editor window sit behind the `playback` and `gui` features,
+//! Ogg export and the extra decoders behind `codecs`.
+
+mod track; pub use track::*;
+mod timing; pub use timing::*;
+mod error; pub use error::*;
+mod project; pub use project::*;
+mod load; pub use load::*;
+mod load_job; pub use load_job::*;
+mod cache; pub use cache::*;
+mod export; pub use export::*;
+mod flac_export; pub use flac_export::*;
+#[cfg(feature = "codecs")] mod ogg_export;
+#[cfg(feature = "codecs")] pub use ogg_export::*;
+mod sprite; pub use sprite::*;
+mod loudness; pub use loudness::*;
+mod sound; pub use sound::*;
+mod batch; pub use batch::*;
+mod cli; pub use cli::*;
+mod decode; pub use decode::*;
+#[cfg(feature = "codecs")] mod opus_decoder;
+#[cfg(feature = "codecs")] pub use opus_decoder::*;
+mod source; pub use source::*;
+mod metadata; pub use metadata::*;
+mod cue_sheet; pub use cue_sheet::*;
+mod channel_map; pub use channel_map::*;
+mod fade; pub use fade::*;
+mod stream; pub use stream::*;
+#[cfg(feature = "playback")] mod player;
+#[cfg(feature = "playback")] pub use player::*;
+mod filter;
+
+#[cfg(feature = "playback")]
+use cpal::traits::{DeviceTrait, StreamTrait};
+use symphonia::core::{audio::{AudioBuffer, AudioBufferRef, Channels, Signal}, codecs::{Decoder, DecoderOptions}, conv::IntoSample, errors::Error as SymphoniaError, sample::Sample, formats::{FormatOptions, FormatReader, SeekMode, SeekTo}, io::{MediaSourceStream, MediaSourceStreamOptions}, meta::MetadataOptions, units::{Time, TimeBase}};
+use rubato::Resampler;
+
+
+
+const RESAMPLER_BLOCK_SIZE: usize = 1024;
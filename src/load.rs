@@ -1,102 +1,156 @@
 
 
+use std::ops::Range;
+
 use crate::*;
 
-pub fn load_audio<P>(paths: &[P]) -> Result<Vec<AudioTrack<2>>, String> where P: AsRef<std::path::Path> {
+
+/// Settings that can differ between the files of one `load_audio_with_options` call
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+	pub channel_policy: ChannelPolicy,
+	/// Set to `Skip` or `ZeroFill` to load damaged files instead of failing
+	pub decode_errors: DecodeErrorPolicy,
+	/// Needed for headerless PCM, and for other sources whose format can't be probed from the data alone
+	pub hint: SourceHint,
+	/// Only load this part of the source, in seconds. The reader seeks close to the start and stops decoding after the end.
+	pub region: Option<Range<f64>>,
+}
+
+pub struct LoadedTrack {
+	pub track: AudioTrack<2>,
+	/// Packets skipped or zero-filled in lenient mode, positions in resampled frames
+	pub decode_issues: Vec<DecodeIssue>,
+	/// Tags, markers and loops, positions in resampled frames
+	pub metadata: TrackMetadata,
+}
+
+
+pub fn load_audio<P>(paths: &[P], settings: &ProjectSettings) -> Result<Vec<AudioTrack<2>>, LoadError> where P: AsRef<std::path::Path> {
+	let files = paths.iter().map(|path| (path, LoadOptions::default())).collect::<Vec<_>>();
+	Ok(load_audio_with_options(&files, settings)?.into_iter().map(|loaded| loaded.track).collect())
+}
+
+pub fn load_audio_with_options<P>(files_options: &[(P, LoadOptions)], settings: &ProjectSettings) -> Result<Vec<LoadedTrack>, LoadError> where P: AsRef<std::path::Path> {
+	load_audio_from_sources(files_options.iter().map(|(path, options)| (AudioSource::from(path.as_ref()), options.clone())).collect(), settings)
+}
+
+/// Loads from in-memory bytes and readers as well as paths
+pub fn load_audio_from_sources(sources: Vec<(AudioSource, LoadOptions)>, settings: &ProjectSettings) -> Result<Vec<LoadedTrack>, LoadError> {
 	
-	let num_tracks = paths.len();
+	let mut decoders = vec![];
 	
-	let mut files = vec![];
-	let mut total_frames = 0;
-	let mut track_ends = vec![];
+	for (source, options) in sources {
+		decoders.push((TrackDecoder::open(source, &options)?, options.region));
+	}
+	
+	
+	// Consecutive files with the same rate are decoded into one buffer and resampled together, every run with its own ratio.
+	// Regions are streamed on their own instead.
+	let mut runs: Vec<DecodedRun> = vec![];
+	let mut decode_issues = vec![];
+	let mut metadata = vec![];
+	let mut regions = vec![];
 	
-	for path in paths {
-		let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
-		let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions { buffer_len: 1024 * 1024 });
-		let mut format = FormatOptions::default();
-		format.enable_gapless = true;
+	for (mut decoder, region) in decoders {
+		if let Some(region) = region {
+			regions.push(Some(load_region(decoder, region, settings)?));
+			continue
+		}
+		regions.push(None);
+		
+		let rate = decoder.rate();
+		if runs.last().is_none_or(|run| run.rate != rate) {
+			runs.push(DecodedRun { rate, samples: [vec![], vec![]], track_ends: vec![] });
+		}
+		let run = runs.last_mut().unwrap();
+		
+		// Only a hint for preallocation, VBR and streamed formats often don't know their length up front
+		let expected_frames = decoder.n_frames().unwrap_or(0) as usize;
+		for c in 0..2 { run.samples[c].reserve(expected_frames); }
 		
-		let probe = symphonia::default::get_probe().format(&Hint::new(), mss, &format, &MetadataOptions::default()).map_err(|e| e.to_string())?;
-		let track = probe.format.default_track().unwrap_or(&probe.format.tracks()[0]).clone();
+		while decoder.decode_next(&mut run.samples)? {}
 		
-		let n_frames = track.codec_params.n_frames.ok_or("No frame count")? as usize;
-		total_frames += n_frames;
-		files.push((probe, track));
+		run.track_ends.push(run.samples[0].len());
 		
-		track_ends.push(total_frames);
+		let resample_ratio = settings.resample_ratio(rate);
+		decode_issues.push(decoder.issues.iter().map(|issue| issue.rescaled(resample_ratio)).collect());
+		metadata.push(decoder.metadata.rescaled(resample_ratio));
 	}
 	
 	
-	let padded_length = ((total_frames - 1) / RESAMPLER_BLOCK_SIZE + 1) * RESAMPLER_BLOCK_SIZE;
-	let mut audio_track = AudioTrack::<2>::new(padded_length);
-	
-	let rate = files[0].1.codec_params.sample_rate.unwrap();
-	let channels = files[0].1.codec_params.channels.unwrap().count();
-	
-	
-	let mut frame = 0;
-	
-	for (mut probe, track) in files {
-		let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default()).map_err(|e| e.to_string())?;
-		
-		loop {
-			let packet = match probe.format.next_packet() {
-				Ok(p) => p,
-				Err(symphonia::core::errors::Error::IoError(_)) => break,
-				Err(_) => panic!(),
-			};
-			
-			if packet.track_id() != track.id { continue }
-			
-			let audio_buf = decoder.decode(&packet).map_err(|e| e.to_string())?;
-			
-			let frames_now = packet.dur as usize;
-			
-			match audio_buf {
-				AudioBufferRef::F32(buf) => {
-					for channel in 0..2 {
-						audio_track.data[channel][frame..(frame + frames_now)].copy_from_slice(buf.chan(channel));
-					}
-				}
-				AudioBufferRef::F64(buf) => for channel in 0..2 { let samples = buf.chan(channel); for i in 0..frames_now { audio_track.data[channel][frame + i] = samples[i].into_sample(); } }
-				AudioBufferRef::S8 (buf) => for channel in 0..2 { let samples = buf.chan(channel); for i in 0..frames_now { audio_track.data[channel][frame + i] = samples[i].into_sample(); } }
-				AudioBufferRef::S16(buf) => for channel in 0..2 { let samples = buf.chan(channel); for i in 0..frames_now { audio_track.data[channel][frame + i] = samples[i].into_sample(); } }
-				AudioBufferRef::S24(buf) => for channel in 0..2 { let samples = buf.chan(channel); for i in 0..frames_now { audio_track.data[channel][frame + i] = samples[i].into_sample(); } }
-				AudioBufferRef::S32(buf) => for channel in 0..2 { let samples = buf.chan(channel); for i in 0..frames_now { audio_track.data[channel][frame + i] = samples[i].into_sample(); } }
-				AudioBufferRef::U8 (buf) => for channel in 0..2 { let samples = buf.chan(channel); for i in 0..frames_now { audio_track.data[channel][frame + i] = samples[i].into_sample(); } }
-				AudioBufferRef::U16(buf) => for channel in 0..2 { let samples = buf.chan(channel); for i in 0..frames_now { audio_track.data[channel][frame + i] = samples[i].into_sample(); } }
-				AudioBufferRef::U24(buf) => for channel in 0..2 { let samples = buf.chan(channel); for i in 0..frames_now { audio_track.data[channel][frame + i] = samples[i].into_sample(); } }
-				AudioBufferRef::U32(buf) => for channel in 0..2 { let samples = buf.chan(channel); for i in 0..frames_now { audio_track.data[channel][frame + i] = samples[i].into_sample(); } }
-			}
-			
-			frame += packet.dur as usize;
-		}
+	let mut resampled_tracks = vec![];
+	for run in runs {
+		resampled_tracks.extend(resample_run(run, settings)?);
 	}
 	
+	let mut loaded_tracks = resampled_tracks.into_iter().zip(decode_issues).zip(metadata).map(|((track, decode_issues), metadata)| LoadedTrack { track, decode_issues, metadata });
+	Ok(regions.into_iter().map(|region| region.unwrap_or_else(|| loaded_tracks.next().unwrap())).collect())
+}
+
+/// Decodes only the frames inside `region` (in seconds), the same frames as cutting them out of the fully loaded track.
+/// They're bit-exact at the project rate, resampled ones can differ by float rounding.
+pub fn load_region(decoder: TrackDecoder, region: Range<f64>, settings: &ProjectSettings) -> Result<LoadedTrack, LoadError> {
+	if !(region.start >= 0.0 && region.end > region.start) { return Err(LoadError::InvalidRegion { start: region.start, end: region.end }) }
+	
+	let start = settings.seconds_to_frames(region.start);
+	let end = settings.seconds_to_frames(region.end);
+	
+	let mut stream = AudioStream::from_decoder(decoder, settings)?;
+	stream.seek(start)?;
+	let track = stream.read_track(end - start)?;
+	if track.length() == 0 { return Err(LoadError::Empty) }
+	
+	let frames = start..(start + track.length());
+	Ok(LoadedTrack {
+		decode_issues: stream.decode_issues().into_iter()
+			.filter(|issue| frames.contains(&issue.frame))
+			.map(|issue| DecodeIssue { frame: issue.frame - start, ..issue })
+			.collect(),
+		metadata: stream.metadata().region(frames),
+		track,
+	})
+}
+
+
+
+/// Frames decoded from consecutive files that share a sample rate
+pub struct DecodedRun {
+	pub rate: u32,
+	pub samples: [Vec<f32>; 2],
+	pub track_ends: Vec<usize>,
+}
+
+/// Resamples a run of concatenated files to the project rate and splits it back into one track per file
+pub fn resample_run(run: DecodedRun, settings: &ProjectSettings) -> Result<Vec<AudioTrack<2>>, LoadError> {
 	
+	let DecodedRun { rate, mut samples, track_ends } = run;
 	
+	let total_frames = samples[0].len();
+	if total_frames == 0 { return Err(LoadError::Empty) }
 	
+	let resample_ratio = settings.resample_ratio(rate);
+	let mut resampler = ProjectResampler::new(settings, rate)?;
+	let max_resampled_block_size = resampler.output_frames_max();
+	let delay = resampler.output_delay();
 	
-	let resample_ratio = 48000.0 / rate as f64;
-	let max_resampled_block_size = (RESAMPLER_BLOCK_SIZE as f64 * resample_ratio) as usize + 10;
+	// Extra input so the resampler's latency never runs it out of frames before the last track is filled
+	let latency_frames = ((delay + resampler.latency()) as f64 / resample_ratio).ceil() as usize;
+	let padded_length = ((total_frames + latency_frames - 1) / RESAMPLER_BLOCK_SIZE + 2) * RESAMPLER_BLOCK_SIZE;
+	for channel in &mut samples { channel.resize(padded_length, 0.0); }
+	let audio_track = AudioTrack { data: samples.map(TrackChannel::from), sample_rate: rate };
 	
-	// Can't just resample n_frames to get exact length, extra steps needed to avoid rounding errors
-	let mut resampled_tracks = (0..num_tracks).map(|i| 
-		AudioTrack::new((track_ends[i] as f64 * resample_ratio) as usize - (if i == 0 {0} else {track_ends[i - 1]} as f64 * resample_ratio) as usize)
-	).collect::<Vec<_>>();
 	
+	// Can't just resample n_frames to get exact length, extra steps needed to avoid rounding errors.
+	// The resampler's delay is treated as an extra track at the start that gets thrown away.
+	let mut resampled_tracks = std::iter::once(AudioTrack::new(delay, settings.rate())).chain((0..track_ends.len()).map(|i| 
+		AudioTrack::new((track_ends[i] as f64 * resample_ratio) as usize - (if i == 0 {0} else {track_ends[i - 1]} as f64 * resample_ratio) as usize, settings.rate())
+	)).collect::<Vec<_>>();
+	let num_tracks = resampled_tracks.len();
 	
 	
-	let mut resampled_edge_buffer = AudioTrack::new((RESAMPLER_BLOCK_SIZE as f64 * resample_ratio) as usize + 10);
 	
-	let mut resampler = rubato::FastFixedIn::<f32>::new(resample_ratio, 1.0, rubato::PolynomialDegree::Septic, RESAMPLER_BLOCK_SIZE, channels).unwrap();
-	// let mut resampler = rubato::SincFixedIn::<f32>::new(resample_ratio, 1.0, rubato::SincInterpolationParameters {
-	// 	sinc_len: 256,
-	// 	f_cutoff: 0.95,
-	// 	interpolation: rubato::SincInterpolationType::Cubic,
-	// 	oversampling_factor: 256,
-	// 	window: rubato::WindowFunction::BlackmanHarris2,
-	// }, block_size, channels).unwrap();
+	let mut resampled_edge_buffer = AudioTrack::new(max_resampled_block_size, settings.rate());
 	
 	
 	let mut i = 0;
@@ -111,32 +165,30 @@ pub fn load_audio<P>(paths: &[P]) -> Result<Vec<AudioTrack<2>>, String> where P:
 		if resampled_frames_left_in_track >= max_resampled_block_size {
 			// Resample block directly into track data
 			
-			let (_, n) = resampler.process_into_buffer(
+			let n = resampler.process_into_buffer(
 				&audio_track.get_slice(frame..(frame + RESAMPLER_BLOCK_SIZE)),
 				&mut resampled_tracks[i].get_slice_mut(resampled_track_frame..(resampled_track_frame + max_resampled_block_size)),
-				None
-			).map_err(|e| e.to_string())?;
+			)?;
 			
 			resampled_track_frame += n;
 			
 		} else {
 			// Probably not enough space to fit resampled frames, read to buffer instead
 			
-			let (_, n) = resampler.process_into_buffer(
+			let n = resampler.process_into_buffer(
 				&audio_track.get_slice(frame..(frame + RESAMPLER_BLOCK_SIZE)),
 				&mut resampled_edge_buffer.data,
-				None
-			).map_err(|e| e.to_string())?;
+			)?;
 			
 			if resampled_frames_left_in_track >= n {
 				// Rare case where the end of the track actually fell in the safety margin just after this block, copy whole buffer and move on
-				resampled_tracks[i].copy_from_range(resampled_track_frame..(resampled_track_frame + n), &resampled_edge_buffer, 0..n);
+				resampled_tracks[i].copy_from_range(resampled_track_frame..(resampled_track_frame + n), &resampled_edge_buffer, 0..n)?;
 				resampled_track_frame += n;
 				
 			} else {
 				// Track ends in the middle of the buffer, do partial copies into multiple tracks
 				
-				resampled_tracks[i].copy_from_range(resampled_track_frame..track_length, &resampled_edge_buffer, 0..resampled_frames_left_in_track);
+				resampled_tracks[i].copy_from_range(resampled_track_frame..track_length, &resampled_edge_buffer, 0..resampled_frames_left_in_track)?;
 				resampled_track_frame = n - resampled_frames_left_in_track;
 				
 				loop {
@@ -146,10 +198,10 @@ pub fn load_audio<P>(paths: &[P]) -> Result<Vec<AudioTrack<2>>, String> where P:
 					let buffer_progress = n - resampled_track_frame;
 					let track_length = resampled_tracks[i].length();
 					if resampled_track_frame > track_length {
-						resampled_tracks[i].copy_from_range(0..track_length, &resampled_edge_buffer, buffer_progress..(buffer_progress + track_length));
+						resampled_tracks[i].copy_from_range(0..track_length, &resampled_edge_buffer, buffer_progress..(buffer_progress + track_length))?;
 						resampled_track_frame -= track_length;
 					} else {
-						resampled_tracks[i].copy_from_range(0..resampled_track_frame, &resampled_edge_buffer, buffer_progress..n);
+						resampled_tracks[i].copy_from_range(0..resampled_track_frame, &resampled_edge_buffer, buffer_progress..n)?;
 						break
 					}
 				}
@@ -162,7 +214,7 @@ pub fn load_audio<P>(paths: &[P]) -> Result<Vec<AudioTrack<2>>, String> where P:
 	
 	
 	
+	resampled_tracks.remove(0);
 	Ok(resampled_tracks)
 	
 }
-
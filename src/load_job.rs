@@ -0,0 +1,180 @@
+use std::{path::{Path, PathBuf}, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc, Arc}, thread::JoinHandle};
+
+use crate::*;
+
+
+/// Packets decoded between progress reports and cancellation checks
+const PACKETS_PER_REPORT: usize = 32;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadStage {
+	/// Fraction of the source decoded so far, `None` if the container doesn't report its length
+	Decoding { fraction: Option<f32> },
+	Resampling,
+	Done,
+	Failed(String),
+	Cancelled,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadProgress {
+	/// Position of the file in the list passed to the loader
+	pub index: usize,
+	pub stage: LoadStage,
+}
+
+
+/// Shared flag to stop a parallel load partway through. Files that already finished keep their result, the rest return `LoadError::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+	
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+}
+
+
+/// Loads every file separately on a pool of `threads` workers, through the cache if there is one. `progress` is called from the worker threads as files advance.
+/// Blocks until all files are done, returns one result per file in input order.
+pub fn load_audio_parallel<P, F>(files_options: &[(P, LoadOptions)], settings: &ProjectSettings, threads: usize, cache: Option<&AudioCache>, cancel: &CancelToken, progress: F) -> Vec<Result<LoadedTrack, LoadError>> where P: AsRef<Path> + Sync, F: Fn(LoadProgress) + Sync {
+	
+	let threads = threads.clamp(1, files_options.len().max(1));
+	let next_file = AtomicUsize::new(0);
+	
+	let mut results = std::thread::scope(|scope| {
+		let workers = (0..threads).map(|_| scope.spawn(|| {
+			let mut results = vec![];
+			
+			loop {
+				let index = next_file.fetch_add(1, Ordering::Relaxed);
+				let Some((path, options)) = files_options.get(index) else { break };
+				
+				// Cache hits are quick enough not to need progress, misses are decoded with it and then stored
+				let cached = cache.filter(|_| !cancel.is_cancelled()).and_then(|cache| cache.lookup(path, options, settings));
+				let result = match cached {
+					Some(loaded) => Ok(loaded),
+					None => load_file(index, path.as_ref(), options, settings, cancel, &progress).inspect(|loaded| {
+						// A cache that can't be written to only means decoding again next time
+						if let Some(cache) = cache { let _ = cache.store(path, options, settings, loaded); }
+					}),
+				};
+				progress(LoadProgress { index, stage: match &result {
+					Ok(_) => LoadStage::Done,
+					Err(LoadError::Cancelled) => LoadStage::Cancelled,
+					Err(e) => LoadStage::Failed(e.to_string()),
+				} });
+				
+				results.push((index, result));
+			}
+			
+			results
+		})).collect::<Vec<_>>();
+		
+		workers.into_iter().flat_map(|worker| worker.join().expect("Load worker panicked")).collect::<Vec<_>>()
+	});
+	
+	results.sort_by_key(|(index, _)| *index);
+	results.into_iter().map(|(_, result)| result).collect()
+}
+
+
+fn load_file<F>(index: usize, path: &Path, options: &LoadOptions, settings: &ProjectSettings, cancel: &CancelToken, progress: &F) -> Result<LoadedTrack, LoadError> where F: Fn(LoadProgress) {
+	if cancel.is_cancelled() { return Err(LoadError::Cancelled) }
+	
+	let mut decoder = TrackDecoder::open(AudioSource::from(path), options)?;
+	if let Some(region) = options.region.clone() {
+		// Regions are streamed straight into their track, without progress reports
+		return load_region(decoder, region, settings)
+	}
+	
+	let n_frames = decoder.n_frames();
+	let fraction = |decoder: &TrackDecoder| n_frames.map(|n| (decoder.frame() as f64 / n.max(1) as f64).min(1.0) as f32);
+	progress(LoadProgress { index, stage: LoadStage::Decoding { fraction: fraction(&decoder) } });
+	
+	let mut samples = [vec![], vec![]];
+	for channel in &mut samples { channel.reserve(n_frames.unwrap_or(0) as usize); }
+	
+	let mut packets = 0;
+	while decoder.decode_next(&mut samples)? {
+		packets += 1;
+		if packets % PACKETS_PER_REPORT == 0 {
+			if cancel.is_cancelled() { return Err(LoadError::Cancelled) }
+			progress(LoadProgress { index, stage: LoadStage::Decoding { fraction: fraction(&decoder) } });
+		}
+	}
+	
+	// Resampling isn't interrupted, so this is the last chance to stop before it
+	if cancel.is_cancelled() { return Err(LoadError::Cancelled) }
+	progress(LoadProgress { index, stage: LoadStage::Resampling });
+	
+	let rate = decoder.rate();
+	let track_ends = vec![samples[0].len()];
+	let track = resample_run(DecodedRun { rate, samples, track_ends }, settings)?.remove(0);
+	
+	let resample_ratio = settings.resample_ratio(rate);
+	Ok(LoadedTrack {
+		track,
+		decode_issues: decoder.issues.iter().map(|issue| issue.rescaled(resample_ratio)).collect(),
+		metadata: decoder.metadata.rescaled(resample_ratio),
+	})
+}
+
+
+
+/// Parallel load running on a background thread, so the event loop can poll it instead of blocking on `load_audio`
+pub struct LoadJob {
+	progress: mpsc::Receiver<LoadProgress>,
+	cancel: CancelToken,
+	handle: JoinHandle<Vec<Result<LoadedTrack, LoadError>>>,
+}
+
+impl LoadJob {
+	/// Starts loading with one worker per available core
+	pub fn spawn(files_options: Vec<(PathBuf, LoadOptions)>, settings: ProjectSettings, cache: Option<Arc<AudioCache>>) -> Self {
+		let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+		Self::spawn_with_threads(files_options, settings, cache, threads)
+	}
+	
+	pub fn spawn_with_threads(files_options: Vec<(PathBuf, LoadOptions)>, settings: ProjectSettings, cache: Option<Arc<AudioCache>>, threads: usize) -> Self {
+		let (sender, receiver) = mpsc::channel();
+		let cancel = CancelToken::new();
+		
+		let handle = std::thread::spawn({
+			let cancel = cancel.clone();
+			move || load_audio_parallel(&files_options, &settings, threads, cache.as_deref(), &cancel, |progress| {
+				// The receiver may already be gone if the job was dropped, the results are still collected
+				let _ = sender.send(progress);
+			})
+		});
+		
+		Self { progress: receiver, cancel, handle }
+	}
+	
+	/// Progress reported since the last call, without blocking
+	pub fn progress(&self) -> impl Iterator<Item = LoadProgress> + '_ {
+		self.progress.try_iter()
+	}
+	
+	pub fn cancel(&self) {
+		self.cancel.cancel();
+	}
+	
+	pub fn is_finished(&self) -> bool {
+		self.handle.is_finished()
+	}
+	
+	/// Waits for the job to finish and returns one result per file in input order
+	pub fn join(self) -> Vec<Result<LoadedTrack, LoadError>> {
+		self.handle.join().expect("Load job panicked")
+	}
+}
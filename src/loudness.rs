@@ -0,0 +1,257 @@
+use std::path::Path;
+
+use crate::*;
+
+
+/// Gating block of integrated loudness, and its step, in seconds (ITU-R BS.1770-4)
+const MOMENTARY_WINDOW: f64 = 0.4;
+const BLOCK_STEP: f64 = 0.1;
+const SHORT_TERM_WINDOW: f64 = 3.0;
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks quieter than this, relative to the loudness of the blocks above the absolute gate, are left out
+const RELATIVE_GATE: f64 = -10.0;
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+/// Taps of each polyphase branch of the true peak interpolator
+const TRUE_PEAK_TAPS_PER_PHASE: usize = 16;
+
+
+/// Loudness as defined by ITU-R BS.1770 and EBU R128, in LUFS and dBTP. Silence measures as negative infinity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoudnessMeasurement {
+	/// Gated loudness of the whole track
+	pub integrated: f64,
+	/// Loudest 3 second window
+	pub short_term_max: f64,
+	/// Peak of the signal reconstructed at 4 times the rate, which catches the overshoot between samples that a converter or codec produces
+	pub true_peak: f64,
+}
+
+impl LoudnessMeasurement {
+	/// The same track after a gain in dB, loudness and peaks all move by the gain
+	pub fn with_gain(&self, gain_db: f64) -> Self {
+		Self {
+			integrated: self.integrated + gain_db,
+			short_term_max: self.short_term_max + gain_db,
+			true_peak: self.true_peak + gain_db,
+		}
+	}
+}
+
+
+/// Level to normalize an export to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoudnessTarget {
+	/// Integrated loudness in LUFS, with the gain held back where it would push the true peak over `max_true_peak` dBTP
+	Integrated { lufs: f64, max_true_peak: Option<f64> },
+	/// Loudest short-term window in LUFS, which suits one-shots too short for integrated loudness to mean much
+	ShortTermMax { lufs: f64, max_true_peak: Option<f64> },
+	/// True peak in dBTP
+	TruePeak(f64),
+}
+
+impl LoudnessTarget {
+	/// Broadcast in Europe
+	pub const EBU_R128: Self = LoudnessTarget::Integrated { lufs: -23.0, max_true_peak: Some(-1.0) };
+	/// Broadcast in the US
+	pub const ATSC_A85: Self = LoudnessTarget::Integrated { lufs: -24.0, max_true_peak: Some(-2.0) };
+	/// Streaming platforms, podcasts and most game platform guidelines
+	pub const STREAMING: Self = LoudnessTarget::Integrated { lufs: -16.0, max_true_peak: Some(-1.0) };
+	
+	/// Gain in dB that brings a measurement to the target, and whether the true peak ceiling cut it short.
+	/// Silence gets no gain.
+	pub fn gain(&self, measured: &LoudnessMeasurement) -> (f64, bool) {
+		let (gain, max_true_peak) = match *self {
+			LoudnessTarget::Integrated { lufs, max_true_peak } => (lufs - measured.integrated, max_true_peak),
+			LoudnessTarget::ShortTermMax { lufs, max_true_peak } => (lufs - measured.short_term_max, max_true_peak),
+			LoudnessTarget::TruePeak(dbtp) => (dbtp - measured.true_peak, None),
+		};
+		if !gain.is_finite() { return (0.0, false) }
+		
+		match max_true_peak {
+			Some(max_true_peak) if measured.true_peak + gain > max_true_peak => (max_true_peak - measured.true_peak, true),
+			_ => (gain, false),
+		}
+	}
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoudnessReport {
+	pub target: LoudnessTarget,
+	/// The track as it came in
+	pub measured: LoudnessMeasurement,
+	pub gain_db: f64,
+	/// The track as it was exported
+	pub normalized: LoudnessMeasurement,
+	/// The gain stopped at the true peak ceiling, so the track is quieter than the target
+	pub peak_limited: bool,
+}
+
+
+
+/// Measures integrated loudness, short-term maximum and true peak of a track at its sample rate.
+/// Channels are weighted by their position in the WAV order `export_wav` writes, surrounds count 1.5 dB more and LFE not at all.
+/// Tracks shorter than a window are measured as one window over their whole length, instead of coming out silent.
+pub fn measure_loudness<const N: usize>(track: &AudioTrack<N>) -> LoudnessMeasurement {
+	let rate = track.sample_rate;
+	let weights = channel_weights(N);
+	let step = ((BLOCK_STEP * rate as f64).round() as usize).max(1);
+	let steps_per_block = (MOMENTARY_WINDOW / BLOCK_STEP).round() as usize;
+	let steps_per_short_term = (SHORT_TERM_WINDOW / BLOCK_STEP).round() as usize;
+	
+	// Weighted sum over channels of the K-weighted energy in each 100 ms step, the windows are built from these
+	let num_steps = track.length().div_ceil(step);
+	let mut step_energy = vec![0.0; num_steps];
+	for (channel, &weight) in track.data.iter().zip(&weights) {
+		if weight == 0.0 { continue }
+		let mut filter = KWeighting::new(rate);
+		for (i, &sample) in channel.iter().enumerate() {
+			let filtered = filter.process(sample as f64);
+			step_energy[i / step] += weight * filtered * filtered;
+		}
+	}
+	
+	let window_energies = |steps_per_window: usize| -> Vec<f64> {
+		if num_steps < steps_per_window {
+			return vec![step_energy.iter().sum::<f64>() / track.length().max(1) as f64]
+		}
+		step_energy.windows(steps_per_window).map(|window| window.iter().sum::<f64>() / (steps_per_window * step) as f64).collect()
+	};
+	
+	let blocks = window_energies(steps_per_block);
+	let above_absolute = blocks.iter().copied().filter(|&energy| loudness(energy) > ABSOLUTE_GATE).collect::<Vec<_>>();
+	let integrated = if above_absolute.is_empty() {
+		f64::NEG_INFINITY
+	} else {
+		let relative_gate = loudness(mean(&above_absolute)) + RELATIVE_GATE;
+		let gated = above_absolute.into_iter().filter(|&energy| loudness(energy) > relative_gate).collect::<Vec<_>>();
+		loudness(mean(&gated))
+	};
+	
+	let short_term_max = window_energies(steps_per_short_term).into_iter().map(loudness).fold(f64::NEG_INFINITY, f64::max);
+	let true_peak = (0..N).map(|c| true_peak(&track.data[c])).fold(0.0, f32::max);
+	
+	LoudnessMeasurement { integrated, short_term_max, true_peak: 20.0 * (true_peak as f64).log10() }
+}
+
+/// Writes a track with the gain that brings it to a loudness target, and reports the loudness before and after
+pub fn export_normalized<P, const N: usize>(track: &AudioTrack<N>, path: P, settings: &ProjectSettings, options: &ExportOptions, target: LoudnessTarget) -> Result<ExportReport, ExportError> where P: AsRef<Path> {
+	let measured = measure_loudness(track);
+	let (gain_db, peak_limited) = target.gain(&measured);
+	
+	let gain = 10f64.powf(gain_db / 20.0) as f32;
+	let mut normalized = track.clone();
+	for channel in normalized.data.iter_mut() {
+		for sample in channel.iter_mut() { *sample *= gain; }
+	}
+	
+	let mut report = export_track(&normalized, path, settings, options)?;
+	report.loudness = Some(LoudnessReport { target, measured, gain_db, normalized: measured.with_gain(gain_db), peak_limited });
+	Ok(report)
+}
+
+
+
+/// The two stage pre-filter of BS.1770: a high shelf for the head's acoustic effect, then a high pass.
+/// Coefficients are worked out for the rate the way libebur128 does, so rates other than 48 kHz get the same curve.
+struct KWeighting {
+	stages: [Biquad; 2],
+}
+
+impl KWeighting {
+	fn new(rate: u32) -> Self {
+		let rate = rate as f64;
+		
+		let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+		let k = (std::f64::consts::PI * f0 / rate).tan();
+		let vh = 10f64.powf(gain / 20.0);
+		let vb = vh.powf(0.4996667741545416);
+		let a0 = 1.0 + k / q + k * k;
+		let shelf = Biquad::new(
+			[(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+			[2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+		);
+		
+		let (f0, q) = (38.13547087602444, 0.5003270373238773);
+		let k = (std::f64::consts::PI * f0 / rate).tan();
+		let a0 = 1.0 + k / q + k * k;
+		let high_pass = Biquad::new([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+		
+		Self { stages: [shelf, high_pass] }
+	}
+	
+	fn process(&mut self, sample: f64) -> f64 {
+		self.stages.iter_mut().fold(sample, |sample, stage| stage.process(sample))
+	}
+}
+
+/// Direct form II transposed, `a0` normalized to 1
+struct Biquad {
+	b: [f64; 3],
+	a: [f64; 2],
+	state: [f64; 2],
+}
+
+impl Biquad {
+	fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+		Self { b, a, state: [0.0; 2] }
+	}
+	
+	fn process(&mut self, x: f64) -> f64 {
+		let y = self.b[0] * x + self.state[0];
+		self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+		self.state[1] = self.b[2] * x - self.a[1] * y;
+		y
+	}
+}
+
+
+/// Highest absolute value of the signal interpolated at 4 times the rate with a windowed sinc, or of the samples themselves if that's higher
+fn true_peak(samples: &[f32]) -> f32 {
+	let taps = TRUE_PEAK_OVERSAMPLING * TRUE_PEAK_TAPS_PER_PHASE;
+	let center = taps / 2;
+	// Blackman windowed sinc cut off at the original Nyquist. Phase 0 hits the sinc's zeros, so it passes the samples through unchanged.
+	let filter = (0..taps).map(|n| {
+		let t = (n as f64 - center as f64) / TRUE_PEAK_OVERSAMPLING as f64;
+		let sinc = if t == 0.0 {1.0} else {(std::f64::consts::PI * t).sin() / (std::f64::consts::PI * t)};
+		let x = n as f64 / taps as f64;
+		let window = 0.42 - 0.5 * (2.0 * std::f64::consts::PI * x).cos() + 0.08 * (4.0 * std::f64::consts::PI * x).cos();
+		(sinc * window) as f32
+	}).collect::<Vec<_>>();
+	
+	let mut peak = samples.iter().fold(0.0, |peak: f32, sample| peak.max(sample.abs()));
+	// Output frame i * 4 + phase sits between samples, and is the sum over input samples j of x[j] * h[(i - j) * 4 + phase + center]
+	for i in 0..(samples.len() + TRUE_PEAK_TAPS_PER_PHASE / 2) {
+		for phase in 1..TRUE_PEAK_OVERSAMPLING {
+			let mut sum = 0.0;
+			for k in 0..TRUE_PEAK_TAPS_PER_PHASE {
+				let Some(j) = (i + TRUE_PEAK_TAPS_PER_PHASE / 2).checked_sub(k) else { break };
+				let Some(&sample) = samples.get(j) else { continue };
+				sum += sample * filter[k * TRUE_PEAK_OVERSAMPLING + phase];
+			}
+			peak = peak.max(sum.abs());
+		}
+	}
+	peak
+}
+
+
+/// BS.1770 channel weights for the WAV speaker layouts
+fn channel_weights(channels: usize) -> Vec<f64> {
+	const SURROUND: f64 = 1.41;
+	match channels {
+		4 => vec![1.0, 1.0, SURROUND, SURROUND],
+		5 => vec![1.0, 1.0, 1.0, SURROUND, SURROUND],
+		6 => vec![1.0, 1.0, 1.0, 0.0, SURROUND, SURROUND],
+		8 => vec![1.0, 1.0, 1.0, 0.0, SURROUND, SURROUND, SURROUND, SURROUND],
+		_ => vec![1.0; channels],
+	}
+}
+
+fn loudness(energy: f64) -> f64 {
+	-0.691 + 10.0 * energy.log10()
+}
+
+fn mean(values: &[f64]) -> f64 {
+	values.iter().sum::<f64>() / values.len() as f64
+}
@@ -1,18 +1,9 @@
-#[allow(dead_code)] mod track; use track::*;
-#[allow(dead_code)] mod load; use load::*;
-#[allow(dead_code)] mod player; use player::*;
-#[allow(dead_code)] mod filter; use filter::*;
-
-use std::sync::Arc;
+use std::{process::ExitCode, sync::Arc};
 use cpal::traits::{HostTrait, DeviceTrait, StreamTrait};
-use symphonia::core::{audio::{AudioBufferRef, Signal}, codecs::DecoderOptions, conv::IntoSample, formats::FormatOptions, io::{MediaSourceStream, MediaSourceStreamOptions}, meta::MetadataOptions, probe::Hint};
-use rubato::Resampler;
 use wgpu::{Device, Queue, Surface, SurfaceConfiguration};
 use winit::{application::ApplicationHandler, dpi::{PhysicalPosition, PhysicalSize}, event::{ElementState, KeyEvent, WindowEvent}, event_loop::ControlFlow, keyboard::{KeyCode, PhysicalKey}, window::Window};
 
-
-
-const RESAMPLER_BLOCK_SIZE: usize = 1024;
+use sfx_daw::*;
 
 
 pub struct WindowState<'a> {
@@ -141,22 +132,59 @@ impl<'a> WindowState<'a> {
 
 pub struct App<'a> {
 	pub window_state: Option<(Arc<Window>, WindowState<'a>)>,
+	pub settings: ProjectSettings,
 	pub audio_player: AudioPlayer,
+	pub load_jobs: Vec<LoadJob>,
+	pub cache: Option<Arc<AudioCache>>,
 	pub mouse_pos: PhysicalPosition<f64>,
 }
 
 
 impl<'a> App<'a> {
 	pub fn new() -> Self {
+		let settings = ProjectSettings::default();
 		let device = cpal::default_host().default_output_device().expect("No default device found");
-		let audio_player = AudioPlayer::new(device);
+		let audio_player = AudioPlayer::new(device, settings);
 		
 		Self {
 			window_state: None,
+			settings,
 			audio_player,
+			load_jobs: vec![],
+			// Loading still works without a cache, just slower
+			cache: AudioCache::new(std::env::temp_dir().join("sfx_daw/cache")).ok().map(Arc::new),
 			mouse_pos: PhysicalPosition { x: 0.0, y: 0.0 }
 		}
 	}
+	
+	/// Hands finished background loads to the player
+	pub fn poll_load_jobs(&mut self) {
+		for job in &self.load_jobs {
+			for progress in job.progress() {
+				if let LoadStage::Failed(message) = progress.stage {
+					eprintln!("Failed to load file {}: {message}", progress.index);
+				}
+			}
+		}
+		
+		let (finished, pending) = std::mem::take(&mut self.load_jobs).into_iter().partition::<Vec<_>, _>(LoadJob::is_finished);
+		self.load_jobs = pending;
+		
+		for job in finished {
+			for loaded in job.join().into_iter().flatten() {
+				if let Err(e) = self.audio_player.add_track(loaded.track) {
+					eprintln!("Failed to add track: {e}");
+				}
+			}
+		}
+	}
+	
+	/// Streams that failed while playing have already stopped, this says why
+	pub fn report_player_errors(&self) {
+		for PlayerError { index, error } in self.audio_player.errors() {
+			eprintln!("Playback of track {index} stopped: {error}");
+		}
+	}
 }
 
 
@@ -176,6 +204,11 @@ impl<'a> ApplicationHandler for App<'a> {
 		
 		
 		
+	}
+	
+	fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+		self.poll_load_jobs();
+		self.report_player_errors();
 	}
 	
 	fn window_event(
@@ -198,10 +231,23 @@ impl<'a> ApplicationHandler for App<'a> {
 			
 			WindowEvent::KeyboardInput { event: KeyEvent { physical_key: PhysicalKey::Code(key_code), state, repeat: _, .. }, .. } => match key_code {
 				KeyCode::Escape => if state.is_pressed() { event_loop.exit() }
+				KeyCode::Backspace => if state.is_pressed() { for job in &self.load_jobs { job.cancel() } }
 				KeyCode::Space => if state.is_pressed() { println!("space") }
 				_ => ()
 			}
 			
+			WindowEvent::DroppedFile(path) => {
+				// A dropped folder loads every file directly inside it, in name order
+				let files = if path.is_dir() {
+					let mut files = std::fs::read_dir(&path).into_iter().flatten().flatten().map(|entry| entry.path()).filter(|path| path.is_file()).collect::<Vec<_>>();
+					files.sort();
+					files
+				} else {
+					vec![path]
+				};
+				self.load_jobs.push(LoadJob::spawn(files.into_iter().map(|path| (path, LoadOptions::default())).collect(), self.settings, self.cache.clone()));
+			}
+			
 			WindowEvent::CursorMoved { position, device_id: _ } => {
 				self.mouse_pos = position;
 			}
@@ -263,7 +309,10 @@ impl<'a> ApplicationHandler for App<'a> {
 
 
 #[allow(deprecated)]
-fn main() {
+fn main() -> ExitCode {
+	// Subcommands render offline and exit before anything opens a window or an audio device
+	let args = std::env::args().collect::<Vec<_>>();
+	if let Some(exit_code) = run_cli(&args) { return exit_code }
 	
 	let event_loop = winit::event_loop::EventLoop::builder().build().unwrap();
 	event_loop.set_control_flow(ControlFlow::Poll);
@@ -279,18 +328,18 @@ fn main() {
 	// 		files.push(entry.path());
 	// 	}
 	// }
-	// let audio = load_audio(&files).unwrap();
+	// let audio = load_audio(&files, &app.settings).unwrap();
 	
 	
 	// let audio = load_audio(&[
 	// 	std::env::home_dir().unwrap().join("OneDrive/Music/cd/Coldplay/Viva La Vida Or Death And All His Friends/01 Life In Technicolor.flac"),
 	// 	std::env::home_dir().unwrap().join("OneDrive/Music/cd/Coldplay/Viva La Vida Or Death And All His Friends/02 Cemeteries of London.flac"),
-	// ]).unwrap();
+	// ], &app.settings).unwrap();
 	
 	// let audio = load_audio(&[
 	// 	std::env::home_dir().unwrap().join("OneDrive/Music/cd/Pierce The Veil/Collide With The Sky/01 May These Noises Startle You In Your Sleep Tonight.flac"),
 	// 	std::env::home_dir().unwrap().join("OneDrive/Music/cd/Pierce The Veil/Collide With The Sky/02 Hell Above.flac"),
-	// ]).unwrap();
+	// ], &app.settings).unwrap();
 	
 	// let filtered_audio = test_filter(&audio);
 	
@@ -305,4 +354,5 @@ fn main() {
 	
 	
 	event_loop.run_app(&mut app).unwrap();
+	ExitCode::SUCCESS
 }
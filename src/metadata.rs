@@ -0,0 +1,566 @@
+use std::{collections::HashMap, io::{Read, Seek, SeekFrom}, ops::Range};
+
+use symphonia::core::meta::{Metadata, MetadataRevision, StandardTagKey};
+
+
+/// Chunks bigger than this are skipped instead of read, no metadata chunk legitimately gets close
+pub const MAX_METADATA_CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+/// Vorbis comment that holds a `Provenance`, and the RIFF INFO id used for it in WAV files
+const PROVENANCE_COMMENT: &str = "PROVENANCE";
+const PROVENANCE_INFO_ID: &str = "IPRV";
+/// Vorbis comment for a marker, `<frame> <label>` or `<frame>+<length> <label>`
+const MARKER_COMMENT: &str = "MARKER";
+/// Standard tags with their Vorbis comment name and RIFF INFO id
+const TAG_NAMES: [(StandardTagKey, &str, Option<&str>); 14] = [
+	(StandardTagKey::TrackTitle, "TITLE", Some("INAM")),
+	(StandardTagKey::Artist, "ARTIST", Some("IART")),
+	(StandardTagKey::Album, "ALBUM", Some("IPRD")),
+	(StandardTagKey::Comment, "COMMENT", Some("ICMT")),
+	(StandardTagKey::Copyright, "COPYRIGHT", Some("ICOP")),
+	(StandardTagKey::Date, "DATE", Some("ICRD")),
+	(StandardTagKey::Genre, "GENRE", Some("IGNR")),
+	(StandardTagKey::Encoder, "ENCODER", Some("ISFT")),
+	(StandardTagKey::EncodedBy, "ENCODED-BY", Some("IENC")),
+	(StandardTagKey::Composer, "COMPOSER", Some("IMUS")),
+	(StandardTagKey::Engineer, "ENGINEER", Some("IENG")),
+	(StandardTagKey::TrackNumber, "TRACKNUMBER", Some("IPRT")),
+	(StandardTagKey::Language, "LANGUAGE", Some("ILNG")),
+	(StandardTagKey::Description, "DESCRIPTION", None),
+];
+
+
+/// Tags, markers and loops of a source. Positions are in frames of the track they belong to:
+/// source frames while decoding, resampled frames once loaded.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackMetadata {
+	pub tags: Vec<MetadataTag>,
+	pub markers: Vec<Marker>,
+	pub loops: Vec<LoopRegion>,
+	/// MIDI note the sample plays at its original pitch, from the WAV `smpl` chunk
+	pub root_note: Option<u8>,
+	pub broadcast: Option<BroadcastInfo>,
+	/// What produced the track, for renders
+	pub provenance: Option<Provenance>,
+}
+
+impl TrackMetadata {
+	/// Converts positions from source frames to frames at another rate
+	pub fn rescaled(&self, ratio: f64) -> Self {
+		let rescale = |frame: usize| (frame as f64 * ratio).round() as usize;
+		Self {
+			markers: self.markers.iter().map(|marker| Marker {
+				frame: rescale(marker.frame),
+				length: marker.length.map(rescale),
+				..marker.clone()
+			}).collect(),
+			loops: self.loops.iter().map(|loop_region| LoopRegion {
+				start: rescale(loop_region.start),
+				end: rescale(loop_region.end),
+				..loop_region.clone()
+			}).collect(),
+			broadcast: self.broadcast.as_ref().map(|broadcast| BroadcastInfo {
+				time_reference: (broadcast.time_reference as f64 * ratio).round() as u64,
+				..broadcast.clone()
+			}),
+			..self.clone()
+		}
+	}
+	
+	/// Keeps the markers and loops inside `frames` and moves them so the region starts at frame 0
+	pub fn region(&self, frames: Range<usize>) -> Self {
+		Self {
+			markers: self.markers.iter().filter(|marker| frames.contains(&marker.frame)).map(|marker| Marker {
+				frame: marker.frame - frames.start,
+				..marker.clone()
+			}).collect(),
+			loops: self.loops.iter().filter(|loop_region| loop_region.start >= frames.start && loop_region.end <= frames.end).map(|loop_region| LoopRegion {
+				start: loop_region.start - frames.start,
+				end: loop_region.end - frames.start,
+				..loop_region.clone()
+			}).collect(),
+			broadcast: self.broadcast.as_ref().map(|broadcast| BroadcastInfo {
+				time_reference: broadcast.time_reference + frames.start as u64,
+				..broadcast.clone()
+			}),
+			..self.clone()
+		}
+	}
+	
+	/// First tag with the given standard key
+	pub fn tag(&self, key: StandardTagKey) -> Option<&str> {
+		self.tags.iter().find(|tag| tag.std_key == Some(key)).map(|tag| tag.value.as_str())
+	}
+	
+	/// Description from the `bext` chunk, or else from the tags
+	pub fn description(&self) -> Option<&str> {
+		self.broadcast.as_ref().map(|broadcast| broadcast.description.as_str()).filter(|description| !description.is_empty())
+			.or_else(|| self.tag(StandardTagKey::Description))
+	}
+	
+	/// Replaces any tags with the same standard key
+	pub fn set_tag(&mut self, std_key: StandardTagKey, key: &str, value: &str) {
+		self.tags.retain(|tag| tag.std_key != Some(std_key));
+		self.tags.push(MetadataTag { key: key.into(), std_key: Some(std_key), value: value.into() });
+	}
+	
+	/// Adds the tags of a metadata revision read by symphonia (ID3, Vorbis comments, RIFF INFO, MP4 atoms, ...)
+	pub fn add_tags(&mut self, revision: &MetadataRevision) {
+		for tag in revision.tags() {
+			// RIFF INFO strings keep their zero terminator
+			let tag = MetadataTag { key: tag.key.clone(), std_key: tag.std_key, value: tag.value.to_string().trim_end_matches('\0').to_string() };
+			if tag.key.eq_ignore_ascii_case(PROVENANCE_COMMENT) || tag.key == PROVENANCE_INFO_ID {
+				self.provenance = Some(Provenance::parse(&tag.value));
+				continue
+			}
+			if !self.tags.contains(&tag) { self.tags.push(tag); }
+		}
+	}
+	
+	/// Adds the tags of every revision in a format reader's metadata log
+	pub fn add_all_tags(&mut self, mut metadata: Metadata) {
+		while let Some(revision) = metadata.pop() { self.add_tags(&revision); }
+		if let Some(revision) = metadata.current() { self.add_tags(revision); }
+	}
+	
+	/// Picks up the `LOOPSTART`/`LOOPLENGTH`/`LOOPEND` comments that game audio tools write into Vorbis and ID3 tags
+	pub fn add_loops_from_tags(&mut self) {
+		let sample_tag = |key: &str| self.tags.iter().find(|tag| tag.key.eq_ignore_ascii_case(key)).and_then(|tag| tag.value.trim().parse::<usize>().ok());
+		
+		let Some(start) = sample_tag("LOOPSTART") else { return };
+		let end = match (sample_tag("LOOPEND"), sample_tag("LOOPLENGTH")) {
+			(Some(end), _) => end,
+			(None, Some(length)) => start + length,
+			(None, None) => return,
+		};
+		
+		if end > start && !self.loops.iter().any(|loop_region| loop_region.start == start && loop_region.end == end) {
+			self.loops.push(LoopRegion { cue_id: None, start, end, kind: LoopKind::Forward, play_count: 0 });
+		}
+	}
+	
+	/// Picks up the `MARKER` comments written by `vorbis_comments`
+	pub fn add_markers_from_tags(&mut self) {
+		let markers = self.tags.iter()
+			.filter(|tag| tag.key.eq_ignore_ascii_case(MARKER_COMMENT))
+			.filter_map(|tag| {
+				let (position, label) = tag.value.split_once(' ').unwrap_or((&tag.value, ""));
+				let (frame, length) = match position.split_once('+') {
+					Some((frame, length)) => (frame.parse().ok()?, Some(length.parse().ok()?)),
+					None => (position.parse().ok()?, None),
+				};
+				Some((frame, length, (!label.is_empty()).then(|| label.to_string())))
+			})
+			.collect::<Vec<_>>();
+		
+		for (frame, length, label) in markers {
+			if self.markers.iter().any(|marker| marker.frame == frame && marker.label == label) { continue }
+			let cue_id = self.markers.iter().map(|marker| marker.cue_id).max().unwrap_or(0) + 1;
+			self.markers.push(Marker { cue_id, frame, label, length });
+		}
+	}
+	
+	/// Tags, markers, the first loop, the description and provenance as Vorbis comments, for FLAC, Vorbis and Opus.
+	/// Positions are multiplied by `ratio`, Opus counts them at 48 kHz whatever the track's rate.
+	pub fn vorbis_comments(&self, ratio: f64) -> Vec<(String, String)> {
+		let rescale = |frame: usize| (frame as f64 * ratio).round() as usize;
+		let mut comments = vec![];
+		
+		for tag in &self.tags {
+			// These are written from the fields below
+			if [MARKER_COMMENT, "LOOPSTART", "LOOPEND", "LOOPLENGTH"].iter().any(|key| tag.key.eq_ignore_ascii_case(key)) { continue }
+			let name = TAG_NAMES.iter().find(|(std_key, ..)| tag.std_key == Some(*std_key)).map_or(tag.key.as_str(), |(_, name, _)| name);
+			comments.push((name.to_string(), tag.value.clone()));
+		}
+		if let Some(description) = self.description().filter(|_| self.tag(StandardTagKey::Description).is_none()) {
+			comments.push(("DESCRIPTION".into(), description.into()));
+		}
+		
+		for marker in &self.markers {
+			let position = match marker.length {
+				Some(length) => format!("{}+{}", rescale(marker.frame), rescale(length)),
+				None => rescale(marker.frame).to_string(),
+			};
+			comments.push((MARKER_COMMENT.into(), format!("{position} {}", marker.label.as_deref().unwrap_or_default()).trim_end().to_string()));
+		}
+		
+		// There's no convention for more than one loop in comments. Empty loops can't be played and are left out.
+		if let Some(loop_region) = self.loops.iter().find(|loop_region| rescale(loop_region.end) > rescale(loop_region.start)) {
+			comments.push(("LOOPSTART".into(), rescale(loop_region.start).to_string()));
+			comments.push(("LOOPLENGTH".into(), (rescale(loop_region.end) - rescale(loop_region.start)).to_string()));
+		}
+		
+		if let Some(provenance) = &self.provenance { comments.push((PROVENANCE_COMMENT.into(), provenance.to_text())); }
+		comments
+	}
+	
+	/// WAV chunks for everything but the samples: `LIST`/`INFO` tags and provenance, `bext` with the description,
+	/// `cue ` markers with their `LIST`/`adtl` labels and region lengths, and `smpl` loops with the root note.
+	/// Meant to go before the `data` chunk, readers that stop at the samples (symphonia among them) still see them there.
+	pub fn riff_chunks(&self, rate: u32) -> Vec<([u8; 4], Vec<u8>)> {
+		let mut chunks = vec![];
+		
+		let mut info = b"INFO".to_vec();
+		for tag in &self.tags {
+			let id = match TAG_NAMES.iter().find(|(std_key, ..)| tag.std_key == Some(*std_key)) {
+				Some((_, _, id)) => *id,
+				// INFO ids without a standard key are kept as read
+				None => (tag.key.len() == 4 && tag.key.starts_with('I') && tag.key.bytes().all(|byte| byte.is_ascii_alphanumeric())).then_some(tag.key.as_str()),
+			};
+			if let Some(id) = id { write_sub_chunk(&mut info, id.as_bytes(), &zero_terminated(&tag.value)); }
+		}
+		if let Some(provenance) = &self.provenance {
+			write_sub_chunk(&mut info, PROVENANCE_INFO_ID.as_bytes(), &zero_terminated(&provenance.to_text()));
+		}
+		if info.len() > 4 { chunks.push((*b"LIST", info)); }
+		
+		let description = self.description().unwrap_or_default();
+		if self.broadcast.is_some() || !description.is_empty() {
+			let broadcast = BroadcastInfo { description: description.into(), ..self.broadcast.clone().unwrap_or_default() };
+			chunks.push((*b"bext", write_bext(&broadcast)));
+		}
+		
+		if !self.markers.is_empty() {
+			let mut cue = (self.markers.len() as u32).to_le_bytes().to_vec();
+			let mut adtl = b"adtl".to_vec();
+			for marker in &self.markers {
+				let frame = marker.frame.min(u32::MAX as usize) as u32;
+				cue.extend_from_slice(&marker.cue_id.to_le_bytes());
+				// Play order position, data chunk id, chunk start and block start, then the sample offset
+				cue.extend_from_slice(&frame.to_le_bytes());
+				cue.extend_from_slice(b"data");
+				cue.extend_from_slice(&[0; 8]);
+				cue.extend_from_slice(&frame.to_le_bytes());
+				
+				if let Some(label) = &marker.label {
+					let mut labl = marker.cue_id.to_le_bytes().to_vec();
+					labl.extend_from_slice(&zero_terminated(label));
+					write_sub_chunk(&mut adtl, b"labl", &labl);
+				}
+				if let Some(length) = marker.length {
+					let mut ltxt = marker.cue_id.to_le_bytes().to_vec();
+					ltxt.extend_from_slice(&(length.min(u32::MAX as usize) as u32).to_le_bytes());
+					// Purpose, then country, language, dialect and code page left unset
+					ltxt.extend_from_slice(b"rgn ");
+					ltxt.extend_from_slice(&[0; 8]);
+					write_sub_chunk(&mut adtl, b"ltxt", &ltxt);
+				}
+			}
+			chunks.push((*b"cue ", cue));
+			if adtl.len() > 4 { chunks.push((*b"LIST", adtl)); }
+		}
+		
+		// Empty loops can't be played, and the smpl chunk can't store them
+		let loops = self.loops.iter().filter(|loop_region| loop_region.end > loop_region.start).collect::<Vec<_>>();
+		if !loops.is_empty() || self.root_note.is_some() {
+			let mut smpl = vec![];
+			// Manufacturer and product
+			smpl.extend_from_slice(&[0; 8]);
+			smpl.extend_from_slice(&(1_000_000_000 / rate.max(1)).to_le_bytes());
+			smpl.extend_from_slice(&(self.root_note.unwrap_or(60) as u32).to_le_bytes());
+			// Pitch fraction, SMPTE format and offset
+			smpl.extend_from_slice(&[0; 12]);
+			smpl.extend_from_slice(&(loops.len() as u32).to_le_bytes());
+			// No sampler data after the loops
+			smpl.extend_from_slice(&0u32.to_le_bytes());
+			for loop_region in loops {
+				smpl.extend_from_slice(&loop_region.cue_id.unwrap_or(0).to_le_bytes());
+				smpl.extend_from_slice(&(match loop_region.kind {
+					LoopKind::Forward => 0u32,
+					LoopKind::PingPong => 1,
+					LoopKind::Backward => 2,
+				}).to_le_bytes());
+				smpl.extend_from_slice(&(loop_region.start.min(u32::MAX as usize) as u32).to_le_bytes());
+				smpl.extend_from_slice(&((loop_region.end - 1).min(u32::MAX as usize) as u32).to_le_bytes());
+				// Fraction
+				smpl.extend_from_slice(&0u32.to_le_bytes());
+				smpl.extend_from_slice(&loop_region.play_count.to_le_bytes());
+			}
+			chunks.push((*b"smpl", smpl));
+		}
+		
+		chunks
+	}
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetadataTag {
+	/// Key as written in the file, its meaning depends on the tag format
+	pub key: String,
+	pub std_key: Option<StandardTagKey>,
+	pub value: String,
+}
+
+/// Cue point, from the WAV `cue ` chunk with its `adtl` label if there is one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Marker {
+	pub cue_id: u32,
+	pub frame: usize,
+	pub label: Option<String>,
+	/// Length of the region starting at the marker, for cues with an `ltxt` entry
+	pub length: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoopKind {
+	#[default]
+	Forward,
+	PingPong,
+	Backward,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoopRegion {
+	/// Cue point the loop is attached to in a WAV `smpl` chunk
+	pub cue_id: Option<u32>,
+	pub start: usize,
+	/// Exclusive, unlike the `smpl` chunk which stores the last frame played
+	pub end: usize,
+	pub kind: LoopKind,
+	/// 0 loops forever
+	pub play_count: u32,
+}
+
+/// Broadcast Wave `bext` chunk
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BroadcastInfo {
+	pub description: String,
+	pub originator: String,
+	pub originator_reference: String,
+	/// yyyy-mm-dd
+	pub origination_date: String,
+	/// hh:mm:ss
+	pub origination_time: String,
+	/// Frames since midnight at the start of the file
+	pub time_reference: u64,
+	pub coding_history: String,
+}
+
+
+/// Project, graph and parameter values a track was rendered from, so an exported asset can be traced back to its session.
+/// Stored as `key=value` lines, parameters as `param.<name>=<value>`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Provenance {
+	pub project: String,
+	pub graph: String,
+	/// Name and value of every parameter of the render
+	pub parameters: Vec<(String, String)>,
+}
+
+impl Provenance {
+	pub fn to_text(&self) -> String {
+		let escape = |text: &str| text.replace('\\', "\\\\").replace('\n', "\\n");
+		let mut text = format!("project={}\ngraph={}", escape(&self.project), escape(&self.graph));
+		for (name, value) in &self.parameters {
+			text += &format!("\nparam.{}={}", escape(name).replace('=', "\\e"), escape(value));
+		}
+		text
+	}
+	
+	/// Reads what `to_text` wrote, ignoring lines it doesn't know
+	pub fn parse(text: &str) -> Self {
+		let unescape = |text: &str| {
+			let mut unescaped = String::with_capacity(text.len());
+			let mut chars = text.chars();
+			while let Some(c) = chars.next() {
+				if c != '\\' { unescaped.push(c); continue }
+				match chars.next() {
+					Some('n') => unescaped.push('\n'),
+					Some('e') => unescaped.push('='),
+					Some(c) => unescaped.push(c),
+					None => (),
+				}
+			}
+			unescaped
+		};
+		
+		let mut provenance = Self::default();
+		for line in text.lines() {
+			let Some((key, value)) = line.split_once('=') else { continue };
+			match key {
+				"project" => provenance.project = unescape(value),
+				"graph" => provenance.graph = unescape(value),
+				_ => if let Some(name) = key.strip_prefix("param.") { provenance.parameters.push((unescape(name), unescape(value))); },
+			}
+		}
+		provenance
+	}
+}
+
+
+
+/// Reads the WAV chunks symphonia skips: `smpl` loops, `cue ` markers with their `LIST`/`adtl` labels, and `bext`.
+/// Returns empty metadata for anything that isn't a RIFF or RF64 WAVE file.
+pub fn read_riff_metadata<R>(reader: &mut R) -> std::io::Result<TrackMetadata> where R: Read + Seek {
+	let mut metadata = TrackMetadata::default();
+	
+	reader.seek(SeekFrom::Start(0))?;
+	let mut header = [0; 12];
+	reader.read_exact(&mut header)?;
+	if !matches!(&header[0..4], b"RIFF" | b"RF64") || &header[8..12] != b"WAVE" { return Ok(metadata) }
+	
+	let mut labels = HashMap::new();
+	let mut lengths = HashMap::new();
+	let mut ds64_data_size = None;
+	
+	loop {
+		let mut chunk_header = [0; 8];
+		if reader.read_exact(&mut chunk_header).is_err() { break }
+		
+		let id = &chunk_header[0..4];
+		let mut size = u32_at(&chunk_header, 4) as u64;
+		// RF64 keeps the real size of the data chunk in ds64
+		if id == b"data" && size == u32::MAX as u64 {
+			let Some(data_size) = ds64_data_size else { break };
+			size = data_size;
+		}
+		
+		if matches!(id, b"smpl" | b"cue " | b"bext" | b"LIST" | b"ds64") && size <= MAX_METADATA_CHUNK_SIZE {
+			let mut body = vec![0; size as usize];
+			if reader.read_exact(&mut body).is_err() { break }
+			
+			match id {
+				b"ds64" if body.len() >= 16 => ds64_data_size = Some(u64_at(&body, 8)),
+				b"smpl" => read_smpl(&body, &mut metadata),
+				b"cue " => read_cue(&body, &mut metadata),
+				b"bext" => metadata.broadcast = read_bext(&body),
+				b"LIST" if body.starts_with(b"adtl") => read_adtl(&body[4..], &mut labels, &mut lengths),
+				_ => (),
+			}
+		} else {
+			reader.seek(SeekFrom::Current(size as i64))?;
+		}
+		
+		// Chunks are padded to an even size
+		if size % 2 == 1 { reader.seek(SeekFrom::Current(1))?; }
+	}
+	
+	for marker in &mut metadata.markers {
+		marker.label = labels.remove(&marker.cue_id);
+		marker.length = lengths.remove(&marker.cue_id);
+	}
+	
+	Ok(metadata)
+}
+
+
+fn read_smpl(body: &[u8], metadata: &mut TrackMetadata) {
+	if body.len() < 36 { return }
+	
+	let unity_note = u32_at(body, 12);
+	if unity_note < 128 { metadata.root_note = Some(unity_note as u8); }
+	
+	let num_loops = u32_at(body, 28) as usize;
+	for loop_data in body[36..].chunks_exact(24).take(num_loops) {
+		let start = u32_at(loop_data, 8) as usize;
+		let end = u32_at(loop_data, 12) as usize + 1;
+		if end <= start { continue }
+		
+		metadata.loops.push(LoopRegion {
+			cue_id: Some(u32_at(loop_data, 0)),
+			start,
+			end,
+			kind: match u32_at(loop_data, 4) {
+				1 => LoopKind::PingPong,
+				2 => LoopKind::Backward,
+				_ => LoopKind::Forward,
+			},
+			play_count: u32_at(loop_data, 20),
+		});
+	}
+}
+
+fn read_cue(body: &[u8], metadata: &mut TrackMetadata) {
+	if body.len() < 4 { return }
+	
+	let num_cues = u32_at(body, 0) as usize;
+	for cue in body[4..].chunks_exact(24).take(num_cues) {
+		metadata.markers.push(Marker {
+			cue_id: u32_at(cue, 0),
+			// Sample offset, relative to the start of the data chunk for PCM
+			frame: u32_at(cue, 20) as usize,
+			label: None,
+			length: None,
+		});
+	}
+}
+
+fn read_adtl(mut body: &[u8], labels: &mut HashMap<u32, String>, lengths: &mut HashMap<u32, usize>) {
+	while body.len() >= 8 {
+		let id = &body[0..4];
+		let size = (u32_at(body, 4) as usize).min(body.len() - 8);
+		let data = &body[8..(8 + size)];
+		
+		match id {
+			b"labl" if data.len() >= 4 => { labels.insert(u32_at(data, 0), fixed_string(&data[4..])); }
+			b"ltxt" if data.len() >= 8 => { lengths.insert(u32_at(data, 0), u32_at(data, 4) as usize); }
+			_ => (),
+		}
+		
+		body = &body[(8 + size + size % 2).min(body.len())..];
+	}
+}
+
+fn read_bext(body: &[u8]) -> Option<BroadcastInfo> {
+	if body.len() < 346 { return None }
+	
+	Some(BroadcastInfo {
+		description: fixed_string(&body[0..256]),
+		originator: fixed_string(&body[256..288]),
+		originator_reference: fixed_string(&body[288..320]),
+		origination_date: fixed_string(&body[320..330]),
+		origination_time: fixed_string(&body[330..338]),
+		time_reference: u64_at(body, 338),
+		// Version, UMID, loudness values and reserved bytes come before the coding history
+		coding_history: body.get(602..).map(fixed_string).unwrap_or_default(),
+	})
+}
+
+fn write_bext(broadcast: &BroadcastInfo) -> Vec<u8> {
+	let fixed = |text: &str, size: usize| {
+		let mut bytes = text.as_bytes()[..text.len().min(size)].to_vec();
+		bytes.resize(size, 0);
+		bytes
+	};
+	
+	let mut bext = fixed(&broadcast.description, 256);
+	bext.extend_from_slice(&fixed(&broadcast.originator, 32));
+	bext.extend_from_slice(&fixed(&broadcast.originator_reference, 32));
+	bext.extend_from_slice(&fixed(&broadcast.origination_date, 10));
+	bext.extend_from_slice(&fixed(&broadcast.origination_time, 8));
+	bext.extend_from_slice(&broadcast.time_reference.to_le_bytes());
+	// Version 1, then the UMID and reserved bytes left unset, without the version 2 loudness values
+	bext.extend_from_slice(&1u16.to_le_bytes());
+	bext.resize(602, 0);
+	bext.extend_from_slice(broadcast.coding_history.as_bytes());
+	bext
+}
+
+/// Appends a chunk inside a `LIST`, padded to an even size
+fn write_sub_chunk(list: &mut Vec<u8>, id: &[u8], body: &[u8]) {
+	list.extend_from_slice(id);
+	list.extend_from_slice(&(body.len() as u32).to_le_bytes());
+	list.extend_from_slice(body);
+	if body.len() % 2 == 1 { list.push(0); }
+}
+
+fn zero_terminated(text: &str) -> Vec<u8> {
+	let mut bytes = text.as_bytes().to_vec();
+	bytes.push(0);
+	bytes
+}
+
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(bytes[offset..(offset + 4)].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+	u64::from_le_bytes(bytes[offset..(offset + 8)].try_into().unwrap())
+}
+
+/// Text field padded with zero bytes
+fn fixed_string(bytes: &[u8]) -> String {
+	let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+	String::from_utf8_lossy(&bytes[..end]).trim_end().to_string()
+}
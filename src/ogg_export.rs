@@ -0,0 +1,329 @@
+use std::{io::{Cursor, ErrorKind, Write}, num::{NonZeroU32, NonZeroU8}, path::Path};
+
+use ogg::{reading::PacketReader, writing::{PacketWriteEndInfo, PacketWriter}};
+use symphonia::default::formats::OggReader;
+use unsafe_libopus::{opus_multistream_encode_float, opus_multistream_encoder_ctl, opus_multistream_encoder_destroy, opus_multistream_surround_encoder_create, OpusMSEncoder};
+use vorbis_rs::{VorbisBitrateManagementStrategy, VorbisEncoderBuilder};
+
+use crate::*;
+
+
+/// Opus always runs at 48 kHz, other rates are resampled on export and decoders play back at 48 kHz too
+pub const OPUS_RATE: u32 = 48000;
+/// 20 ms, the frame size Opus is tuned for
+const OPUS_FRAME_SIZE: usize = 960;
+/// 5 ms, for sounds so short they'd otherwise fit in a single packet
+const OPUS_SHORT_FRAME_SIZE: usize = 240;
+/// One second of 20 ms packets per page
+const OPUS_PACKETS_PER_PAGE: usize = 50;
+/// Enough for a 20 ms frame of 8 channels at the highest bitrate
+const OPUS_MAX_PACKET_SIZE: usize = 8 * 1275;
+/// Frames handed to the Vorbis encoder at a time
+const VORBIS_BLOCK_SIZE: usize = 1024;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VorbisExportOptions {
+	/// -0.1 to 1.0, roughly 45 kbit/s to 500 kbit/s for stereo. 0.5 is around 160 kbit/s.
+	pub quality: f32,
+	pub clip_detection: ClipDetection,
+	/// Vorbis comments, key then value
+	pub comments: Vec<(String, String)>,
+	/// Tags, markers, the first loop, description and provenance, written as Vorbis comments before `comments`
+	pub metadata: TrackMetadata,
+	pub stream_serial: u32,
+}
+
+impl Default for VorbisExportOptions {
+	fn default() -> Self {
+		Self { quality: 0.5, clip_detection: ClipDetection::default(), comments: vec![], metadata: TrackMetadata::default(), stream_serial: 1 }
+	}
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpusExportOptions {
+	/// Target bitrate over all channels in bit/s, `None` lets the encoder pick one from the channel count
+	pub bitrate: Option<u32>,
+	/// 0 to 10, higher is slower and sounds better at the same bitrate
+	pub complexity: u8,
+	pub clip_detection: ClipDetection,
+	/// Written to the `OpusTags` header, key then value
+	pub comments: Vec<(String, String)>,
+	/// Like `comments`, with positions converted to 48 kHz frames as Opus counts them
+	pub metadata: TrackMetadata,
+	pub stream_serial: u32,
+}
+
+impl Default for OpusExportOptions {
+	fn default() -> Self {
+		Self { bitrate: None, complexity: 10, clip_detection: ClipDetection::default(), comments: vec![], metadata: TrackMetadata::default(), stream_serial: 1 }
+	}
+}
+
+
+
+/// Writes a track to an Ogg Vorbis file
+pub fn export_ogg_vorbis<P, const N: usize>(track: &AudioTrack<N>, path: P, settings: &ProjectSettings, options: &VorbisExportOptions) -> Result<ExportReport, ExportError> where P: AsRef<Path> {
+	write_file_atomically(path.as_ref(), |writer| write_ogg_vorbis(track, writer, settings, options))
+}
+
+/// Writes a track as an Ogg Vorbis stream at the project rate, resampled to it if it's at another.
+/// libvorbis sets the granule position of the last page to the exact frame count, so decoders trim the final block back to the track length.
+pub fn write_ogg_vorbis<W, const N: usize>(track: &AudioTrack<N>, writer: &mut W, settings: &ProjectSettings, options: &VorbisExportOptions) -> Result<ExportReport, ExportError> where W: Write {
+	let channel_order = vorbis_channel_order(N).ok_or_else(|| ExportError::Unsupported(format!("{N} channels in Vorbis")))?;
+	// Marker and loop positions are in the track's frames, so they move with the samples
+	let metadata = options.metadata.rescaled(settings.resample_ratio(track.sample_rate));
+	let track = &settings.at_project_rate(track).map_err(|e| ExportError::Encoder(e.to_string()))?;
+	let report = check_clipping(track, options.clip_detection)?;
+	
+	let mut builder = VorbisEncoderBuilder::new_with_serial(
+		NonZeroU32::new(settings.rate()).unwrap(),
+		NonZeroU8::new(N as u8).unwrap(),
+		Vec::new(),
+		options.stream_serial as i32,
+	);
+	builder.bitrate_management_strategy(VorbisBitrateManagementStrategy::QualityVbr { target_quality: options.quality.clamp(-0.1, 1.0) });
+	let comments = [metadata.vorbis_comments(1.0), options.comments.clone()].concat();
+	builder.comment_tags(comments.iter().map(|(key, value)| (key.as_str(), value.as_str())))?;
+	let mut encoder = builder.build()?;
+	
+	for block_start in (0..track.length()).step_by(VORBIS_BLOCK_SIZE) {
+		let block_end = (block_start + VORBIS_BLOCK_SIZE).min(track.length());
+		let block = channel_order.iter().map(|&c| &track.data[c][block_start..block_end]).collect::<Vec<_>>();
+		encoder.encode_audio_block(&block)?;
+	}
+	
+	repage_vorbis(encoder.finish()?, writer, options.stream_serial)?;
+	Ok(report)
+}
+
+/// libvorbis puts a short sound on a single page, and readers that look for encoder delay on the first page (symphonia does)
+/// then take the padding at the end for padding at the start. Rewrites the stream with the last packet on a page of its own,
+/// so every other page has an exact granule position and only the last one is cut short.
+fn repage_vorbis<W>(ogg: Vec<u8>, writer: &mut W, serial: u32) -> Result<(), ExportError> where W: Write {
+	// The mapper in symphonia's Ogg reader works out each packet's duration from its block size
+	let mut reader = OggReader::try_new(MediaSourceStream::new(Box::new(Cursor::new(ogg.clone())), Default::default()), &FormatOptions::default())
+		.map_err(|e| ExportError::Encoder(e.to_string()))?;
+	let mut durations = vec![];
+	loop {
+		match reader.next_packet() {
+			Ok(packet) => durations.push(packet.dur),
+			Err(SymphoniaError::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+			Err(e) => return Err(ExportError::Encoder(e.to_string())),
+		}
+	}
+	
+	let mut packet_reader = PacketReader::new(Cursor::new(ogg));
+	let mut packets = vec![];
+	while let Some(packet) = packet_reader.read_packet().map_err(|e| ExportError::Encoder(e.to_string()))? { packets.push(packet); }
+	// Identification, comment and setup headers come first
+	if packets.len() != durations.len() + 3 { return Err(ExportError::Encoder("vorbis packets don't match their durations".into())) }
+	let total_frames = packets.last().map_or(0, |packet| packet.absgp_page());
+	
+	let mut packet_writer = PacketWriter::new(writer);
+	let mut granule = 0;
+	for (i, packet) in packets.iter().enumerate() {
+		let end_info = if i + 1 == packets.len() {
+			PacketWriteEndInfo::EndStream
+		} else if i == 0 || i == 2 || i + 2 == packets.len() || packet.last_in_page() {
+			PacketWriteEndInfo::EndPage
+		} else {
+			PacketWriteEndInfo::NormalPacket
+		};
+		if i >= 3 { granule = (granule + durations[i - 3]).min(total_frames); }
+		let absgp = if i + 1 == packets.len() {total_frames} else {granule};
+		packet_writer.write_packet(&packet.data[..], serial, end_info, absgp)?;
+	}
+	
+	Ok(())
+}
+
+
+/// Writes a track to an Ogg Opus file
+pub fn export_ogg_opus<P, const N: usize>(track: &AudioTrack<N>, path: P, settings: &ProjectSettings, options: &OpusExportOptions) -> Result<ExportReport, ExportError> where P: AsRef<Path> {
+	write_file_atomically(path.as_ref(), |writer| write_ogg_opus(track, writer, settings, options))
+}
+
+/// Writes a track as an Ogg Opus stream, resampled to 48 kHz unless the track is already at that rate.
+/// The encoder's lookahead goes into the header as pre-skip and the last granule position marks the end of the track,
+/// so a gapless decoder gets back exactly the frames that went in. At other project rates the length comes back within a frame, from resampling both ways.
+pub fn write_ogg_opus<W, const N: usize>(track: &AudioTrack<N>, writer: &mut W, _settings: &ProjectSettings, options: &OpusExportOptions) -> Result<ExportReport, ExportError> where W: Write {
+	let channel_order = vorbis_channel_order(N).ok_or_else(|| ExportError::Unsupported(format!("{N} channels in Opus")))?;
+	let report = check_clipping(track, options.clip_detection)?;
+	
+	let samples = convert_sample_rate(track, OPUS_RATE).map_err(|e| ExportError::Encoder(e.to_string()))?;
+	let frames = samples.length();
+	
+	let encoder = OpusEncoder::new(N, options)?;
+	let pre_skip = encoder.lookahead;
+	
+	let serial = options.stream_serial;
+	let mut packet_writer = PacketWriter::new(writer);
+	// Both headers sit on pages of their own, with granule position 0
+	packet_writer.write_packet(opus_head(N, pre_skip, track.sample_rate, &encoder.mapping), serial, PacketWriteEndInfo::EndPage, 0)?;
+	let comments = [options.metadata.vorbis_comments(OPUS_RATE as f64 / track.sample_rate as f64), options.comments.clone()].concat();
+	packet_writer.write_packet(opus_tags(&comments), serial, PacketWriteEndInfo::EndPage, 0)?;
+	
+	// Input runs on past the track with silence until the lookahead has been flushed out.
+	// The last packet goes on a page of its own, so there are always at least two packets and readers can't mistake the end padding for a start delay.
+	let total_frames = pre_skip + frames;
+	let frame_size = if total_frames > OPUS_FRAME_SIZE {OPUS_FRAME_SIZE} else {OPUS_SHORT_FRAME_SIZE};
+	let num_packets = total_frames.div_ceil(frame_size);
+	let mut interleaved = vec![0.0; frame_size * N];
+	let mut packet = vec![0; OPUS_MAX_PACKET_SIZE];
+	
+	for i in 0..num_packets {
+		let start = i * frame_size;
+		interleaved.fill(0.0);
+		for frame in start..(start + frame_size).min(frames) {
+			for (j, &c) in channel_order.iter().enumerate() {
+				interleaved[(frame - start) * N + j] = samples.data[c][frame];
+			}
+		}
+		
+		let length = encoder.encode(&interleaved, frame_size, &mut packet)?;
+		let end_info = if i + 1 == num_packets {
+			PacketWriteEndInfo::EndStream
+		} else if i + 2 == num_packets || (i + 1) % OPUS_PACKETS_PER_PAGE == 0 {
+			PacketWriteEndInfo::EndPage
+		} else {
+			PacketWriteEndInfo::NormalPacket
+		};
+		// Granule positions count decoded frames including the pre-skip, the last one cuts off the padding
+		let granule = ((i + 1) * frame_size).min(total_frames) as u64;
+		packet_writer.write_packet(packet[..length].to_vec(), serial, end_info, granule)?;
+	}
+	
+	Ok(report)
+}
+
+
+/// Vorbis and Opus channel order as indices into WAV channel order, for the layouts both define
+pub fn vorbis_channel_order(channels: usize) -> Option<&'static [usize]> {
+	Some(match channels {
+		1 => &[0],
+		2 => &[0, 1],
+		3 => &[0, 2, 1],
+		4 => &[0, 1, 2, 3],
+		5 => &[0, 2, 1, 3, 4],
+		6 => &[0, 2, 1, 4, 5, 3],
+		7 => &[0, 2, 1, 5, 6, 4, 3],
+		8 => &[0, 2, 1, 6, 7, 4, 5, 3],
+		_ => return None,
+	})
+}
+
+
+/// Lossy encoders take floats over full scale as they are, so clipping is only reported, nothing gets clamped
+fn check_clipping<const N: usize>(track: &AudioTrack<N>, clip_detection: ClipDetection) -> Result<ExportReport, ExportError> {
+	let mut report = ExportReport::default();
+	for frame in 0..track.length() {
+		for c in 0..N {
+			let sample = track.data[c][frame];
+			report.record(frame, c, sample, sample.abs() > 1.0, clip_detection)?;
+		}
+	}
+	Ok(report)
+}
+
+/// `OpusHead` identification header, RFC 7845 section 5.1
+fn opus_head(channels: usize, pre_skip: usize, input_rate: u32, mapping: &OpusMapping) -> Vec<u8> {
+	let mut head = Vec::with_capacity(21 + channels);
+	head.extend_from_slice(b"OpusHead");
+	head.push(1);
+	head.push(channels as u8);
+	head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+	head.extend_from_slice(&input_rate.to_le_bytes());
+	// Output gain
+	head.extend_from_slice(&0u16.to_le_bytes());
+	head.push(mapping.family);
+	if mapping.family != 0 {
+		head.push(mapping.streams);
+		head.push(mapping.coupled_streams);
+		head.extend_from_slice(&mapping.table[..channels]);
+	}
+	head
+}
+
+/// `OpusTags` comment header, laid out like Vorbis comments
+fn opus_tags(comments: &[(String, String)]) -> Vec<u8> {
+	let vendor = format!("sfx_daw {}", unsafe_libopus::opus_get_version_string());
+	let mut tags = Vec::new();
+	tags.extend_from_slice(b"OpusTags");
+	tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+	tags.extend_from_slice(vendor.as_bytes());
+	tags.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+	for (key, value) in comments {
+		let comment = format!("{key}={value}");
+		tags.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+		tags.extend_from_slice(comment.as_bytes());
+	}
+	tags
+}
+
+
+/// How channels are split into coupled (stereo) and mono Opus streams
+struct OpusMapping {
+	/// 0 for mono and stereo, 1 for the Vorbis surround layouts
+	family: u8,
+	streams: u8,
+	coupled_streams: u8,
+	table: [u8; 8],
+}
+
+/// Owns a libopus multistream encoder
+struct OpusEncoder {
+	encoder: *mut OpusMSEncoder,
+	mapping: OpusMapping,
+	/// Frames of priming the encoder puts before the signal, the header's pre-skip
+	lookahead: usize,
+}
+
+impl OpusEncoder {
+	fn new(channels: usize, options: &OpusExportOptions) -> Result<Self, ExportError> {
+		let family = if channels <= 2 {0} else {1};
+		let mut streams = 0;
+		let mut coupled_streams = 0;
+		let mut table = [0; 8];
+		let mut error = 0;
+		
+		// SAFETY: the out pointers are valid and the mapping table has room for the 8 channels at most that get here
+		let encoder = unsafe { opus_multistream_surround_encoder_create(
+			OPUS_RATE as i32, channels as i32, family, &mut streams, &mut coupled_streams, table.as_mut_ptr(), unsafe_libopus::OPUS_APPLICATION_AUDIO, &mut error,
+		) };
+		if encoder.is_null() || error != unsafe_libopus::OPUS_OK { return Err(ExportError::Encoder(format!("opus encoder creation failed ({error})"))) }
+		
+		let mut opus_encoder = Self {
+			encoder,
+			mapping: OpusMapping { family: family as u8, streams: streams as u8, coupled_streams: coupled_streams as u8, table },
+			lookahead: 0,
+		};
+		
+		let mut lookahead = 0;
+		// SAFETY: the encoder was just created and each request gets the argument type it expects
+		unsafe {
+			if let Some(bitrate) = options.bitrate { opus_multistream_encoder_ctl!(encoder, unsafe_libopus::OPUS_SET_BITRATE_REQUEST, bitrate as i32); }
+			opus_multistream_encoder_ctl!(encoder, unsafe_libopus::OPUS_SET_COMPLEXITY_REQUEST, options.complexity.min(10) as i32);
+			opus_multistream_encoder_ctl!(encoder, unsafe_libopus::OPUS_GET_LOOKAHEAD_REQUEST, &mut lookahead);
+		}
+		opus_encoder.lookahead = lookahead as usize;
+		
+		Ok(opus_encoder)
+	}
+	
+	/// Encodes one frame of interleaved samples, returns the packet length
+	fn encode(&self, interleaved: &[f32], frame_size: usize, packet: &mut [u8]) -> Result<usize, ExportError> {
+		// SAFETY: `interleaved` holds `frame_size` frames of every channel the encoder was created with
+		let length = unsafe { opus_multistream_encode_float(self.encoder, interleaved.as_ptr(), frame_size as i32, packet.as_mut_ptr(), packet.len() as i32) };
+		if length < 0 { return Err(ExportError::Encoder(format!("opus encoding failed ({length})"))) }
+		Ok(length as usize)
+	}
+}
+
+impl Drop for OpusEncoder {
+	fn drop(&mut self) {
+		// SAFETY: created by `opus_multistream_surround_encoder_create` and only freed here
+		unsafe { opus_multistream_encoder_destroy(self.encoder) }
+	}
+}
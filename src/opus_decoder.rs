@@ -0,0 +1,116 @@
+use symphonia::core::{audio::{AsAudioBufferRef, SignalSpec}, codecs::{CodecDescriptor, CodecParameters, FinalizeResult, CODEC_TYPE_OPUS}, formats::Packet, support_codec};
+use unsafe_libopus::{opus_multistream_decode_float, opus_multistream_decoder_create, opus_multistream_decoder_ctl, opus_multistream_decoder_destroy, OpusMSDecoder};
+
+use crate::*;
+
+
+/// Longest packet Opus allows, 120 ms at 48 kHz
+const OPUS_MAX_PACKET_FRAMES: usize = 5760;
+
+
+/// Opus decoder for symphonia, which can read Ogg Opus but ships no decoder for it.
+/// symphonia leaves the header's pre-skip in the stream, so it's dropped here from the start of the first packets.
+pub struct OpusDecoder {
+	params: CodecParameters,
+	decoder: *mut OpusMSDecoder,
+	channels: usize,
+	/// Channels in the order libopus outputs them, as indices into symphonia's order
+	channel_order: &'static [usize],
+	interleaved: Vec<f32>,
+	buf: AudioBuffer<f32>,
+	/// Pre-skip frames still to drop
+	skip: usize,
+}
+
+// SAFETY: the libopus state is owned exclusively and only touched through `&mut self`
+unsafe impl Send for OpusDecoder {}
+unsafe impl Sync for OpusDecoder {}
+
+impl Decoder for OpusDecoder {
+	fn try_new(params: &CodecParameters, _options: &DecoderOptions) -> symphonia::core::errors::Result<Self> {
+		// The mapper passes the whole `OpusHead` packet along, RFC 7845 section 5.1
+		let head = params.extra_data.as_deref().unwrap_or_default();
+		if head.len() < 19 || &head[0..8] != b"OpusHead" { return Err(SymphoniaError::DecodeError("opus: missing identification header")) }
+		
+		let channels = head[9] as usize;
+		let gain = i16::from_le_bytes([head[16], head[17]]);
+		let (streams, coupled_streams, mapping) = match head[18] {
+			0 if channels <= 2 => (1, channels as u8 - 1, vec![0, 1]),
+			_ if head.len() >= 21 + channels => (head[19], head[20], head[21..(21 + channels)].to_vec()),
+			_ => return Err(SymphoniaError::DecodeError("opus: truncated channel mapping")),
+		};
+		let channel_order = vorbis_channel_order(channels).ok_or(SymphoniaError::Unsupported("opus: more than 8 channels"))?;
+		let spec_channels = params.channels.filter(|layout| layout.count() == channels).ok_or(SymphoniaError::Unsupported("opus: unknown channel layout"))?;
+		
+		let mut error = 0;
+		// SAFETY: the mapping table has an entry for every channel
+		let decoder = unsafe { opus_multistream_decoder_create(OPUS_RATE as i32, channels as i32, streams as i32, coupled_streams as i32, mapping.as_ptr(), &mut error) };
+		if decoder.is_null() || error != unsafe_libopus::OPUS_OK { return Err(SymphoniaError::DecodeError("opus: invalid stream layout")) }
+		// SAFETY: the decoder was just created and the request takes an i32
+		if gain != 0 { unsafe { opus_multistream_decoder_ctl!(decoder, unsafe_libopus::OPUS_SET_GAIN_REQUEST, gain as i32); } }
+		
+		Ok(Self {
+			params: params.clone(),
+			decoder,
+			channels,
+			channel_order,
+			interleaved: vec![0.0; OPUS_MAX_PACKET_FRAMES * channels],
+			buf: AudioBuffer::new(OPUS_MAX_PACKET_FRAMES as u64, SignalSpec::new(OPUS_RATE, spec_channels)),
+			skip: params.delay.unwrap_or(0) as usize,
+		})
+	}
+	
+	fn supported_codecs() -> &'static [CodecDescriptor] {
+		&[support_codec!(CODEC_TYPE_OPUS, "opus", "Opus")]
+	}
+	
+	fn reset(&mut self) {
+		// Seeks are offset by the pre-skip in the timestamps, the frames before the target are dropped by whoever seeked
+		self.skip = 0;
+		// SAFETY: the decoder is valid for the lifetime of self
+		unsafe { opus_multistream_decoder_ctl!(self.decoder, unsafe_libopus::OPUS_RESET_STATE); }
+	}
+	
+	fn codec_params(&self) -> &CodecParameters {
+		&self.params
+	}
+	
+	fn decode(&mut self, packet: &Packet) -> symphonia::core::errors::Result<AudioBufferRef<'_>> {
+		self.buf.clear();
+		
+		// SAFETY: the output buffer holds the longest packet Opus allows for every channel
+		let frames = unsafe { opus_multistream_decode_float(
+			self.decoder, packet.data.as_ptr(), packet.data.len() as i32, self.interleaved.as_mut_ptr(), OPUS_MAX_PACKET_FRAMES as i32, 0,
+		) };
+		if frames < 0 { return Err(SymphoniaError::DecodeError("opus: invalid packet")) }
+		let frames = frames as usize;
+		
+		self.buf.render_reserved(Some(frames));
+		for (j, &c) in self.channel_order.iter().enumerate() {
+			for (i, sample) in self.buf.chan_mut(c).iter_mut().enumerate() {
+				*sample = self.interleaved[i * self.channels + j];
+			}
+		}
+		
+		let skip = self.skip.min(frames);
+		self.skip -= skip;
+		self.buf.trim(packet.trim_start() as usize + skip, packet.trim_end() as usize);
+		
+		Ok(self.buf.as_audio_buffer_ref())
+	}
+	
+	fn finalize(&mut self) -> FinalizeResult {
+		FinalizeResult::default()
+	}
+	
+	fn last_decoded(&self) -> AudioBufferRef<'_> {
+		self.buf.as_audio_buffer_ref()
+	}
+}
+
+impl Drop for OpusDecoder {
+	fn drop(&mut self) {
+		// SAFETY: created by `opus_multistream_decoder_create` and only freed here
+		unsafe { opus_multistream_decoder_destroy(self.decoder) }
+	}
+}
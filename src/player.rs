@@ -1,4 +1,4 @@
-use std::{collections::VecDeque, sync::{Arc, RwLock}};
+use std::{collections::VecDeque, sync::{mpsc, Arc, RwLock}};
 
 use cpal::{Device, OutputCallbackInfo, SampleFormat, SampleRate, Stream};
 
@@ -14,16 +14,52 @@ pub struct PlaybackState {
 	pub queue: VecDeque<usize>,
 }
 
+/// Something the player can read frames from: either a fully loaded track or a stream decoded ahead on its own thread
+pub enum PlayerSource {
+	Track(AudioTrack<2>),
+	Stream(BufferedStream),
+}
+
+impl PlayerSource {
+	/// Writes interleaved stereo frames starting at `frame` into `data`, without blocking. Fewer frames than requested are written
+	/// once the source ends, or while a stream is still decoding.
+	pub fn read_interleaved(&self, frame: usize, data: &mut [f32]) -> FramesRead {
+		match self {
+			PlayerSource::Track(track) => {
+				let frames_now = (data.len() / 2).min(track.length().saturating_sub(frame));
+				let mut i = 0;
+				for f in 0..frames_now {
+					data[i]   = track.data[0][frame + f];
+					data[i|1] = track.data[1][frame + f];
+					i += 2;
+				}
+				FramesRead { frames: frames_now, ended: frame + frames_now >= track.length() }
+			}
+			PlayerSource::Stream(stream) => stream.read_interleaved(frame, data),
+		}
+	}
+}
+
+/// A stream that failed while playing, it stops as if it had ended
+#[derive(Debug)]
+pub struct PlayerError {
+	pub index: usize,
+	pub error: LoadError,
+}
+
 pub struct AudioPlayer {
 	stream: Stream,
-	pub tracks: Arc<RwLock<Vec<Option<AudioTrack<2>>>>>,
+	pub settings: ProjectSettings,
+	pub tracks: Arc<RwLock<Vec<Option<PlayerSource>>>>,
 	pub playback_state: Arc<RwLock<Option<PlaybackState>>>,
+	error_sender: mpsc::Sender<PlayerError>,
+	errors: mpsc::Receiver<PlayerError>,
 }
 
 
 
 impl AudioPlayer {
-	pub fn new(device: Device) -> Self {
+	pub fn new(device: Device, settings: ProjectSettings) -> Self {
 		let mut supported_configs_range = device.supported_output_configs().expect("No device configs found");
 		let mut config = None;
 		while let Some(c) = supported_configs_range.next() {
@@ -32,10 +68,10 @@ impl AudioPlayer {
 				break
 			}
 		}
-		let config = config.expect("No F32 sample format available").with_sample_rate(SampleRate(48000));
+		let config = config.expect("No F32 sample format available").with_sample_rate(SampleRate(settings.rate()));
 		
 		
-		let tracks: Arc<RwLock<Vec<Option<AudioTrack<2>>>>> = Arc::new(RwLock::new(vec![]));
+		let tracks: Arc<RwLock<Vec<Option<PlayerSource>>>> = Arc::new(RwLock::new(vec![]));
 		
 		let playback_state: Arc<RwLock<Option<PlaybackState>>> = Arc::new(RwLock::new(None));
 		
@@ -44,87 +80,69 @@ impl AudioPlayer {
 		
 		
 		let stream = device.build_output_stream(&config.into(), move |data: &mut [f32], _output_callback_info: &OutputCallbackInfo| {
-			let mut state_binding = playback_state_backend.write().unwrap();
+			let tracks_binding = tracks_backend.read().unwrap();
+			let mut i = 0;
 			
-			if let Some(state_params) = state_binding.as_mut() {
+			// Runs again for each queued track that starts within this buffer
+			loop {
+				// The state is only locked to read and update the position, not while frames are copied
+				let (index, frame) = match *playback_state_backend.read().unwrap() {
+					Some(PlaybackState { index, frame, playing: true, .. }) => (index, frame),
+					_ => break,
+				};
+				
+				let Some(Some(source)) = (*tracks_binding).get(index) else {
+					*playback_state_backend.write().unwrap() = None;
+					break
+				};
+				let read = source.read_interleaved(frame, &mut data[i..]);
+				i += read.frames * 2;
+				
+				let mut state_binding = playback_state_backend.write().unwrap();
+				let Some(state_params) = state_binding.as_mut() else { break };
+				// Seeked or switched to another track while the frames were copied, that takes over from the next buffer
+				if state_params.index != index || state_params.frame != frame { break }
+				
+				state_params.frame += read.frames;
+				if !read.ended { break }
 				
-				let tracks_binding = tracks_backend.read().unwrap();
-				if let Some(Some(track)) = (*tracks_binding).get(state_params.index) {
-					if state_params.playing {
-						
-						let current_frame = state_params.frame;
-						let frames_left = track.length().saturating_sub(current_frame);
-						let frames_now = data.len() / 2;
-						
-						if frames_left >= frames_now {
-							state_params.frame += frames_now;
-							drop(state_binding);
-							
-							let mut i = 0;
-							for frame in 0..frames_now {
-								for channel in 0..2 {
-									data[i] = track.data[channel][current_frame + frame];
-									i += 1;
-								}
-							}
-						} else {
-							let next_track = 
-							if let Some(next_index) = state_params.queue.pop_front() {
-								if let Some(Some(next_track)) = (*tracks_binding).get(next_index) {
-									state_params.index = next_index;
-									state_params.frame = frames_now - frames_left;
-									Some(next_track)
-								} else {
-									state_params.frame = 0;
-									state_params.playing = false;
-									None
-								}
-							} else {
-								state_params.frame = 0;
-								state_params.playing = false;
-								None
-							};
-							
-							drop(state_binding);
-							
-							let mut i = 0;
-							for frame in 0..frames_left {
-								data[i]   = track.data[0][current_frame + frame];
-								data[i|1] = track.data[1][current_frame + frame];
-								i += 2;
-							}
-							
-							if let Some(next_track) = next_track {
-								for frame in 0..(frames_now - frames_left) {
-									data[i]   = next_track.data[0][frame];
-									data[i|1] = next_track.data[1][frame];
-									i += 2;
-								}
-							}
-							
-							
-						}
-						
-						
+				match state_params.queue.pop_front() {
+					Some(next_index) if matches!((*tracks_binding).get(next_index), Some(Some(_))) => {
+						state_params.index = next_index;
+						state_params.frame = 0;
+					}
+					_ => {
+						state_params.frame = 0;
+						state_params.playing = false;
+						break
 					}
-					
-				} else {
-					*state_binding = None;
 				}
 			}
 			
-		}, move |err| { println!("{err}"); }, None).unwrap();
+			data[i..].fill(0.0);
+			
+		}, move |err| { eprintln!("{err}"); }, None).unwrap();
 		
 		
 		stream.play().unwrap();
 		
+		let (error_sender, errors) = mpsc::channel();
+		
 		Self {
 			stream,
+			settings,
 			tracks,
 			playback_state,
+			error_sender,
+			errors,
 		}
 	}
 	
+	/// Errors from streams since the last call, without blocking
+	pub fn errors(&self) -> impl Iterator<Item = PlayerError> + '_ {
+		self.errors.try_iter()
+	}
+	
 	pub fn resume_stream(&self) {
 		self.stream.play().unwrap()
 	}
@@ -133,37 +151,41 @@ impl AudioPlayer {
 		self.stream.pause().unwrap()
 	}
 	
-	pub fn add_track(&self, track: AudioTrack<2>) -> usize {
+	pub fn add_source(&self, source: PlayerSource) -> usize {
 		let mut tracks_binding = self.tracks.write().unwrap();
-		
-		for i in 0..tracks_binding.len() {
-			if tracks_binding[i].is_none() {
-				tracks_binding[i] = Some(track);
-				return i
-			}
-		}
-		
-		tracks_binding.push(Some(track));
-		return tracks_binding.len() - 1
+		let index = free_slot(&mut tracks_binding);
+		tracks_binding[index] = Some(source);
+		index
+	}
+	
+	/// Tracks at another rate than the project's are resampled to it
+	pub fn add_track(&self, track: AudioTrack<2>) -> Result<usize, LoadError> {
+		let track = if track.sample_rate == self.settings.rate() {track} else {convert_sample_rate(&track, self.settings.rate())?};
+		Ok(self.add_source(PlayerSource::Track(track)))
 	}
 	
-	pub fn add_tracks(&self, tracks: impl Iterator<Item = AudioTrack<2>>) -> Vec<usize> {
+	/// Decodes the stream on its own thread, a second ahead. Errors while playing it are reported through `errors`.
+	pub fn add_stream(&self, stream: AudioStream) -> usize {
+		let mut tracks_binding = self.tracks.write().unwrap();
+		let index = free_slot(&mut tracks_binding);
+		let error_sender = self.error_sender.clone();
+		let on_error = move |error| { let _ = error_sender.send(PlayerError { index, error }); };
+		tracks_binding[index] = Some(PlayerSource::Stream(BufferedStream::spawn(stream, self.settings.rate() as usize, on_error)));
+		index
+	}
+	
+	pub fn add_tracks(&self, tracks: impl Iterator<Item = AudioTrack<2>>) -> Result<Vec<usize>, LoadError> {
 		tracks.map(|track| self.add_track(track)).collect()
 	}
 	
 	pub fn remove_track(&self, index: usize) {
 		let mut tracks_binding = self.tracks.write().unwrap();
 		if index >= tracks_binding.len() { return }
-		tracks_binding[index] = None;
-		if index == tracks_binding.len() - 1 {
-			loop {
-				match tracks_binding.last() {
-					Some(None) => { tracks_binding.pop(); }
-					None => return,
-					_ => ()
-				}
-			}
-		}
+		let removed = tracks_binding[index].take();
+		while let Some(None) = tracks_binding.last() { tracks_binding.pop(); }
+		// Dropping a stream waits for its decoding thread, which mustn't hold up the audio callback
+		drop(tracks_binding);
+		drop(removed);
 	}
 	
 	pub fn play_track(&self, index: usize) {
@@ -195,7 +217,7 @@ impl AudioPlayer {
 	pub fn seek(&self, seconds: f64) {
 		let mut state_binding = self.playback_state.write().unwrap();
 		if let Some(PlaybackState { frame, .. }) = state_binding.as_mut() {
-			*frame = (seconds * 48000.0) as usize;
+			*frame = self.settings.seconds_to_frames(seconds);
 		}
 	}
 	
@@ -203,6 +225,13 @@ impl AudioPlayer {
 
 
 
-
-
-
+/// First empty slot in the track list, adding one at the end if there are none
+fn free_slot(tracks: &mut Vec<Option<PlayerSource>>) -> usize {
+	match tracks.iter().position(|source| source.is_none()) {
+		Some(index) => index,
+		None => {
+			tracks.push(None);
+			tracks.len() - 1
+		}
+	}
+}
@@ -0,0 +1,195 @@
+use std::borrow::Cow;
+
+use crate::*;
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProjectRate {
+	Hz44100,
+	#[default]
+	Hz48000,
+	Hz88200,
+	Hz96000,
+	Hz192000,
+}
+
+impl ProjectRate {
+	pub const ALL: [ProjectRate; 5] = [ProjectRate::Hz44100, ProjectRate::Hz48000, ProjectRate::Hz88200, ProjectRate::Hz96000, ProjectRate::Hz192000];
+	
+	pub fn hz(self) -> u32 {
+		match self {
+			ProjectRate::Hz44100 => 44100,
+			ProjectRate::Hz48000 => 48000,
+			ProjectRate::Hz88200 => 88200,
+			ProjectRate::Hz96000 => 96000,
+			ProjectRate::Hz192000 => 192000,
+		}
+	}
+	
+	pub fn from_hz(hz: u32) -> Option<Self> {
+		Self::ALL.into_iter().find(|rate| rate.hz() == hz)
+	}
+}
+
+
+/// Sources that are already at the project rate skip the resampler regardless of the preset
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResamplerQuality {
+	/// Septic polynomial interpolation, fast enough for interactive use
+	#[default]
+	Draft,
+	/// Windowed sinc interpolation for final renders
+	HighQuality,
+}
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProjectSettings {
+	pub sample_rate: ProjectRate,
+	pub resampler: ResamplerQuality,
+}
+
+impl ProjectSettings {
+	pub fn rate(&self) -> u32 {
+		self.sample_rate.hz()
+	}
+	
+	/// Nearest frame at the project rate to a time, times before 0 are frame 0
+	pub fn seconds_to_frames(&self, seconds: f64) -> usize {
+		(seconds * self.rate() as f64).round().max(0.0) as usize
+	}
+	
+	/// Ratio to resample a source at `source_rate` to the project rate
+	pub fn resample_ratio(&self, source_rate: u32) -> f64 {
+		self.rate() as f64 / source_rate as f64
+	}
+	
+	/// The track itself if it's at the project rate, otherwise a copy resampled to it
+	pub fn at_project_rate<'a, const N: usize>(&self, track: &'a AudioTrack<N>) -> Result<Cow<'a, AudioTrack<N>>, LoadError> {
+		if track.sample_rate == self.rate() { return Ok(Cow::Borrowed(track)) }
+		Ok(Cow::Owned(convert_sample_rate(track, self.rate())?))
+	}
+}
+
+
+/// Resamples a whole track by `ratio` with a windowed sinc, for offline work like exports and pitch changes.
+/// The result is exactly `ratio` times as long, rounded to a frame, and keeps the track's sample rate.
+pub fn resample_track<const N: usize>(track: &AudioTrack<N>, ratio: f64) -> Result<AudioTrack<N>, LoadError> {
+	if ratio == 1.0 { return Ok(track.clone()) }
+	
+	let target_frames = (track.length() as f64 * ratio).round() as usize;
+	let mut resampler = rubato::SincFixedIn::<f32>::new(ratio, 1.0, rubato::SincInterpolationParameters {
+		sinc_len: 256,
+		f_cutoff: 0.95,
+		interpolation: rubato::SincInterpolationType::Cubic,
+		oversampling_factor: 256,
+		window: rubato::WindowFunction::BlackmanHarris2,
+	}, RESAMPLER_BLOCK_SIZE, N)?;
+	
+	let mut output = vec![Vec::with_capacity(target_frames + resampler.output_frames_max()); N];
+	let mut input = vec![vec![0.0; RESAMPLER_BLOCK_SIZE]; N];
+	let mut frame = 0;
+	
+	// Silence past the end flushes the filter. The sinc resampler starts half a filter length back, so there's no delay to cut off.
+	while output.first().is_some_and(|output| output.len() < target_frames) {
+		for (c, input) in input.iter_mut().enumerate() {
+			input.fill(0.0);
+			let end = (frame + RESAMPLER_BLOCK_SIZE).min(track.length());
+			if frame < end { input[..(end - frame)].copy_from_slice(&track.data[c][frame..end]); }
+		}
+		frame += RESAMPLER_BLOCK_SIZE;
+		
+		let resampled = resampler.process(&input, None)?;
+		for (output, resampled) in output.iter_mut().zip(resampled) { output.extend_from_slice(&resampled); }
+	}
+	
+	let mut resampled = AudioTrack::new(target_frames, track.sample_rate);
+	for (channel, output) in resampled.data.iter_mut().zip(output) { channel.copy_from_slice(&output[..target_frames]); }
+	Ok(resampled)
+}
+
+/// Resamples a track to another rate, keeping its duration
+pub fn convert_sample_rate<const N: usize>(track: &AudioTrack<N>, sample_rate: u32) -> Result<AudioTrack<N>, LoadError> {
+	let mut converted = resample_track(track, sample_rate as f64 / track.sample_rate as f64)?;
+	converted.sample_rate = sample_rate;
+	Ok(converted)
+}
+
+
+/// Stereo resampler for one source, chosen from the project settings
+pub enum ProjectResampler {
+	Bypass,
+	Draft(rubato::FastFixedIn<f32>),
+	HighQuality(rubato::SincFixedIn<f32>),
+}
+
+impl ProjectResampler {
+	pub fn new(settings: &ProjectSettings, source_rate: u32) -> Result<Self, LoadError> {
+		let resample_ratio = settings.resample_ratio(source_rate);
+		if source_rate == settings.rate() { return Ok(ProjectResampler::Bypass) }
+		
+		Ok(match settings.resampler {
+			ResamplerQuality::Draft => ProjectResampler::Draft(
+				rubato::FastFixedIn::<f32>::new(resample_ratio, 1.0, rubato::PolynomialDegree::Septic, RESAMPLER_BLOCK_SIZE, 2)?
+			),
+			ResamplerQuality::HighQuality => ProjectResampler::HighQuality(
+				rubato::SincFixedIn::<f32>::new(resample_ratio, 1.0, rubato::SincInterpolationParameters {
+					sinc_len: 256,
+					f_cutoff: 0.95,
+					interpolation: rubato::SincInterpolationType::Cubic,
+					oversampling_factor: 256,
+					window: rubato::WindowFunction::BlackmanHarris2,
+				}, RESAMPLER_BLOCK_SIZE, 2)?
+			),
+		})
+	}
+	
+	/// Resamples one block of `RESAMPLER_BLOCK_SIZE` frames. Returns the number of frames written to `output`.
+	pub fn process_into_buffer<Vin, Vout>(&mut self, input: &[Vin], output: &mut [Vout]) -> Result<usize, LoadError> where Vin: AsRef<[f32]>, Vout: AsMut<[f32]> {
+		Ok(match self {
+			ProjectResampler::Bypass => {
+				for (i, o) in input.iter().zip(output.iter_mut()) {
+					o.as_mut()[..RESAMPLER_BLOCK_SIZE].copy_from_slice(&i.as_ref()[..RESAMPLER_BLOCK_SIZE]);
+				}
+				RESAMPLER_BLOCK_SIZE
+			}
+			ProjectResampler::Draft(resampler) => resampler.process_into_buffer(input, output, None)?.1,
+			ProjectResampler::HighQuality(resampler) => resampler.process_into_buffer(input, output, None)?.1,
+		})
+	}
+	
+	pub fn output_frames_max(&self) -> usize {
+		match self {
+			ProjectResampler::Bypass => RESAMPLER_BLOCK_SIZE,
+			ProjectResampler::Draft(resampler) => resampler.output_frames_max(),
+			ProjectResampler::HighQuality(resampler) => resampler.output_frames_max(),
+		}
+	}
+	
+	/// Frames of output that come before the first input frame and have to be discarded
+	pub fn output_delay(&self) -> usize {
+		match self {
+			ProjectResampler::Bypass => 0,
+			ProjectResampler::Draft(resampler) => resampler.output_delay(),
+			// The sinc resampler starts half a filter length back, so its output is already aligned with the input
+			ProjectResampler::HighQuality(_) => 0,
+		}
+	}
+	
+	/// Frames of output held back until more input arrives, which have to be flushed out with silence at the end
+	pub fn latency(&self) -> usize {
+		match self {
+			ProjectResampler::Bypass => 0,
+			ProjectResampler::Draft(resampler) => resampler.output_delay(),
+			ProjectResampler::HighQuality(resampler) => resampler.output_delay(),
+		}
+	}
+	
+	pub fn reset(&mut self) {
+		match self {
+			ProjectResampler::Bypass => (),
+			ProjectResampler::Draft(resampler) => resampler.reset(),
+			ProjectResampler::HighQuality(resampler) => resampler.reset(),
+		}
+	}
+}
@@ -0,0 +1,238 @@
+use std::path::{Path, PathBuf};
+
+use crate::*;
+
+
+/// A sound put together from source files. Each layer plays a file after a delay, with a gain and a pitch change.
+/// Layer settings can refer to the definition's parameters and vary randomly from render to render,
+/// so one definition renders to any number of variations.
+///
+/// Definitions are text, one `key = value` setting per line and `#` starting a comment:
+/// ```text
+/// name = footstep
+/// param pitch = 0
+///
+/// layer = heel.wav
+/// gain = -3 +- 1.5
+/// pitch = $pitch +- 0.5
+/// delay = 0.02 +- 0.01
+/// ```
+/// Settings after a `layer` line belong to that layer. A value is a number or a `$parameter`,
+/// with an optional `+- range` of uniform randomness around it. Source paths are relative to the definition file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SoundDefinition {
+	pub name: String,
+	/// File the definition was read from
+	pub path: Option<PathBuf>,
+	/// Name and default value of each parameter
+	pub parameters: Vec<(String, f64)>,
+	pub layers: Vec<SoundLayer>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoundLayer {
+	pub source: PathBuf,
+	/// In dB
+	pub gain: SoundValue,
+	/// In semitones, by playing the source faster or slower
+	pub pitch: SoundValue,
+	/// Seconds of silence before the layer starts
+	pub delay: SoundValue,
+	/// Seconds skipped at the start of the source
+	pub start: SoundValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoundValue {
+	pub base: SoundValueBase,
+	/// Each render adds a uniform random offset in ±`random`
+	pub random: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SoundValueBase {
+	Constant(f64),
+	Parameter(String),
+}
+
+impl SoundValue {
+	pub fn constant(value: f64) -> Self {
+		Self { base: SoundValueBase::Constant(value), random: 0.0 }
+	}
+}
+
+
+/// Seed and parameter values of one render
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderVariation {
+	pub seed: u64,
+	/// Parameters left out keep their default
+	pub parameters: Vec<(String, f64)>,
+}
+
+
+impl SoundDefinition {
+	pub fn from_file<P>(path: P) -> Result<Self, RenderError> where P: AsRef<Path> {
+		let path = path.as_ref();
+		let mut definition = Self::parse(&std::fs::read_to_string(path)?, path.parent().unwrap_or(Path::new("")))?;
+		definition.path = Some(path.to_path_buf());
+		Ok(definition)
+	}
+	
+	/// Parses a definition, with source paths resolved against `base_dir`
+	pub fn parse(text: &str, base_dir: &Path) -> Result<Self, RenderError> {
+		let mut definition = Self::default();
+		
+		for (i, line) in text.lines().enumerate() {
+			let error = |message: String| RenderError::Definition { line: i + 1, message };
+			let line = line.split('#').next().unwrap_or_default().trim();
+			if line.is_empty() { continue }
+			let Some((key, value)) = line.split_once('=').map(|(key, value)| (key.trim(), value.trim())) else {
+				return Err(error(format!("expected `key = value`, found `{line}`")))
+			};
+			
+			match key.split_whitespace().collect::<Vec<_>>()[..] {
+				["name"] => definition.name = value.into(),
+				["param", name] => {
+					if definition.parameters.iter().any(|(other, _)| other == name) { return Err(error(format!("parameter {name} defined twice"))) }
+					let default = value.parse().map_err(|_| error(format!("`{value}` isn't a number")))?;
+					definition.parameters.push((name.into(), default));
+				}
+				["layer"] => definition.layers.push(SoundLayer {
+					source: base_dir.join(value),
+					gain: SoundValue::constant(0.0),
+					pitch: SoundValue::constant(0.0),
+					delay: SoundValue::constant(0.0),
+					start: SoundValue::constant(0.0),
+				}),
+				[setting @ ("gain" | "pitch" | "delay" | "start")] => {
+					let sound_value = definition.parse_value(value).map_err(error)?;
+					let Some(layer) = definition.layers.last_mut() else { return Err(error(format!("{setting} comes before any layer"))) };
+					match setting {
+						"gain" => layer.gain = sound_value,
+						"pitch" => layer.pitch = sound_value,
+						"delay" => layer.delay = sound_value,
+						_ => layer.start = sound_value,
+					}
+				}
+				_ => return Err(error(format!("unknown setting `{key}`"))),
+			}
+		}
+		
+		if definition.name.is_empty() { return Err(RenderError::Definition { line: 0, message: "the sound has no name".into() }) }
+		Ok(definition)
+	}
+	
+	fn parse_value(&self, text: &str) -> Result<SoundValue, String> {
+		let (base, random) = match text.split_once("+-") {
+			Some((base, random)) => (base.trim(), random.trim().parse::<f64>().map_err(|_| format!("`{}` isn't a number", random.trim()))?.abs()),
+			None => (text, 0.0),
+		};
+		
+		let base = match base.strip_prefix('$') {
+			Some(name) if self.parameters.iter().any(|(parameter, _)| parameter == name) => SoundValueBase::Parameter(name.into()),
+			Some(name) => return Err(format!("parameter {name} isn't defined")),
+			None => SoundValueBase::Constant(base.parse().map_err(|_| format!("`{base}` isn't a number"))?),
+		};
+		Ok(SoundValue { base, random })
+	}
+	
+	/// Default parameter values with the variation's on top
+	pub fn parameter_values(&self, variation: &RenderVariation) -> Result<Vec<(String, f64)>, RenderError> {
+		let mut values = self.parameters.clone();
+		for (name, value) in &variation.parameters {
+			let Some(parameter) = values.iter_mut().find(|(parameter, _)| parameter == name) else { return Err(RenderError::UnknownParameter(name.clone())) };
+			parameter.1 = *value;
+		}
+		Ok(values)
+	}
+	
+	/// Provenance of a render: the definition file, the sound's name, then the seed and every parameter value
+	pub fn provenance(&self, seed: u64, parameters: &[(String, f64)]) -> Provenance {
+		Provenance {
+			project: self.path.as_ref().map(|path| path.to_string_lossy().into_owned()).unwrap_or_default(),
+			graph: self.name.clone(),
+			parameters: std::iter::once(("seed".to_string(), seed.to_string()))
+				.chain(parameters.iter().map(|(name, value)| (name.clone(), value.to_string())))
+				.collect(),
+		}
+	}
+}
+
+
+
+/// Renders variations of a sound definition, with every source loaded once up front
+pub struct SoundRenderer<'a> {
+	definition: &'a SoundDefinition,
+	settings: ProjectSettings,
+	sources: Vec<AudioTrack<2>>,
+}
+
+impl<'a> SoundRenderer<'a> {
+	pub fn new(definition: &'a SoundDefinition, settings: &ProjectSettings) -> Result<Self, RenderError> {
+		let paths = definition.layers.iter().map(|layer| &layer.source).collect::<Vec<_>>();
+		let sources = if paths.is_empty() {vec![]} else {load_audio(&paths, settings)?};
+		Ok(Self { definition, settings: *settings, sources })
+	}
+	
+	pub fn definition(&self) -> &SoundDefinition {
+		self.definition
+	}
+	
+	/// Mixes the layers with this variation's parameters and random draws. The same variation always renders the same samples.
+	pub fn render(&self, variation: &RenderVariation) -> Result<AudioTrack<2>, RenderError> {
+		let parameters = self.definition.parameter_values(variation)?;
+		let mut rng = Rng::new(variation.seed);
+		let mut value = |sound_value: &SoundValue| {
+			let base = match &sound_value.base {
+				SoundValueBase::Constant(value) => *value,
+				SoundValueBase::Parameter(name) => parameters.iter().find(|(parameter, _)| parameter == name).map_or(0.0, |(_, value)| *value),
+			};
+			// Drawn even without randomness, so adding a range to one setting doesn't change the draws of the others
+			base + sound_value.random * (2.0 * rng.next_uniform() - 1.0)
+		};
+		
+		let mut layers = Vec::with_capacity(self.sources.len());
+		for (layer, source) in self.definition.layers.iter().zip(&self.sources) {
+			let gain = 10f64.powf(value(&layer.gain) / 20.0) as f32;
+			let pitch = value(&layer.pitch);
+			let delay = self.settings.seconds_to_frames(value(&layer.delay).max(0.0));
+			let start = self.settings.seconds_to_frames(value(&layer.start).max(0.0)).min(source.length());
+			
+			let trimmed = AudioTrack::clone_range(source, start..source.length());
+			// Playing faster by the pitch ratio makes the source shorter by the same ratio
+			let pitched = if pitch == 0.0 {trimmed} else {resample_track(&trimmed, 2f64.powf(-pitch / 12.0))?};
+			layers.push((pitched, gain, delay));
+		}
+		
+		let length = layers.iter().map(|(track, _, delay)| delay + track.length()).max().unwrap_or(0);
+		let mut mix = AudioTrack::<2>::new(length, self.settings.rate());
+		for (track, gain, delay) in &layers {
+			for c in 0..2 {
+				for (mixed, sample) in mix.data[c][*delay..].iter_mut().zip(track.data[c].iter()) { *mixed += sample * gain; }
+			}
+		}
+		Ok(mix)
+	}
+}
+
+
+/// xorshift64*, the same sequence on every platform
+struct Rng {
+	state: u64,
+}
+
+impl Rng {
+	fn new(seed: u64) -> Self {
+		// Zero is a fixed point of xorshift
+		Self { state: seed ^ 0x9e37_79b9_7f4a_7c15 }
+	}
+	
+	/// Uniform in [0, 1)
+	fn next_uniform(&mut self) -> f64 {
+		self.state ^= self.state >> 12;
+		self.state ^= self.state << 25;
+		self.state ^= self.state >> 27;
+		(self.state.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+	}
+}
@@ -0,0 +1,549 @@
+use std::{io::{Cursor, Read, Seek, SeekFrom}, ops::Range, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use symphonia::core::{io::MediaSource, probe::Instantiate};
+
+use crate::*;
+
+
+/// Where the bytes of a file come from
+pub enum AudioSource {
+	Path(PathBuf),
+	/// Embedded assets, archive members or generated buffers, shared so the same bytes can be opened more than once
+	Bytes(Arc<[u8]>),
+	/// Any seekable reader, read from its start
+	Reader(Box<dyn MediaSource>),
+}
+
+impl AudioSource {
+	pub fn from_reader<R>(reader: R) -> Self where R: Read + Seek + Send + Sync + 'static {
+		AudioSource::Reader(Box::new(SeekableReader(reader)))
+	}
+	
+	fn extension(&self) -> Option<String> {
+		match self {
+			AudioSource::Path(path) => path.extension().map(|extension| extension.to_string_lossy().into_owned()),
+			_ => None,
+		}
+	}
+	
+	fn into_media_source(self) -> Result<Box<dyn MediaSource>, LoadError> {
+		Ok(match self {
+			AudioSource::Path(path) => Box::new(std::fs::File::open(path)?),
+			AudioSource::Bytes(bytes) => Box::new(Cursor::new(bytes)),
+			AudioSource::Reader(reader) => reader,
+		})
+	}
+}
+
+impl From<&Path> for AudioSource {
+	fn from(path: &Path) -> Self {
+		AudioSource::Path(path.to_path_buf())
+	}
+}
+
+impl From<PathBuf> for AudioSource {
+	fn from(path: PathBuf) -> Self {
+		AudioSource::Path(path)
+	}
+}
+
+impl From<&[u8]> for AudioSource {
+	fn from(bytes: &[u8]) -> Self {
+		AudioSource::Bytes(bytes.into())
+	}
+}
+
+impl From<Vec<u8>> for AudioSource {
+	fn from(bytes: Vec<u8>) -> Self {
+		AudioSource::Bytes(bytes.into())
+	}
+}
+
+impl From<Arc<[u8]>> for AudioSource {
+	fn from(bytes: Arc<[u8]>) -> Self {
+		AudioSource::Bytes(bytes)
+	}
+}
+
+
+/// Tells the loader what a source holds when the data alone isn't enough.
+/// Symphonia's probe only looks for format markers and ignores its `Hint`, so hints are applied here instead:
+/// the extension or MIME type picks the format reader when probing finds nothing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SourceHint {
+	/// Without the dot. Taken from the path when not given.
+	pub extension: Option<String>,
+	pub mime_type: Option<String>,
+	/// Layout of headerless PCM, which has no markers to probe for
+	pub raw_pcm: Option<RawPcmFormat>,
+}
+
+impl SourceHint {
+	pub fn extension(extension: &str) -> Self {
+		Self { extension: Some(extension.into()), ..Default::default() }
+	}
+	
+	pub fn mime_type(mime_type: &str) -> Self {
+		Self { mime_type: Some(mime_type.into()), ..Default::default() }
+	}
+	
+	pub fn raw_pcm(format: RawPcmFormat) -> Self {
+		Self { raw_pcm: Some(format), ..Default::default() }
+	}
+}
+
+
+/// Interleaved little-endian sample encodings for headerless PCM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcmEncoding {
+	U8,
+	S16,
+	S24,
+	S32,
+	F32,
+	F64,
+}
+
+impl PcmEncoding {
+	pub fn bytes_per_sample(self) -> u16 {
+		match self {
+			PcmEncoding::U8 => 1,
+			PcmEncoding::S16 => 2,
+			PcmEncoding::S24 => 3,
+			PcmEncoding::S32 | PcmEncoding::F32 => 4,
+			PcmEncoding::F64 => 8,
+		}
+	}
+	
+	fn is_float(self) -> bool {
+		matches!(self, PcmEncoding::F32 | PcmEncoding::F64)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RawPcmFormat {
+	pub encoding: PcmEncoding,
+	pub sample_rate: u32,
+	pub channels: u16,
+}
+
+impl RawPcmFormat {
+	/// A format a WAV header can describe: at least one channel, a rate, and frames and bytes per second that fit its fields
+	pub fn check(&self) -> Result<(), LoadError> {
+		let invalid = |message: &str| Err(LoadError::UnsupportedFormat(format!("Raw PCM with {message}")));
+		if self.channels == 0 { return invalid("no channels") }
+		if self.sample_rate == 0 { return invalid("a sample rate of 0") }
+		let Some(block_align) = self.encoding.bytes_per_sample().checked_mul(self.channels) else { return invalid(&format!("{} channels", self.channels)) };
+		if self.sample_rate.checked_mul(block_align as u32).is_none() { return invalid(&format!("{} Hz and {} channels", self.sample_rate, self.channels)) }
+		Ok(())
+	}
+}
+
+
+
+/// Container formats that can be picked by hint, for when probing doesn't find a marker
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum HintedFormat {
+	Wav,
+	Aiff,
+	Flac,
+	Mpeg,
+	Adts,
+	Ogg,
+	Matroska,
+	Mp4,
+	Caf,
+}
+
+impl HintedFormat {
+	fn from_hint(hint: &SourceHint, extension: Option<&str>) -> Option<Self> {
+		let from_extension = extension.and_then(|extension| Some(match extension.to_ascii_lowercase().as_str() {
+			"wav" | "wave" => HintedFormat::Wav,
+			"aif" | "aiff" | "aifc" => HintedFormat::Aiff,
+			"flac" => HintedFormat::Flac,
+			"mp1" | "mp2" | "mp3" | "mpa" => HintedFormat::Mpeg,
+			"aac" | "adts" => HintedFormat::Adts,
+			"ogg" | "oga" | "opus" => HintedFormat::Ogg,
+			"mka" | "mkv" | "webm" => HintedFormat::Matroska,
+			"mp4" | "m4a" | "m4b" | "mov" => HintedFormat::Mp4,
+			"caf" => HintedFormat::Caf,
+			_ => return None,
+		}));
+		
+		let from_mime_type = hint.mime_type.as_deref().and_then(|mime_type| Some(match mime_type.to_ascii_lowercase().as_str() {
+			"audio/wav" | "audio/wave" | "audio/x-wav" | "audio/vnd.wave" => HintedFormat::Wav,
+			"audio/aiff" | "audio/x-aiff" => HintedFormat::Aiff,
+			"audio/flac" | "audio/x-flac" => HintedFormat::Flac,
+			"audio/mpeg" | "audio/mp3" => HintedFormat::Mpeg,
+			"audio/aac" | "audio/aacp" | "audio/x-aac" => HintedFormat::Adts,
+			"audio/ogg" | "application/ogg" | "audio/vorbis" | "audio/opus" => HintedFormat::Ogg,
+			"audio/webm" | "video/webm" | "audio/x-matroska" | "video/x-matroska" => HintedFormat::Matroska,
+			"audio/mp4" | "audio/x-m4a" | "video/mp4" => HintedFormat::Mp4,
+			"audio/x-caf" => HintedFormat::Caf,
+			_ => return None,
+		}));
+		
+		from_extension.or(from_mime_type)
+	}
+	
+	fn instantiate(self, mss: MediaSourceStream, options: &FormatOptions) -> Result<Box<dyn FormatReader>, SymphoniaError> {
+		use symphonia::default::formats::*;
+		
+		fn new<R>(mss: MediaSourceStream, options: &FormatOptions) -> Result<Box<dyn FormatReader>, SymphoniaError> where R: FormatReader + 'static {
+			Ok(Box::new(R::try_new(mss, options)?))
+		}
+		
+		match self {
+			HintedFormat::Wav => new::<WavReader>(mss, options),
+			#[cfg(feature = "codecs")]
+			HintedFormat::Aiff => new::<AiffReader>(mss, options),
+			HintedFormat::Flac => new::<FlacReader>(mss, options),
+			#[cfg(feature = "codecs")]
+			HintedFormat::Mpeg => new::<MpaReader>(mss, options),
+			#[cfg(feature = "codecs")]
+			HintedFormat::Adts => new::<AdtsReader>(mss, options),
+			HintedFormat::Ogg => new::<OggReader>(mss, options),
+			HintedFormat::Matroska => new::<MkvReader>(mss, options),
+			#[cfg(feature = "codecs")]
+			HintedFormat::Mp4 => new::<IsoMp4Reader>(mss, options),
+			#[cfg(feature = "codecs")]
+			HintedFormat::Caf => new::<CafReader>(mss, options),
+			#[cfg(not(feature = "codecs"))]
+			_ => Err(SymphoniaError::Unsupported("this container needs the codecs feature")),
+		}
+	}
+}
+
+
+/// Format reader for a source, along with the metadata found in front of the container while probing and in WAV chunks symphonia skips
+pub struct OpenedSource {
+	pub format: Box<dyn FormatReader>,
+	pub metadata: TrackMetadata,
+}
+
+/// Finds a format reader for the source. Headerless PCM is read as described by the hint, raw ADTS is recognised by its
+/// sync word, anything else is probed for markers and falls back to the format named by the hint.
+pub fn open_source(source: AudioSource, hint: &SourceHint, options: &FormatOptions) -> Result<OpenedSource, LoadError> {
+	let extension = hint.extension.clone().or_else(|| source.extension());
+	let hinted_format = HintedFormat::from_hint(hint, extension.as_deref());
+	
+	let mut media_source = source.into_media_source()?;
+	let mut rf64_metadata = None;
+	if let Some(pcm) = hint.raw_pcm {
+		pcm.check()?;
+		let data_len = stream_len(&mut media_source)?;
+		media_source = Box::new(HeaderedSource::new(wav_header(pcm, data_len), media_source, 0..data_len)?);
+	} else if let Some(rf64) = rf64_riff_header(&mut media_source)? {
+		// Chunks after the audio are out of reach once the source is wrapped
+		rf64_metadata = Some(read_riff_metadata(&mut media_source).unwrap_or_default());
+		media_source = Box::new(HeaderedSource::new(rf64.header, media_source, rf64.data)?);
+	}
+	let source = SharedSource(Arc::new(Mutex::new(media_source)));
+	
+	if hint.raw_pcm.is_some() {
+		return Ok(OpenedSource { format: HintedFormat::Wav.instantiate(source.rewound()?, options)?, metadata: TrackMetadata::default() })
+	}
+	
+	if hinted_format == Some(HintedFormat::Adts) || source.starts_with_adts() {
+		// Symphonia's ADTS reader only syncs on MPEG-4 headers without CRC, so rewrite the rest to that form first
+		let mut data = vec![];
+		source.rewound()?.read_to_end(&mut data)?;
+		if let Some(data) = normalize_adts(&data) {
+			let mss = MediaSourceStream::new(Box::new(Cursor::new(data)), MediaSourceStreamOptions::default());
+			return Ok(OpenedSource { format: HintedFormat::Adts.instantiate(mss, options)?, metadata: TrackMetadata::default() })
+		}
+	}
+	
+	// Damaged chunks only cost the metadata, not the audio
+	let mut metadata = rf64_metadata.unwrap_or_else(|| read_riff_metadata(&mut source.clone()).unwrap_or_default());
+	
+	let format = match probe(source.rewound()?, options, &mut metadata) {
+		Ok(format) => format,
+		Err(e) => match hinted_format {
+			Some(format) => format.instantiate(source.rewound()?, options).map_err(|_| e)?,
+			None => return Err(e),
+		}
+	};
+	
+	Ok(OpenedSource { format, metadata })
+}
+
+/// Same as symphonia's `Probe::format`, but keeps the tags of metadata blocks (ID3) found before the container
+fn probe(mut mss: MediaSourceStream, options: &FormatOptions, metadata: &mut TrackMetadata) -> Result<Box<dyn FormatReader>, LoadError> {
+	loop {
+		match symphonia::default::get_probe().next(&mut mss)? {
+			Instantiate::Format(format) => return Ok(format(mss, options)?),
+			Instantiate::Metadata(reader) => metadata.add_tags(&reader(&MetadataOptions::default()).read_all(&mut mss)?),
+		}
+	}
+}
+
+
+
+/// Media source behind a lock, so it can be rewound and handed to another format reader after one gave up on it
+#[derive(Clone)]
+struct SharedSource(Arc<Mutex<Box<dyn MediaSource>>>);
+
+impl SharedSource {
+	fn rewound(&self) -> Result<MediaSourceStream, LoadError> {
+		self.0.lock().unwrap().seek(SeekFrom::Start(0))?;
+		Ok(MediaSourceStream::new(Box::new(self.clone()), MediaSourceStreamOptions { buffer_len: 1024 * 1024 }))
+	}
+	
+	fn starts_with_adts(&self) -> bool {
+		let mut source = self.0.lock().unwrap();
+		let mut header = [0; 2];
+		source.seek(SeekFrom::Start(0)).is_ok() && source.read_exact(&mut header).is_ok() && is_adts_sync(&header)
+	}
+}
+
+impl Read for SharedSource {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		self.0.lock().unwrap().read(buf)
+	}
+}
+
+impl Seek for SharedSource {
+	fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+		self.0.lock().unwrap().seek(pos)
+	}
+}
+
+impl MediaSource for SharedSource {
+	fn is_seekable(&self) -> bool {
+		self.0.lock().unwrap().is_seekable()
+	}
+	
+	fn byte_len(&self) -> Option<u64> {
+		self.0.lock().unwrap().byte_len()
+	}
+}
+
+
+struct SeekableReader<R>(R);
+
+impl<R> Read for SeekableReader<R> where R: Read {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		self.0.read(buf)
+	}
+}
+
+impl<R> Seek for SeekableReader<R> where R: Seek {
+	fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+		self.0.seek(pos)
+	}
+}
+
+impl<R> MediaSource for SeekableReader<R> where R: Read + Seek + Send + Sync {
+	fn is_seekable(&self) -> bool {
+		true
+	}
+	
+	fn byte_len(&self) -> Option<u64> {
+		None
+	}
+}
+
+
+/// Serves `header` in front of a range of the inner source, to make headerless data readable by a container format reader
+struct HeaderedSource {
+	header: Vec<u8>,
+	inner: Box<dyn MediaSource>,
+	inner_start: u64,
+	inner_len: u64,
+	pos: u64,
+}
+
+impl HeaderedSource {
+	fn new(header: Vec<u8>, mut inner: Box<dyn MediaSource>, inner_range: Range<u64>) -> std::io::Result<Self> {
+		inner.seek(SeekFrom::Start(inner_range.start))?;
+		Ok(Self { header, inner, inner_start: inner_range.start, inner_len: inner_range.end - inner_range.start, pos: 0 })
+	}
+}
+
+impl Read for HeaderedSource {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let header_len = self.header.len() as u64;
+		let n = if self.pos < header_len {
+			let header = &self.header[(self.pos as usize)..];
+			let n = header.len().min(buf.len());
+			buf[..n].copy_from_slice(&header[..n]);
+			n
+		} else {
+			let inner_left = (header_len + self.inner_len).saturating_sub(self.pos);
+			let n = buf.len().min(inner_left.try_into().unwrap_or(usize::MAX));
+			self.inner.read(&mut buf[..n])?
+		};
+		
+		self.pos += n as u64;
+		Ok(n)
+	}
+}
+
+impl Seek for HeaderedSource {
+	fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+		let len = self.header.len() as u64 + self.inner_len;
+		let pos = match pos {
+			SeekFrom::Start(pos) => Some(pos),
+			SeekFrom::End(offset) => len.checked_add_signed(offset),
+			SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+		}.ok_or(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Seek before start"))?;
+		
+		self.inner.seek(SeekFrom::Start(self.inner_start + pos.saturating_sub(self.header.len() as u64)))?;
+		self.pos = pos;
+		Ok(pos)
+	}
+}
+
+impl MediaSource for HeaderedSource {
+	fn is_seekable(&self) -> bool {
+		true
+	}
+	
+	fn byte_len(&self) -> Option<u64> {
+		Some(self.header.len() as u64 + self.inner_len)
+	}
+}
+
+
+fn stream_len(source: &mut Box<dyn MediaSource>) -> Result<u64, LoadError> {
+	if let Some(len) = source.byte_len() { return Ok(len) }
+	let len = source.seek(SeekFrom::End(0))?;
+	source.seek(SeekFrom::Start(0))?;
+	Ok(len)
+}
+
+/// Canonical 44 byte WAV header describing `data_len` bytes of raw PCM, in a format that passed `RawPcmFormat::check`
+fn wav_header(format: RawPcmFormat, data_len: u64) -> Vec<u8> {
+	let bytes_per_sample = format.encoding.bytes_per_sample();
+	let block_align = bytes_per_sample * format.channels;
+	// Only whole frames, and WAV can't describe more than 4 GiB of data
+	let data_len = (data_len - data_len % block_align as u64).min(u32::MAX as u64 - 36) as u32;
+	
+	let mut header = Vec::with_capacity(44);
+	header.extend_from_slice(b"RIFF");
+	header.extend_from_slice(&(36 + data_len).to_le_bytes());
+	header.extend_from_slice(b"WAVEfmt ");
+	header.extend_from_slice(&16u32.to_le_bytes());
+	header.extend_from_slice(&(if format.encoding.is_float() { 3u16 } else { 1u16 }).to_le_bytes());
+	header.extend_from_slice(&format.channels.to_le_bytes());
+	header.extend_from_slice(&format.sample_rate.to_le_bytes());
+	header.extend_from_slice(&(format.sample_rate * block_align as u32).to_le_bytes());
+	header.extend_from_slice(&block_align.to_le_bytes());
+	header.extend_from_slice(&(bytes_per_sample * 8).to_le_bytes());
+	header.extend_from_slice(b"data");
+	header.extend_from_slice(&data_len.to_le_bytes());
+	header
+}
+
+/// RIFF header standing in for an RF64 one
+struct Rf64Header {
+	header: Vec<u8>,
+	/// Position of the audio in the source
+	data: Range<u64>,
+}
+
+/// Symphonia only reads RIFF WAVE. For an RF64 file, builds a RIFF header out of the chunks before the audio, and returns it with
+/// the position of the audio in the source. Past 4 GiB the sizes are set to the streaming placeholder, and symphonia stops there.
+fn rf64_riff_header(source: &mut Box<dyn MediaSource>) -> Result<Option<Rf64Header>, LoadError> {
+	let mut file_header = [0; 12];
+	let is_rf64 = source.read_exact(&mut file_header).is_ok() && &file_header[0..4] == b"RF64" && &file_header[8..12] == b"WAVE";
+	source.seek(SeekFrom::Start(if is_rf64 {12} else {0}))?;
+	if !is_rf64 { return Ok(None) }
+	
+	let mut chunks = vec![];
+	let mut ds64_data_size = None;
+	
+	loop {
+		let mut chunk_header = [0; 8];
+		source.read_exact(&mut chunk_header)?;
+		let size = u32::from_le_bytes(chunk_header[4..8].try_into().unwrap()) as u64;
+		
+		match &chunk_header[0..4] {
+			b"data" => {
+				let data_size = if size == u32::MAX as u64 {
+					ds64_data_size.ok_or(LoadError::UnsupportedFormat("RF64 file without a ds64 chunk".into()))?
+				} else { size };
+				let data_start = source.stream_position()?;
+				
+				let riff_size = 4 + chunks.len() as u64 + 8 + data_size;
+				let (riff_size, data_size_field) = if riff_size > u32::MAX as u64 { (u32::MAX, u32::MAX) } else { (riff_size as u32, data_size as u32) };
+				
+				let mut header = Vec::with_capacity(20 + chunks.len());
+				header.extend_from_slice(b"RIFF");
+				header.extend_from_slice(&riff_size.to_le_bytes());
+				header.extend_from_slice(b"WAVE");
+				header.extend_from_slice(&chunks);
+				header.extend_from_slice(b"data");
+				header.extend_from_slice(&data_size_field.to_le_bytes());
+				
+				source.seek(SeekFrom::Start(0))?;
+				return Ok(Some(Rf64Header { header, data: data_start..(data_start + data_size) }))
+			}
+			
+			// Nothing that belongs in the header is this big, so it's left out rather than read into memory
+			_ if size > MAX_METADATA_CHUNK_SIZE => {
+				source.seek(SeekFrom::Current((size + size % 2) as i64))?;
+			}
+			
+			b"ds64" => {
+				let mut body = vec![0; size as usize + size as usize % 2];
+				source.read_exact(&mut body)?;
+				if body.len() >= 16 { ds64_data_size = Some(u64::from_le_bytes(body[8..16].try_into().unwrap())); }
+			}
+			
+			_ => {
+				chunks.extend_from_slice(&chunk_header);
+				let start = chunks.len();
+				chunks.resize(start + size as usize + size as usize % 2, 0);
+				source.read_exact(&mut chunks[start..])?;
+			}
+		}
+	}
+}
+
+
+/// 12 bit sync word followed by layer 0, which MPEG audio never uses
+fn is_adts_sync(bytes: &[u8]) -> bool {
+	bytes[0] == 0xff && bytes[1] & 0xf6 == 0xf0
+}
+
+/// Rewrites ADTS frames as MPEG-4 without CRC, the only kind symphonia's reader accepts.
+/// Returns `None` if the data doesn't start with an ADTS frame.
+fn normalize_adts(data: &[u8]) -> Option<Vec<u8>> {
+	if data.len() < 7 || !is_adts_sync(data) { return None }
+	
+	let mut normalized = Vec::with_capacity(data.len());
+	let mut pos = 0;
+	
+	while pos + 7 <= data.len() {
+		let header = &data[pos..];
+		if !is_adts_sync(header) {
+			// Junk between frames, look for the next sync word
+			pos += 1;
+			continue
+		}
+		
+		let protection_absent = header[1] & 1 == 1;
+		let header_len = if protection_absent { 7 } else { 9 };
+		let frame_len = ((header[3] & 0x03) as usize) << 11 | (header[4] as usize) << 3 | (header[5] >> 5) as usize;
+		if frame_len < header_len || pos + frame_len > data.len() { break }
+		
+		let payload = &data[(pos + header_len)..(pos + frame_len)];
+		let new_len = 7 + payload.len();
+		normalized.extend_from_slice(&[
+			0xff,
+			0xf1,
+			header[2],
+			(header[3] & 0xfc) | (new_len >> 11) as u8,
+			(new_len >> 3) as u8,
+			((new_len & 0x07) << 5) as u8 | (header[5] & 0x1f),
+			header[6],
+		]);
+		normalized.extend_from_slice(payload);
+		
+		pos += frame_len;
+	}
+	
+	Some(normalized)
+}
@@ -0,0 +1,138 @@
+use std::{io::Write, path::Path};
+
+use crate::*;
+
+
+/// One sound of a sprite
+#[derive(Clone, Copy)]
+pub struct SpriteSound<'a, const N: usize> {
+	pub name: &'a str,
+	pub track: &'a AudioTrack<N>,
+	/// Marks the sound for looping playback in the manifest, and as a loop region in the file's metadata
+	pub looping: bool,
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteExportOptions {
+	pub format: ExportOptions,
+	/// Silence between sounds, so lossy codecs and resampling players don't bleed one sound into the next
+	pub padding_seconds: f64,
+}
+
+impl Default for SpriteExportOptions {
+	fn default() -> Self {
+		Self { format: ExportOptions::default(), padding_seconds: 0.1 }
+	}
+}
+
+
+/// Where each sound ended up in a sprite file, written next to it as JSON
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpriteManifest {
+	/// File name of the audio, relative to the manifest
+	pub file: String,
+	/// Rate of the audio file, which is what the frame positions count in
+	pub rate: u32,
+	pub channels: usize,
+	pub frames: usize,
+	pub sounds: Vec<SpriteEntry>,
+	pub report: ExportReport,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteEntry {
+	pub name: String,
+	pub start: usize,
+	/// Exclusive
+	pub end: usize,
+	pub looping: bool,
+}
+
+impl SpriteManifest {
+	/// Frame positions, plus a Howler style `sprite` table of start, duration in milliseconds and loop flag
+	pub fn to_json(&self) -> String {
+		let ms = |frames: usize| frames as f64 * 1000.0 / self.rate as f64;
+		let sounds = self.sounds.iter().map(|sound| format!(
+			"\t\t{{ \"name\": {}, \"start\": {}, \"end\": {}, \"loop\": {} }}",
+			json_string(&sound.name), sound.start, sound.end, sound.looping,
+		)).collect::<Vec<_>>();
+		let sprite = self.sounds.iter().map(|sound| format!(
+			"\t\t{}: [{:.3}, {:.3}, {}]",
+			json_string(&sound.name), ms(sound.start), ms(sound.end - sound.start), sound.looping,
+		)).collect::<Vec<_>>();
+		
+		format!(
+			"{{\n\t\"file\": {},\n\t\"rate\": {},\n\t\"channels\": {},\n\t\"frames\": {},\n\t\"sounds\": [\n{}\n\t],\n\t\"sprite\": {{\n{}\n\t}}\n}}\n",
+			json_string(&self.file), self.rate, self.channels, self.frames, sounds.join(",\n"), sprite.join(",\n"),
+		)
+	}
+}
+
+
+
+/// Concatenates sounds into one file with silence between them and writes a JSON manifest of their positions next to it,
+/// with the extension replaced by `json`. The sounds are also written into the file's metadata as markers labelled with their names.
+/// Sounds at another rate than the project's are resampled to it.
+pub fn export_sprite<P, const N: usize>(sounds: &[SpriteSound<N>], path: P, settings: &ProjectSettings, options: &SpriteExportOptions) -> Result<SpriteManifest, ExportError> where P: AsRef<Path> {
+	let path = path.as_ref();
+	for (i, sound) in sounds.iter().enumerate() {
+		if sound.name.is_empty() { return Err(ExportError::InvalidInput(format!("sprite sound {i} has no name"))) }
+		if sounds[..i].iter().any(|other| other.name == sound.name) { return Err(ExportError::InvalidInput(format!("two sprite sounds are named {}", sound.name))) }
+	}
+	
+	let tracks = sounds.iter()
+		.map(|sound| settings.at_project_rate(sound.track))
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|e| ExportError::Encoder(e.to_string()))?;
+	
+	let padding = settings.seconds_to_frames(options.padding_seconds.max(0.0));
+	let mut starts = Vec::with_capacity(sounds.len());
+	let mut frames = 0;
+	for (i, track) in tracks.iter().enumerate() {
+		if i > 0 { frames += padding; }
+		starts.push(frames);
+		frames += track.length();
+	}
+	
+	let mut track = AudioTrack::<N>::new(frames, settings.rate());
+	let mut format = options.format.clone();
+	let metadata = format.metadata_mut();
+	let first_cue_id = metadata.markers.iter().map(|marker| marker.cue_id).max().unwrap_or(0) + 1;
+	for (i, ((sound, sound_track), &start)) in sounds.iter().zip(&tracks).zip(&starts).enumerate() {
+		let range = start..(start + sound_track.length());
+		track.copy_from_range(range.clone(), sound_track, 0..sound_track.length())?;
+		
+		let cue_id = first_cue_id + i as u32;
+		metadata.markers.push(Marker { cue_id, frame: range.start, label: Some(sound.name.into()), length: Some(range.len()) });
+		if sound.looping && !range.is_empty() {
+			metadata.loops.push(LoopRegion { cue_id: Some(cue_id), start: range.start, end: range.end, kind: LoopKind::Forward, play_count: 0 });
+		}
+	}
+	
+	let report = export_track(&track, path, settings, &format)?;
+	
+	let rate = format.file_rate(settings);
+	let rescale = |frame: usize| (frame as f64 * rate as f64 / settings.rate() as f64).round() as usize;
+	let manifest = SpriteManifest {
+		file: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+		rate,
+		channels: N,
+		frames: rescale(frames),
+		sounds: sounds.iter().zip(&tracks).zip(&starts).map(|((sound, sound_track), &start)| SpriteEntry {
+			name: sound.name.into(),
+			start: rescale(start),
+			end: rescale(start + sound_track.length()),
+			looping: sound.looping,
+		}).collect(),
+		report,
+	};
+	
+	let json = manifest.to_json();
+	write_file_atomically(&path.with_extension("json"), |writer| {
+		writer.write_all(json.as_bytes())?;
+		Ok(ExportReport::default())
+	})?;
+	
+	Ok(manifest)
+}
@@ -0,0 +1,372 @@
+use std::{sync::{atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering}, Arc}, thread::JoinHandle};
+
+use crate::*;
+
+
+/// Audio source that decodes and resamples packets on demand instead of materializing the whole file.
+/// Frame positions are always in resampled frames at the project rate.
+pub struct AudioStream {
+	decoder: TrackDecoder,
+	project_rate: u32,
+	resample_ratio: f64,
+	resampler: ProjectResampler,
+	/// Resampled frames still to be dropped to make up for the resampler's delay
+	discard: usize,
+	
+	/// Decoded frames waiting to be resampled
+	input: [Vec<f32>; 2],
+	/// Resampled frames ready to be read, starting at `output_read`
+	output: [Vec<f32>; 2],
+	output_read: usize,
+	
+	frame: usize,
+	length: Option<usize>,
+	source_finished: bool,
+}
+
+impl AudioStream {
+	pub fn open<P>(path: P, settings: &ProjectSettings) -> Result<Self, LoadError> where P: AsRef<std::path::Path> {
+		Self::open_with_options(path, &LoadOptions::default(), settings)
+	}
+	
+	pub fn open_with_options<P>(path: P, options: &LoadOptions, settings: &ProjectSettings) -> Result<Self, LoadError> where P: AsRef<std::path::Path> {
+		Self::open_source(AudioSource::from(path.as_ref()), options, settings)
+	}
+	
+	/// Streams from in-memory bytes or a reader instead of a file
+	pub fn open_source(source: AudioSource, options: &LoadOptions, settings: &ProjectSettings) -> Result<Self, LoadError> {
+		Self::from_decoder(TrackDecoder::open(source, options)?, settings)
+	}
+	
+	pub fn from_decoder(decoder: TrackDecoder, settings: &ProjectSettings) -> Result<Self, LoadError> {
+		let resample_ratio = settings.resample_ratio(decoder.rate());
+		let resampler = ProjectResampler::new(settings, decoder.rate())?;
+		let output_frames_max = resampler.output_frames_max();
+		let discard = resampler.output_delay();
+		let length = decoder.n_frames().map(|n| (n as f64 * resample_ratio) as usize);
+		
+		Ok(Self {
+			decoder,
+			project_rate: settings.rate(),
+			resample_ratio,
+			resampler,
+			discard,
+			input: core::array::from_fn(|_c| Vec::with_capacity(RESAMPLER_BLOCK_SIZE * 2)),
+			output: core::array::from_fn(|_c| vec![0.0; output_frames_max]),
+			output_read: output_frames_max,
+			frame: 0,
+			length,
+			source_finished: false,
+		})
+	}
+	
+	/// Total length in resampled frames, if the container reports it or the end has already been decoded.
+	/// Files without a known frame count only get a length once decoding reaches the end.
+	pub fn length(&self) -> Option<usize> {
+		self.length
+	}
+	
+	/// Current read position in resampled frames
+	pub fn frame(&self) -> usize {
+		self.frame
+	}
+	
+	pub fn source_rate(&self) -> u32 {
+		self.decoder.rate()
+	}
+	
+	/// Packets skipped or zero-filled so far in lenient mode, positions in resampled frames
+	pub fn decode_issues(&self) -> Vec<DecodeIssue> {
+		self.decoder.issues.iter().map(|issue| issue.rescaled(self.resample_ratio)).collect()
+	}
+	
+	/// Tags, markers and loops, positions in resampled frames
+	pub fn metadata(&self) -> TrackMetadata {
+		self.decoder.metadata.rescaled(self.resample_ratio)
+	}
+	
+	pub fn is_finished(&self) -> bool {
+		self.length.is_some_and(|length| self.frame >= length)
+	}
+	
+	/// Reads frames into `out` until it is full or the stream ends. Returns the number of frames read.
+	pub fn read(&mut self, mut out: [&mut [f32]; 2]) -> Result<usize, LoadError> {
+		let frames = out[0].len().min(out[1].len());
+		let mut written = 0;
+		
+		while written < frames {
+			let available = self.prepare_output()?;
+			if available == 0 { break }
+			
+			let n = available.min(frames - written);
+			for (out, output) in out.iter_mut().zip(&self.output) {
+				out[written..(written + n)].copy_from_slice(&output[self.output_read..(self.output_read + n)]);
+			}
+			self.consume_output(n);
+			written += n;
+		}
+		
+		Ok(written)
+	}
+	
+	/// Same as `read`, but writes interleaved stereo frames as expected by the output stream
+	pub fn read_interleaved(&mut self, out: &mut [f32]) -> Result<usize, LoadError> {
+		let frames = out.len() / 2;
+		let mut written = 0;
+		
+		while written < frames {
+			let available = self.prepare_output()?;
+			if available == 0 { break }
+			
+			let n = available.min(frames - written);
+			for frame in 0..n {
+				out[(written + frame) * 2]     = self.output[0][self.output_read + frame];
+				out[(written + frame) * 2 + 1] = self.output[1][self.output_read + frame];
+			}
+			self.consume_output(n);
+			written += n;
+		}
+		
+		Ok(written)
+	}
+	
+	/// Decodes up to `frames` frames from the current position into a new track, for processing without a fully loaded file
+	pub fn read_track(&mut self, frames: usize) -> Result<AudioTrack<2>, LoadError> {
+		let mut track = AudioTrack::new(frames, self.project_rate);
+		let n = self.read(track.get_slice_mut(0..frames))?;
+		Ok(if n < frames { AudioTrack::clone_range(&track, 0..n) } else { track })
+	}
+	
+	/// Moves the read position to `frame`, in resampled frames
+	pub fn seek(&mut self, frame: usize) -> Result<(), LoadError> {
+		let rate = self.decoder.rate() as u64;
+		let project_rate = self.project_rate as u64;
+		
+		// Start decoding a little early, on a source frame that falls exactly on a project frame. That way the output lines up
+		// sample for sample with decoding from the start, and the resampler has some history to work with.
+		let grid = rate / gcd(rate, project_rate);
+		let preroll = (self.resampler.latency() as f64 / self.resample_ratio).ceil() as u64;
+		let source_frame = (frame as u64 * rate / project_rate).saturating_sub(preroll) / grid * grid;
+		self.decoder.seek(source_frame)?;
+		
+		self.resampler.reset();
+		self.discard = self.resampler.output_delay() + frame - (source_frame * project_rate / rate) as usize;
+		for c in 0..2 { self.input[c].clear(); }
+		self.output_read = self.output[0].len();
+		
+		self.frame = frame;
+		self.source_finished = false;
+		
+		Ok(())
+	}
+	
+	
+	
+	fn consume_output(&mut self, n: usize) {
+		self.output_read += n;
+		self.frame += n;
+	}
+	
+	/// Makes sure there are resampled frames ready to read, decoding more packets if needed. Returns how many can be read.
+	fn prepare_output(&mut self) -> Result<usize, LoadError> {
+		let mut available = self.output[0].len() - self.output_read;
+		
+		while available == 0 {
+			// Once the source is done, keep feeding silence until the resampler's delay has been flushed out
+			if self.source_finished && self.input[0].is_empty() && self.length.is_some_and(|length| self.frame >= length) { return Ok(0) }
+			
+			while self.input[0].len() < RESAMPLER_BLOCK_SIZE && !self.source_finished {
+				if !self.decoder.decode_next(&mut self.input)? {
+					self.source_finished = true;
+					// The container's frame count is missing for some formats and wrong for truncated files, trust what was actually decoded
+					self.length = Some((self.decoder.frame() as f64 * self.resample_ratio) as usize);
+				}
+			}
+			
+			if self.input[0].len() < RESAMPLER_BLOCK_SIZE {
+				// Pad the final block with silence, like the padded buffer in load_audio
+				for c in 0..2 { self.input[c].resize(RESAMPLER_BLOCK_SIZE, 0.0); }
+			}
+			
+			let output_frames_max = self.resampler.output_frames_max();
+			for c in 0..2 { self.output[c].resize(output_frames_max, 0.0); }
+			
+			let n = self.resampler.process_into_buffer(
+				&[&self.input[0][..RESAMPLER_BLOCK_SIZE], &self.input[1][..RESAMPLER_BLOCK_SIZE]],
+				&mut self.output,
+			)?;
+			
+			for c in 0..2 {
+				self.input[c].drain(..RESAMPLER_BLOCK_SIZE);
+				self.output[c].truncate(n);
+			}
+			
+			let discard = self.discard.min(n);
+			self.discard -= discard;
+			self.output_read = discard;
+			available = n - discard;
+		}
+		
+		if let Some(length) = self.length {
+			available = available.min(length.saturating_sub(self.frame));
+		}
+		
+		Ok(available)
+	}
+}
+
+
+
+fn gcd(a: u64, b: u64) -> u64 {
+	if b == 0 { a } else { gcd(b, a % b) }
+}
+
+
+
+/// Frames a `BufferedStream` decodes at a time
+const BUFFERED_CHUNK_FRAMES: usize = 1024;
+/// How long the decoding thread of a `BufferedStream` sleeps when its buffer is full
+const BUFFERED_IDLE_WAIT: std::time::Duration = std::time::Duration::from_millis(2);
+/// `seek` value while the reader is reading on
+const NO_SEEK: usize = usize::MAX;
+
+
+/// Frames read from a source that can run out, `ended` once the last frame has been read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FramesRead {
+	pub frames: usize,
+	pub ended: bool,
+}
+
+/// An `AudioStream` decoded ahead on its own thread into a lock-free ring buffer, so it can be read from a real-time audio callback.
+/// Reading never blocks, allocates or touches the file: frames that aren't decoded yet, after a seek or when decoding falls behind, just aren't read yet.
+/// Meant for a single reader, the audio callback.
+pub struct BufferedStream {
+	shared: Arc<BufferShared>,
+	thread: Option<JoinHandle<()>>,
+}
+
+/// Everything the reader and the decoding thread share. Frame counters only ever grow, positions in `samples` wrap around.
+struct BufferShared {
+	/// Interleaved stereo, stored as bits so they can be shared without locking
+	samples: Box<[AtomicU32]>,
+	/// Frames pushed by the decoding thread and taken by the reader so far
+	written: AtomicUsize,
+	read: AtomicUsize,
+	/// Stream frame the next frame in the buffer belongs to, valid while no seek is pending
+	position: AtomicUsize,
+	/// Frame the reader wants to continue from, `NO_SEEK` if there is none pending. The reader leaves the buffer alone until the decoding thread has done it.
+	seek: AtomicUsize,
+	/// Everything up to the end of the stream is in the buffer, or decoding failed
+	finished: AtomicBool,
+	stop: AtomicBool,
+}
+
+impl BufferedStream {
+	/// Starts decoding, buffering up to `capacity` frames ahead. Decoding and seeking errors go to `on_error` and end the stream.
+	pub fn spawn<F>(mut stream: AudioStream, capacity: usize, on_error: F) -> Self where F: Fn(LoadError) + Send + 'static {
+		let capacity = capacity.max(BUFFERED_CHUNK_FRAMES);
+		let shared = Arc::new(BufferShared {
+			samples: (0..(capacity * 2)).map(|_| AtomicU32::new(0)).collect(),
+			written: AtomicUsize::new(0),
+			read: AtomicUsize::new(0),
+			position: AtomicUsize::new(stream.frame()),
+			seek: AtomicUsize::new(NO_SEEK),
+			finished: AtomicBool::new(false),
+			stop: AtomicBool::new(false),
+		});
+		
+		let thread = std::thread::spawn({
+			let shared = Arc::clone(&shared);
+			move || {
+				let mut chunk = vec![0.0; BUFFERED_CHUNK_FRAMES * 2];
+				
+				while !shared.stop.load(Ordering::Relaxed) {
+					let target = shared.seek.load(Ordering::Acquire);
+					if target != NO_SEEK {
+						// The reader waits while a seek is pending, so the buffer can be emptied from this side
+						shared.read.store(shared.written.load(Ordering::Relaxed), Ordering::Release);
+						let failed = stream.seek(target).map_err(&on_error).is_err();
+						shared.finished.store(failed, Ordering::Relaxed);
+						shared.position.store(target, Ordering::Relaxed);
+						shared.seek.store(NO_SEEK, Ordering::Release);
+						continue
+					}
+					
+					if shared.finished.load(Ordering::Relaxed) || shared.free() < BUFFERED_CHUNK_FRAMES {
+						std::thread::sleep(BUFFERED_IDLE_WAIT);
+						continue
+					}
+					
+					match stream.read_interleaved(&mut chunk) {
+						Ok(frames) => {
+							shared.push(&chunk[..(frames * 2)]);
+							if frames < BUFFERED_CHUNK_FRAMES { shared.finished.store(true, Ordering::Release); }
+						}
+						Err(e) => {
+							on_error(e);
+							shared.finished.store(true, Ordering::Release);
+						}
+					}
+				}
+			}
+		});
+		
+		Self { shared, thread: Some(thread) }
+	}
+	
+	/// Writes interleaved stereo frames from `frame` on into `out`, as many as are decoded already.
+	/// Reading from anywhere but where the last read stopped starts a seek, and reads nothing until the decoding thread has caught up.
+	pub fn read_interleaved(&self, frame: usize, out: &mut [f32]) -> FramesRead {
+		let shared = &self.shared;
+		let nothing = FramesRead { frames: 0, ended: false };
+		if shared.seek.load(Ordering::Acquire) != NO_SEEK { return nothing }
+		if shared.position.load(Ordering::Relaxed) != frame {
+			shared.seek.store(frame, Ordering::Release);
+			return nothing
+		}
+		
+		// Checked before looking at what was written, so a finished stream's last frames are seen too
+		let finished = shared.finished.load(Ordering::Acquire);
+		let written = shared.written.load(Ordering::Acquire);
+		let read = shared.read.load(Ordering::Relaxed);
+		let frames = (written - read).min(out.len() / 2);
+		
+		let capacity = shared.samples.len() / 2;
+		for f in 0..frames {
+			let i = (read + f) % capacity * 2;
+			out[f * 2]     = f32::from_bits(shared.samples[i].load(Ordering::Relaxed));
+			out[f * 2 + 1] = f32::from_bits(shared.samples[i + 1].load(Ordering::Relaxed));
+		}
+		shared.read.store(read + frames, Ordering::Release);
+		shared.position.store(frame + frames, Ordering::Relaxed);
+		
+		FramesRead { frames, ended: finished && read + frames == written }
+	}
+}
+
+impl Drop for BufferedStream {
+	fn drop(&mut self) {
+		self.shared.stop.store(true, Ordering::Relaxed);
+		if let Some(thread) = self.thread.take() { let _ = thread.join(); }
+	}
+}
+
+impl BufferShared {
+	/// Frames the decoding thread can push without overwriting unread ones
+	fn free(&self) -> usize {
+		self.samples.len() / 2 - (self.written.load(Ordering::Relaxed) - self.read.load(Ordering::Acquire))
+	}
+	
+	/// Called with no more frames than `free` allows
+	fn push(&self, interleaved: &[f32]) {
+		let written = self.written.load(Ordering::Relaxed);
+		let capacity = self.samples.len() / 2;
+		for (f, frame) in interleaved.chunks_exact(2).enumerate() {
+			let i = (written + f) % capacity * 2;
+			self.samples[i].store(frame[0].to_bits(), Ordering::Relaxed);
+			self.samples[i + 1].store(frame[1].to_bits(), Ordering::Relaxed);
+		}
+		self.written.store(written + interleaved.len() / 2, Ordering::Release);
+	}
+}
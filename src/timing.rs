@@ -0,0 +1,167 @@
+use std::fmt;
+
+
+/// Resolution of `MusicalTime`, the usual MIDI pulses per quarter note
+pub const TICKS_PER_BEAT: u32 = 960;
+
+
+/// Constant tempo and meter that musical positions are counted in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tempo {
+	pub bpm: f64,
+	pub beats_per_bar: u32,
+}
+
+impl Default for Tempo {
+	fn default() -> Self {
+		Self { bpm: 120.0, beats_per_bar: 4 }
+	}
+}
+
+impl Tempo {
+	pub fn seconds_per_beat(&self) -> f64 {
+		60.0 / self.bpm
+	}
+}
+
+
+/// Position in bars, beats and ticks, each counted from 0. Displays as `bar.beat.tick` counted from 1, like a ruler.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MusicalTime {
+	pub bar: u32,
+	pub beat: u32,
+	pub tick: u32,
+}
+
+impl MusicalTime {
+	/// Nearest tick to a position in beats, positions before 0 are 0
+	pub fn from_beats(beats: f64, tempo: &Tempo) -> Self {
+		let ticks = (beats * TICKS_PER_BEAT as f64).round().max(0.0) as u64;
+		let ticks_per_bar = TICKS_PER_BEAT as u64 * tempo.beats_per_bar.max(1) as u64;
+		Self {
+			bar: (ticks / ticks_per_bar) as u32,
+			beat: (ticks % ticks_per_bar / TICKS_PER_BEAT as u64) as u32,
+			tick: (ticks % TICKS_PER_BEAT as u64) as u32,
+		}
+	}
+	
+	pub fn to_beats(&self, tempo: &Tempo) -> f64 {
+		(self.bar as f64 * tempo.beats_per_bar as f64) + self.beat as f64 + self.tick as f64 / TICKS_PER_BEAT as f64
+	}
+	
+	pub fn from_seconds(seconds: f64, tempo: &Tempo) -> Self {
+		Self::from_beats(seconds / tempo.seconds_per_beat(), tempo)
+	}
+	
+	pub fn to_seconds(&self, tempo: &Tempo) -> f64 {
+		self.to_beats(tempo) * tempo.seconds_per_beat()
+	}
+}
+
+impl fmt::Display for MusicalTime {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}.{}.{:03}", self.bar + 1, self.beat + 1, self.tick)
+	}
+}
+
+
+
+/// SMPTE frame rates
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimecodeRate {
+	Fps24,
+	#[default]
+	Fps25,
+	/// NTSC, 30000/1001 frames a second with frame numbers 0 and 1 skipped each minute except every tenth, so the timecode keeps up with the clock
+	Fps29_97Drop,
+	Fps30,
+}
+
+impl TimecodeRate {
+	/// Frame numbers in each timecode second
+	pub fn frames_per_second(self) -> u32 {
+		match self {
+			TimecodeRate::Fps24 => 24,
+			TimecodeRate::Fps25 => 25,
+			TimecodeRate::Fps29_97Drop | TimecodeRate::Fps30 => 30,
+		}
+	}
+	
+	/// Video frames in a second of real time
+	pub fn fps(self) -> f64 {
+		match self {
+			TimecodeRate::Fps29_97Drop => 30000.0 / 1001.0,
+			_ => self.frames_per_second() as f64,
+		}
+	}
+}
+
+
+/// SMPTE timecode. Displays as `hh:mm:ss:ff`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timecode {
+	pub hours: u32,
+	pub minutes: u32,
+	pub seconds: u32,
+	pub frames: u32,
+}
+
+impl Timecode {
+	/// Timecode of the `count`th video frame from 0
+	pub fn from_frame_count(count: u64, rate: TimecodeRate) -> Self {
+		let fps = rate.frames_per_second() as u64;
+		let label = match rate {
+			TimecodeRate::Fps29_97Drop => {
+				// 17982 frames in every 10 minutes, 1798 in each minute after the first of them
+				let (tens, rest) = (count / 17982, count % 17982);
+				let skipped = 18 * tens + if rest < 2 {0} else {2 * ((rest - 2) / 1798)};
+				count + skipped
+			}
+			_ => count,
+		};
+		Self {
+			hours: (label / (fps * 3600)) as u32,
+			minutes: (label / (fps * 60) % 60) as u32,
+			seconds: (label / fps % 60) as u32,
+			frames: (label % fps) as u32,
+		}
+	}
+	
+	/// Video frames from 0 to this timecode
+	pub fn frame_count(&self, rate: TimecodeRate) -> u64 {
+		let fps = rate.frames_per_second() as u64;
+		let label = ((self.hours as u64 * 60 + self.minutes as u64) * 60 + self.seconds as u64) * fps + self.frames as u64;
+		match rate {
+			TimecodeRate::Fps29_97Drop => {
+				let minutes = self.hours as u64 * 60 + self.minutes as u64;
+				label - 2 * (minutes - minutes / 10)
+			}
+			_ => label,
+		}
+	}
+	
+	/// Timecode of the video frame a time falls in
+	pub fn from_seconds(seconds: f64, rate: TimecodeRate) -> Self {
+		// A little slack so a time computed from a frame count lands back on that frame
+		Self::from_frame_count((seconds.max(0.0) * rate.fps() + 1e-6).floor() as u64, rate)
+	}
+	
+	/// Time the video frame starts at
+	pub fn to_seconds(&self, rate: TimecodeRate) -> f64 {
+		self.frame_count(rate) as f64 / rate.fps()
+	}
+	
+	/// Parses `hh:mm:ss:ff`, with `;` or `.` also accepted before the frames as drop frame timecode is often written
+	pub fn parse(text: &str) -> Option<Self> {
+		let fields = text.trim().split([':', ';', '.']).map(|field| field.parse().ok()).collect::<Option<Vec<u32>>>()?;
+		let [hours, minutes, seconds, frames] = fields[..] else { return None };
+		if minutes >= 60 || seconds >= 60 { return None }
+		Some(Self { hours, minutes, seconds, frames })
+	}
+}
+
+impl fmt::Display for Timecode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:02}:{:02}:{:02}:{:02}", self.hours, self.minutes, self.seconds, self.frames)
+	}
+}
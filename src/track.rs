@@ -1,28 +1,75 @@
-use std::ops::Range;
+use std::{fmt, ops::{Deref, DerefMut, Range}, sync::{Arc, OnceLock}};
 
+use crate::*;
 
+
+/// Audio as an immutable value. Clones, ranges, reversals and channel subsets are views of the same samples,
+/// which get copied only when a view is written to while something else still shares them.
 #[derive(Clone)]
 pub struct AudioTrack<const N: usize> {
-	pub data: [Box<[f32]>; N],
+	pub data: [TrackChannel; N],
+	/// Rate the frames are at, in Hz
+	pub sample_rate: u32,
 }
 
 impl<const N: usize> AudioTrack<N> {
-	pub fn new(frames: usize) -> Self {
+	pub fn new(frames: usize, sample_rate: u32) -> Self {
 		Self {
-			data: core::array::from_fn(|_c| vec![0.0; frames].into_boxed_slice()),
+			data: core::array::from_fn(|_c| TrackChannel::silence(frames)),
+			sample_rate,
 		}
 	}
 	
+	/// View of a range of frames, without copying
 	pub fn clone_range(track: &AudioTrack<N>, range: Range<usize>) -> Self {
 		Self {
-			data: core::array::from_fn(|c| track.data[c][range.clone()].iter().cloned().collect()),
+			data: core::array::from_fn(|c| track.data[c].slice(range.clone())),
+			sample_rate: track.sample_rate,
+		}
+	}
+	
+	/// View of the track played backwards, without copying
+	pub fn reversed(&self) -> Self {
+		Self {
+			data: core::array::from_fn(|c| self.data[c].reversed()),
+			sample_rate: self.sample_rate,
 		}
 	}
 	
-	pub fn copy_from_range(&mut self, range: Range<usize>, other_track: &AudioTrack<N>, other_range: Range<usize>) {
+	/// View of some of the channels, in the given order, without copying. A channel can be picked more than once.
+	/// Panics on a channel out of range, so callers check them first.
+	pub(crate) fn select_channels<const M: usize>(&self, channels: [usize; M]) -> AudioTrack<M> {
+		AudioTrack {
+			data: channels.map(|c| self.data[c].clone()),
+			sample_rate: self.sample_rate,
+		}
+	}
+	
+	/// Fails without copying anything if the tracks are at different rates, or the ranges are out of bounds or of different lengths
+	pub fn copy_from_range(&mut self, range: Range<usize>, other_track: &AudioTrack<N>, other_range: Range<usize>) -> Result<(), TrackError> {
+		self.check_rate(other_track)?;
+		self.check_range(&range)?;
+		other_track.check_range(&other_range)?;
+		if range.len() != other_range.len() { return Err(TrackError::LengthMismatch { expected: range.len(), found: other_range.len() }) }
 		for c in 0..N {
-			self.data[c][range.clone()].copy_from_slice(&other_track.data[c][other_range.clone()]);
+			self.data[c][range.clone()].copy_from_slice(&other_track.data[c].slice(other_range.clone()));
 		}
+		Ok(())
+	}
+	
+	/// Tracks can only be combined frame by frame at the same rate
+	pub fn check_rate<const M: usize>(&self, other_track: &AudioTrack<M>) -> Result<(), TrackError> {
+		if other_track.sample_rate != self.sample_rate {
+			return Err(TrackError::RateMismatch { expected: self.sample_rate, found: other_track.sample_rate })
+		}
+		Ok(())
+	}
+	
+	pub fn check_range(&self, range: &Range<usize>) -> Result<(), TrackError> {
+		if range.start > range.end || range.end > self.length() {
+			return Err(TrackError::OutOfRange { range: range.clone(), length: self.length() })
+		}
+		Ok(())
 	}
 	
 	pub fn length(&self) -> usize {
@@ -32,20 +79,450 @@ impl<const N: usize> AudioTrack<N> {
 		}
 	}
 	
+	/// Length in seconds
+	pub fn duration(&self) -> f64 {
+		self.frames_to_seconds(self.length())
+	}
+	
+	pub fn frames_to_seconds(&self, frames: usize) -> f64 {
+		frames as f64 / self.sample_rate as f64
+	}
+	
+	/// Nearest frame to a time, times before 0 are frame 0
+	pub fn seconds_to_frames(&self, seconds: f64) -> usize {
+		(seconds * self.sample_rate as f64).round().max(0.0) as usize
+	}
+	
+	pub fn frames_to_musical(&self, frames: usize, tempo: &Tempo) -> MusicalTime {
+		MusicalTime::from_seconds(self.frames_to_seconds(frames), tempo)
+	}
+	
+	pub fn musical_to_frames(&self, time: &MusicalTime, tempo: &Tempo) -> usize {
+		self.seconds_to_frames(time.to_seconds(tempo))
+	}
+	
+	/// Timecode of the video frame a frame falls in
+	pub fn frames_to_timecode(&self, frames: usize, rate: TimecodeRate) -> Timecode {
+		Timecode::from_seconds(self.frames_to_seconds(frames), rate)
+	}
+	
+	/// First frame of a timecode's video frame
+	pub fn timecode_to_frames(&self, timecode: &Timecode, rate: TimecodeRate) -> usize {
+		self.seconds_to_frames(timecode.to_seconds(rate))
+	}
+	
 	pub fn get_slice(&self, range: Range<usize>) -> [&[f32]; N] {
 		core::array::from_fn(|c| &self.data[c][range.clone()])
 	}
+	
+	pub fn get_slice_mut(&mut self, range: Range<usize>) -> [&mut [f32]; N] {
+		self.data.each_mut().map(|channel| &mut channel[range.clone()])
+	}
+	
+	
+	// Edits leave the track alone and return a new one. Results that are a single range of one track are views of it, the rest are copied once.
+	
+	/// The frames in a range, as a view
+	pub fn trim(&self, range: Range<usize>) -> Result<Self, TrackError> {
+		self.check_range(&range)?;
+		Ok(Self::clone_range(self, range))
+	}
+	
+	/// This track followed by another
+	pub fn concat(&self, other: &AudioTrack<N>) -> Result<Self, TrackError> {
+		self.check_rate(other)?;
+		Ok(self.join_parts(&[(self, 0..self.length()), (other, 0..other.length())]))
+	}
+	
+	/// Another track inserted before `frame`, pushing the rest back
+	pub fn insert(&self, frame: usize, other: &AudioTrack<N>) -> Result<Self, TrackError> {
+		self.check_rate(other)?;
+		self.check_range(&(frame..frame))?;
+		Ok(self.join_parts(&[(self, 0..frame), (other, 0..other.length()), (self, frame..self.length())]))
+	}
+	
+	/// The track with a range cut out and the rest closing the gap
+	pub fn delete(&self, range: Range<usize>) -> Result<Self, TrackError> {
+		self.check_range(&range)?;
+		Ok(self.join_parts(&[(self, 0..range.start), (self, range.end..self.length())]))
+	}
+	
+	/// The track with a range played backwards
+	pub fn reverse_range(&self, range: Range<usize>) -> Result<Self, TrackError> {
+		self.check_range(&range)?;
+		let reversed = Self::clone_range(self, range.clone()).reversed();
+		Ok(self.join_parts(&[(self, 0..range.start), (&reversed, 0..reversed.length()), (self, range.end..self.length())]))
+	}
+	
+	/// Another track written over the frames from `frame` on, making the track longer if it runs past the end
+	pub fn overwrite(&self, frame: usize, other: &AudioTrack<N>) -> Result<Self, TrackError> {
+		self.check_rate(other)?;
+		self.check_range(&(frame..frame))?;
+		let end = (frame + other.length()).min(self.length());
+		Ok(self.join_parts(&[(self, 0..frame), (other, 0..other.length()), (self, end..self.length())]))
+	}
+	
+	/// Another track scaled by a linear `gain` and added from `offset` on, making the track longer if it runs past the end
+	pub fn mix(&self, other: &AudioTrack<N>, offset: usize, gain: f32) -> Result<Self, TrackError> {
+		self.check_rate(other)?;
+		self.check_range(&(offset..offset))?;
+		
+		let length = self.length().max(offset + other.length());
+		// Nothing to add, but silent tracks still lengthen the result when they run past the end
+		if (other.length() == 0 || gain == 0.0) && length == self.length() { return Ok(self.clone()) }
+		
+		Ok(Self {
+			data: core::array::from_fn(|c| {
+				let mut samples = Vec::with_capacity(length);
+				samples.extend(self.data[c].iter());
+				samples.resize(length, 0.0);
+				for (sample, other) in samples[offset..].iter_mut().zip(other.data[c].iter()) { *sample += gain * other; }
+				TrackChannel::from(samples)
+			}),
+			sample_rate: self.sample_rate,
+		})
+	}
+	
+	/// The track played `times` times in a row
+	pub fn repeat(&self, times: usize) -> Self {
+		self.join_parts(&vec![(self, 0..self.length()); times])
+	}
+	
+	/// The track faded in over its first `frames`, from silence at the first frame to full gain at the last
+	pub fn fade_in(&self, frames: usize, curve: &FadeCurve) -> Result<Self, TrackError> {
+		self.check_range(&(0..frames))?;
+		curve.check()?;
+		let faded = self.faded(0..frames, |i| curve.gain(fade_position(i, frames)));
+		Ok(self.join_parts(&[(&faded, 0..frames), (self, frames..self.length())]))
+	}
+	
+	/// The track faded out over its last `frames`, from full gain at the first to silence at the last frame
+	pub fn fade_out(&self, frames: usize, curve: &FadeCurve) -> Result<Self, TrackError> {
+		self.check_range(&(0..frames))?;
+		curve.check()?;
+		let start = self.length() - frames;
+		let faded = self.faded(start..self.length(), |i| curve.gain(fade_position(frames - 1 - i, frames)));
+		Ok(self.join_parts(&[(self, 0..start), (&faded, 0..frames)]))
+	}
+	
+	/// This track followed by another, overlapping by `frames` as this one fades out and the other fades in
+	pub fn crossfade(&self, other: &AudioTrack<N>, frames: usize, curve: &FadeCurve) -> Result<Self, TrackError> {
+		self.check_rate(other)?;
+		self.check_range(&(0..frames))?;
+		other.check_range(&(0..frames))?;
+		curve.check()?;
+		let start = self.length() - frames;
+		let overlap = self.crossfaded(start, other, 0, frames, curve);
+		Ok(self.join_parts(&[(self, 0..start), (&overlap, 0..frames), (other, frames..other.length())]))
+	}
+	
+	/// Another track put in place of a range, crossfading over `frames` at both joins.
+	/// The outgoing side plays on past each join as the incoming one fades in, so the result is as long as with hard cuts.
+	pub fn splice(&self, range: Range<usize>, other: &AudioTrack<N>, frames: usize, curve: &FadeCurve) -> Result<Self, TrackError> {
+		self.check_rate(other)?;
+		self.check_range(&range)?;
+		// The fade into the other track reads on from the start of the range, the fade back reads up to its end
+		self.check_range(&(range.start..range.start + frames))?;
+		let tail = range.end.checked_sub(frames).ok_or(TrackError::OutOfRange { range: range.clone(), length: self.length() })?;
+		other.check_range(&(0..2 * frames))?;
+		curve.check()?;
+		
+		let other_end = other.length() - frames;
+		let fade_in = self.crossfaded(range.start, other, 0, frames, curve);
+		let fade_back = other.crossfaded(other_end, self, tail, frames, curve);
+		Ok(self.join_parts(&[
+			(self, 0..range.start),
+			(&fade_in, 0..frames),
+			(other, frames..other_end),
+			(&fade_back, 0..frames),
+			(self, range.end..self.length()),
+		]))
+	}
+	
+	/// A range of the track with each frame scaled by `gain(index in range)`
+	fn faded(&self, range: Range<usize>, gain: impl Fn(usize) -> f32) -> Self {
+		Self {
+			data: core::array::from_fn(|c| self.data[c].slice(range.clone()).iter().enumerate().map(|(i, sample)| sample * gain(i)).collect()),
+			sample_rate: self.sample_rate,
+		}
+	}
+	
+	/// `frames` of this track from `start` fading out over the same number of the other from `other_start` fading in.
+	/// Gains are taken at the middle of each frame, so the two sides are balanced the same way at every frame.
+	fn crossfaded(&self, start: usize, other: &AudioTrack<N>, other_start: usize, frames: usize, curve: &FadeCurve) -> Self {
+		Self {
+			data: core::array::from_fn(|c| {
+				let outgoing = self.data[c].slice(start..start + frames);
+				let incoming = other.data[c].slice(other_start..other_start + frames);
+				outgoing.iter().zip(incoming.iter()).enumerate().map(|(i, (out, inc))| {
+					let x = (i as f32 + 0.5) / frames as f32;
+					out * curve.gain(1.0 - x) + inc * curve.gain(x)
+				}).collect()
+			}),
+			sample_rate: self.sample_rate,
+		}
+	}
+	
+	/// Frames from each part in turn, all at this track's rate
+	fn join_parts(&self, parts: &[(&AudioTrack<N>, Range<usize>)]) -> Self {
+		let parts = parts.iter().filter(|(_, range)| !range.is_empty()).collect::<Vec<_>>();
+		match parts[..] {
+			[] => Self::new(0, self.sample_rate),
+			[(track, range)] => Self::clone_range(track, range.clone()),
+			_ => {
+				let length = parts.iter().map(|(_, range)| range.len()).sum();
+				Self {
+					data: core::array::from_fn(|c| {
+						let mut samples = Vec::with_capacity(length);
+						for (track, range) in &parts { samples.extend(track.data[c].slice(range.clone()).iter()); }
+						TrackChannel::from(samples)
+					}),
+					sample_rate: self.sample_rate,
+				}
+			}
+		}
+	}
 }
 
-impl AudioTrack<2> {
-	pub fn get_slice_mut(&mut self, range: Range<usize>) -> [&mut [f32]; 2] {
-		let data_mut = self.data.split_at_mut(1);
-		[
-			data_mut.0[0][range.clone()].as_mut(),
-			data_mut.1[0][range].as_mut(),
-		]
+
+/// How far through a fade of `frames` frame `i` is, from 0 at the first frame to 1 at the last. A one-frame fade is only its silent end.
+fn fade_position(i: usize, frames: usize) -> f32 {
+	if frames <= 1 { return 0.0 }
+	i as f32 / (frames - 1) as f32
+}
+
+
+/// Samples of one channel: a range of a buffer shared between views, read forwards or backwards.
+/// Reading through `iter` and `get` never copies. Reading a reversed view as a slice copies it once, into a cache the view's clones share.
+/// Writing copies the samples first unless this view is the only one left on its buffer.
+#[derive(Clone)]
+pub struct TrackChannel {
+	buffer: Arc<[f32]>,
+	start: usize,
+	len: usize,
+	reversed: bool,
+	forwards: Arc<OnceLock<Box<[f32]>>>,
+}
+
+impl TrackChannel {
+	pub fn silence(frames: usize) -> Self {
+		Self::from(std::iter::repeat_n(0.0, frames).collect::<Arc<[f32]>>())
+	}
+	
+	pub fn len(&self) -> usize {
+		self.len
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+	
+	pub fn get(&self, frame: usize) -> Option<f32> {
+		if frame >= self.len { return None }
+		Some(self.buffer[self.start + if self.reversed {self.len - 1 - frame} else {frame}])
+	}
+	
+	pub fn iter(&self) -> TrackChannelIter<'_> {
+		TrackChannelIter { samples: self.buffer[self.start..(self.start + self.len)].iter(), reversed: self.reversed }
+	}
+	
+	/// View of a range of frames. Panics if the range is out of bounds, like slicing.
+	pub fn slice(&self, range: Range<usize>) -> Self {
+		assert!(range.start <= range.end && range.end <= self.len, "range {range:?} out of bounds for a channel of {} frames", self.len);
+		let start = if self.reversed {self.start + self.len - range.end} else {self.start + range.start};
+		Self { buffer: self.buffer.clone(), start, len: range.len(), reversed: self.reversed, forwards: Arc::default() }
+	}
+	
+	pub fn reversed(&self) -> Self {
+		Self { buffer: self.buffer.clone(), start: self.start, len: self.len, reversed: !self.reversed, forwards: Arc::default() }
+	}
+	
+	/// Whether both views read the same samples of the same buffer, so neither was copied
+	pub fn shares_samples(&self, other: &TrackChannel) -> bool {
+		Arc::ptr_eq(&self.buffer, &other.buffer) && (self.start, self.len, self.reversed) == (other.start, other.len, other.reversed)
+	}
+	
+	/// The samples for writing, copied first into a buffer of this view's own if the current one is shared or read backwards
+	pub fn make_mut(&mut self) -> &mut [f32] {
+		if self.reversed || Arc::get_mut(&mut self.buffer).is_none() {
+			*self = Self::from(self.iter().copied().collect::<Arc<[f32]>>());
+		}
+		let range = self.start..(self.start + self.len);
+		&mut Arc::get_mut(&mut self.buffer).expect("buffer was just made unique")[range]
+	}
+}
+
+impl Deref for TrackChannel {
+	type Target = [f32];
+	
+	fn deref(&self) -> &[f32] {
+		if self.reversed {
+			return self.forwards.get_or_init(|| self.iter().copied().collect())
+		}
+		&self.buffer[self.start..(self.start + self.len)]
+	}
+}
+
+impl DerefMut for TrackChannel {
+	fn deref_mut(&mut self) -> &mut [f32] {
+		self.make_mut()
+	}
+}
+
+impl AsRef<[f32]> for TrackChannel {
+	fn as_ref(&self) -> &[f32] {
+		self
+	}
+}
+
+impl AsMut<[f32]> for TrackChannel {
+	fn as_mut(&mut self) -> &mut [f32] {
+		self.make_mut()
+	}
+}
+
+impl From<Arc<[f32]>> for TrackChannel {
+	fn from(buffer: Arc<[f32]>) -> Self {
+		Self { start: 0, len: buffer.len(), buffer, reversed: false, forwards: Arc::default() }
+	}
+}
+
+impl From<Vec<f32>> for TrackChannel {
+	fn from(samples: Vec<f32>) -> Self {
+		Self::from(Arc::<[f32]>::from(samples))
+	}
+}
+
+impl FromIterator<f32> for TrackChannel {
+	fn from_iter<I>(iter: I) -> Self where I: IntoIterator<Item = f32> {
+		Self::from(iter.into_iter().collect::<Arc<[f32]>>())
+	}
+}
+
+impl PartialEq for TrackChannel {
+	fn eq(&self, other: &Self) -> bool {
+		self.len == other.len && self.iter().eq(other.iter())
+	}
+}
+
+impl fmt::Debug for TrackChannel {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_list().entries(self.iter()).finish()
+	}
+}
+
+
+pub struct TrackChannelIter<'a> {
+	samples: std::slice::Iter<'a, f32>,
+	reversed: bool,
+}
+
+impl<'a> Iterator for TrackChannelIter<'a> {
+	type Item = &'a f32;
+	
+	fn next(&mut self) -> Option<&'a f32> {
+		if self.reversed {self.samples.next_back()} else {self.samples.next()}
+	}
+	
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.samples.size_hint()
+	}
+}
+
+impl DoubleEndedIterator for TrackChannelIter<'_> {
+	fn next_back(&mut self) -> Option<Self::Item> {
+		if self.reversed {self.samples.next()} else {self.samples.next_back()}
 	}
 }
 
+impl ExactSizeIterator for TrackChannelIter<'_> {}
 
 
+#[cfg(test)]
+mod tests {
+	use super::*;
+	
+	fn samples(frames: usize) -> TrackChannel {
+		(0..frames).map(|i| i as f32).collect()
+	}
+	
+	#[test]
+	fn writing_a_shared_channel_copies_it() {
+		let original = samples(8);
+		let mut copy = original.clone();
+		assert!(copy.shares_samples(&original));
+		copy[0] = -1.0;
+		assert!(!copy.shares_samples(&original));
+		assert_eq!(original[..], samples(8)[..]);
+		assert_eq!(copy[..2], [-1.0, 1.0]);
+	}
+	
+	#[test]
+	fn writing_an_unshared_channel_does_not_copy() {
+		let mut channel = samples(8);
+		let buffer = channel.as_ptr();
+		channel[0] = -1.0;
+		assert_eq!(channel.as_ptr(), buffer);
+		
+		// A range left on its own is written in place too, at its own offset
+		let mut range = channel.slice(2..5);
+		drop(channel);
+		let buffer = range.as_ptr();
+		range[0] = -2.0;
+		assert_eq!(range.as_ptr(), buffer);
+		assert_eq!(range[..], [-2.0, 3.0, 4.0]);
+	}
+	
+	#[test]
+	fn writing_a_range_leaves_the_channel_alone() {
+		let mut channel = samples(8);
+		let mut range = channel.slice(2..5);
+		range[0] = -1.0;
+		assert_eq!(channel[..], samples(8)[..]);
+		assert_eq!(range[..], [-1.0, 3.0, 4.0]);
+		
+		let range = channel.slice(2..5);
+		channel[2] = -1.0;
+		assert_eq!(range[..], [2.0, 3.0, 4.0]);
+		assert_eq!(channel[..3], [0.0, 1.0, -1.0]);
+	}
+	
+	#[test]
+	fn writing_a_reversed_channel_leaves_the_original_alone() {
+		let mut channel = samples(4);
+		let mut reversed = channel.reversed();
+		assert_eq!(reversed[..], [3.0, 2.0, 1.0, 0.0]);
+		reversed[0] = -1.0;
+		assert_eq!(channel[..], [0.0, 1.0, 2.0, 3.0]);
+		assert_eq!(reversed[..], [-1.0, 2.0, 1.0, 0.0]);
+		
+		// Writing the original afterwards copies it, so a reversed view and the cache its clones share keep their samples
+		let reversed = channel.reversed();
+		let cached = reversed.clone();
+		assert_eq!(cached[..], [3.0, 2.0, 1.0, 0.0]);
+		channel[3] = -1.0;
+		assert_eq!(reversed[..], [3.0, 2.0, 1.0, 0.0]);
+		assert_eq!(reversed.slice(0..2)[..], [3.0, 2.0]);
+		assert_eq!(channel[..], [0.0, 1.0, 2.0, -1.0]);
+	}
+	
+	#[test]
+	fn writing_a_view_of_a_track_leaves_the_track_alone() {
+		let track = AudioTrack { data: [samples(4), samples(4).reversed()], sample_rate: 48000 };
+		
+		let mut range = AudioTrack::clone_range(&track, 1..3);
+		range.data[1][0] = -1.0;
+		assert_eq!(track.data[1][..], [3.0, 2.0, 1.0, 0.0]);
+		assert_eq!(range.data[1][..], [-1.0, 1.0]);
+		
+		let mut picked = track.select_channels([0, 0]);
+		picked.data[0][0] = -1.0;
+		assert_eq!(picked.data[1][..], samples(4)[..]);
+		assert_eq!(track.data[0][..], samples(4)[..]);
+		
+		let mut reversed = track.reversed();
+		reversed.data[0][0] = -1.0;
+		assert_eq!(track.data[0][..], samples(4)[..]);
+		assert_eq!(reversed.data[0][..], [-1.0, 2.0, 1.0, 0.0]);
+	}
+}
@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+
+use sfx_daw::*;
+
+mod common;
+use common::*;
+
+
+/// Empty directory with a short tone and a sound definition named `name` that layers it
+fn sound_dir(test: &str, name: &str) -> (PathBuf, SoundDefinition) {
+	let dir = temp_dir(test);
+	
+	let settings = ProjectSettings::default();
+	let mut tone = AudioTrack::<2>::new(4800, settings.rate());
+	for channel in &mut tone.data {
+		for (i, sample) in channel.iter_mut().enumerate() { *sample = 0.5 * (i as f32 * 0.05).sin(); }
+	}
+	export_wav(&tone, dir.join("tone.wav"), &settings, &WavExportOptions::default()).unwrap();
+	std::fs::write(dir.join("tone.sound"), format!("name = {name}\nlayer = tone.wav\ngain = -6 +- 3\n")).unwrap();
+	
+	let definition = SoundDefinition::from_file(dir.join("tone.sound")).unwrap();
+	(dir, definition)
+}
+
+
+#[test]
+fn renders_into_output_dir() {
+	let (dir, definition) = sound_dir("batch_renders", "tone");
+	let output_dir = dir.join("out");
+	let options = BatchOptions { variations: BatchVariations::Seeds { count: 2, first_seed: 0 }, output_dir: output_dir.clone(), ..Default::default() };
+	
+	let manifest = render_batch(&definition, &ProjectSettings::default(), &options).unwrap();
+	assert_eq!(manifest.renders.iter().map(|render| render.file.as_str()).collect::<Vec<_>>(), ["tone_01.wav", "tone_02.wav"]);
+	assert_eq!(files_in(&output_dir), ["tone_01.wav", "tone_02.wav", "tone_batch.json"]);
+	
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn names_stay_inside_output_dir() {
+	let (dir, definition) = sound_dir("batch_templates", "tone");
+	let output_dir = dir.join("out");
+	
+	for template in ["../{name}_{index}", "sub/{name}", "sub\\{name}", "/tmp/{name}", "{name}/"] {
+		let options = BatchOptions { template: template.into(), output_dir: output_dir.clone(), ..Default::default() };
+		let result = render_batch(&definition, &ProjectSettings::default(), &options);
+		assert!(matches!(result, Err(RenderError::Template(_))), "{template}");
+	}
+	assert!(!output_dir.exists());
+	assert_eq!(files_in(&dir), ["tone.sound", "tone.wav"]);
+	
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn sound_names_stay_inside_output_dir() {
+	for name in ["../tone", "sub/tone"] {
+		let (dir, definition) = sound_dir("batch_names", name);
+		let output_dir = dir.join("out");
+		// The default template is fine, but the name goes into it and into the manifest's name
+		let result = render_batch(&definition, &ProjectSettings::default(), &BatchOptions { output_dir: output_dir.clone(), ..Default::default() });
+		assert!(matches!(result, Err(RenderError::Template(_))), "{name}");
+		assert!(!output_dir.exists());
+		assert_eq!(files_in(&dir), ["tone.sound", "tone.wav"]);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
+
+#[test]
+fn dots_in_names_get_the_extension() {
+	let (dir, definition) = sound_dir("batch_dots", "kick_0.5");
+	let output_dir = dir.join("out");
+	
+	for (template, file) in [("{name}", "kick_0.5.wav"), ("{name}.v{index}", "kick_0.5.v1.wav"), ("{name}.WAV", "kick_0.5.WAV"), ("{name}.flac", "kick_0.5.flac")] {
+		let options = BatchOptions { template: template.into(), output_dir: output_dir.clone(), ..Default::default() };
+		let manifest = render_batch(&definition, &ProjectSettings::default(), &options).unwrap();
+		assert_eq!(manifest.renders[0].file, file, "{template}");
+		assert!(output_dir.join(file).exists(), "{template}");
+	}
+	
+	std::fs::remove_dir_all(dir).unwrap();
+}
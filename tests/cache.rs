@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use sfx_daw::*;
+
+mod common;
+use common::*;
+
+
+fn entries(cache: &AudioCache) -> Vec<PathBuf> {
+	files_in(cache.dir()).into_iter().filter(|file| file.ends_with(".sfxc")).map(|file| cache.dir().join(file)).collect()
+}
+
+fn source(dir: &Path, name: &str) -> PathBuf {
+	let path = dir.join(name);
+	export_wav(&test_track(5000, ExportFormat::Pcm24), &path, &ProjectSettings::default(), &WavExportOptions::default()).unwrap();
+	path
+}
+
+
+#[test]
+fn stored_track_is_found_again() {
+	let dir = temp_dir("cache_hit");
+	let cache = AudioCache::new(dir.join("cache")).unwrap();
+	let settings = ProjectSettings::default();
+	let path = source(&dir, "a.wav");
+	
+	assert!(cache.lookup(&path, &LoadOptions::default(), &settings).is_none());
+	let loaded = cache.load(&path, &LoadOptions::default(), &settings).unwrap();
+	let cached = cache.lookup(&path, &LoadOptions::default(), &settings).unwrap();
+	assert!(cached.track.data == loaded.track.data);
+	assert_eq!(cached.metadata, loaded.metadata);
+	
+	// Other settings are other entries
+	let other = ProjectSettings { sample_rate: ProjectRate::Hz96000, ..settings };
+	assert!(cache.lookup(&path, &LoadOptions::default(), &other).is_none());
+	
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn entries_follow_contents_not_paths() {
+	let dir = temp_dir("cache_contents");
+	let cache = AudioCache::new(dir.join("cache")).unwrap();
+	let settings = ProjectSettings::default();
+	let path = source(&dir, "a.wav");
+	cache.load(&path, &LoadOptions::default(), &settings).unwrap();
+	
+	// Moved, and copied to another name
+	let moved = dir.join("moved.wav");
+	std::fs::rename(&path, &moved).unwrap();
+	assert!(cache.lookup(&moved, &LoadOptions::default(), &settings).is_some());
+	std::fs::copy(&moved, dir.join("copy.wav")).unwrap();
+	cache.load(dir.join("copy.wav"), &LoadOptions::default(), &settings).unwrap();
+	assert_eq!(entries(&cache).len(), 1);
+	
+	// Changed contents miss
+	export_wav(&test_track(5000, ExportFormat::Pcm16), &moved, &settings, &WavExportOptions::default()).unwrap();
+	assert!(cache.lookup(&moved, &LoadOptions::default(), &settings).is_none());
+	
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn damaged_entries_miss() {
+	let dir = temp_dir("cache_damaged");
+	let cache = AudioCache::new(dir.join("cache")).unwrap();
+	let settings = ProjectSettings::default();
+	let path = source(&dir, "a.wav");
+	cache.load(&path, &LoadOptions::default(), &settings).unwrap();
+	let entry = entries(&cache).remove(0);
+	let bytes = std::fs::read(&entry).unwrap();
+	
+	// A frame count far past the end of the file, then a file cut off in the samples
+	let mut huge = bytes.clone();
+	huge[40..48].copy_from_slice(&(u64::MAX / 4).to_le_bytes());
+	std::fs::write(&entry, huge).unwrap();
+	assert!(cache.lookup(&path, &LoadOptions::default(), &settings).is_none());
+	
+	std::fs::write(&entry, &bytes[..1000]).unwrap();
+	assert!(cache.lookup(&path, &LoadOptions::default(), &settings).is_none());
+	
+	// Decoding again replaces the entry
+	cache.load(&path, &LoadOptions::default(), &settings).unwrap();
+	assert!(cache.lookup(&path, &LoadOptions::default(), &settings).is_some());
+	
+	std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,105 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use sfx_daw::*;
+
+
+const M3: f32 = FRAC_1_SQRT_2;
+
+fn gains(from: usize, to: usize) -> Vec<Vec<f32>> {
+	ChannelMatrix::conversion(from, to, MonoLaw::Average).unwrap().gains
+}
+
+
+#[test]
+fn same_layout_passes_through() {
+	for channels in [1, 2, 4, 6, 8] {
+		let identity = (0..channels).map(|output| (0..channels).map(|input| if input == output {1.0} else {0.0}).collect()).collect::<Vec<Vec<f32>>>();
+		assert_eq!(gains(channels, channels), identity, "{channels} channels");
+	}
+}
+
+#[test]
+fn fold_down() {
+	// Quad is FL FR RL RR, 5.1 is FL FR FC LFE RL RR, 7.1 adds SL SR
+	assert_eq!(gains(4, 2), [
+		vec![1.0, 0.0, M3, 0.0],
+		vec![0.0, 1.0, 0.0, M3],
+	]);
+	// Centre at -3 dB in both, LFE dropped
+	assert_eq!(gains(6, 2), [
+		vec![1.0, 0.0, M3, 0.0, M3, 0.0],
+		vec![0.0, 1.0, M3, 0.0, 0.0, M3],
+	]);
+	assert_eq!(gains(8, 2), [
+		vec![1.0, 0.0, M3, 0.0, M3, 0.0, M3, 0.0],
+		vec![0.0, 1.0, M3, 0.0, 0.0, M3, 0.0, M3],
+	]);
+	// Centre into the fronts, rears kept
+	assert_eq!(gains(6, 4), [
+		vec![1.0, 0.0, M3, 0.0, 0.0, 0.0],
+		vec![0.0, 1.0, M3, 0.0, 0.0, 0.0],
+		vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+		vec![0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+	]);
+	// Sides into the rears
+	assert_eq!(gains(8, 6), [
+		vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+		vec![0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+		vec![0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+		vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+		vec![0.0, 0.0, 0.0, 0.0, 1.0, 0.0, M3, 0.0],
+		vec![0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, M3],
+	]);
+}
+
+#[test]
+fn mono_goes_through_stereo() {
+	assert_eq!(gains(2, 1), [vec![0.5, 0.5]]);
+	assert_eq!(ChannelMatrix::conversion(2, 1, MonoLaw::EqualPower).unwrap().gains, [vec![M3, M3]]);
+	assert_eq!(ChannelMatrix::conversion(2, 1, MonoLaw::Sum).unwrap().gains, [vec![1.0, 1.0]]);
+	assert_eq!(gains(6, 1), [vec![0.5, 0.5, M3, 0.0, 0.5 * M3, 0.5 * M3]]);
+}
+
+#[test]
+fn upmix() {
+	// Mono to both fronts without a centre, to the centre with one
+	assert_eq!(gains(1, 2), [vec![1.0], vec![1.0]]);
+	assert_eq!(gains(1, 4), [vec![1.0], vec![1.0], vec![0.0], vec![0.0]]);
+	assert_eq!(gains(1, 6), [vec![0.0], vec![0.0], vec![1.0], vec![0.0], vec![0.0], vec![0.0]]);
+	assert_eq!(gains(1, 8), [vec![0.0], vec![0.0], vec![1.0], vec![0.0], vec![0.0], vec![0.0], vec![0.0], vec![0.0]]);
+	// Wider layouts only gain silent channels
+	assert_eq!(gains(2, 6), [
+		vec![1.0, 0.0],
+		vec![0.0, 1.0],
+		vec![0.0, 0.0],
+		vec![0.0, 0.0],
+		vec![0.0, 0.0],
+		vec![0.0, 0.0],
+	]);
+	assert_eq!(gains(4, 8), [
+		vec![1.0, 0.0, 0.0, 0.0],
+		vec![0.0, 1.0, 0.0, 0.0],
+		vec![0.0, 0.0, 0.0, 0.0],
+		vec![0.0, 0.0, 0.0, 0.0],
+		vec![0.0, 0.0, 1.0, 0.0],
+		vec![0.0, 0.0, 0.0, 1.0],
+		vec![0.0, 0.0, 0.0, 0.0],
+		vec![0.0, 0.0, 0.0, 0.0],
+	]);
+}
+
+#[test]
+fn no_standard_layout() {
+	assert!(ChannelMatrix::conversion(7, 2, MonoLaw::Average).is_none());
+	assert!(ChannelMatrix::conversion(2, 0, MonoLaw::Average).is_none());
+}
+
+#[test]
+fn apply_matrix_mixes_channels() {
+	let mut track = AudioTrack::<6>::new(2, 48000);
+	for (c, channel) in track.data.iter_mut().enumerate() { channel[0] = 0.1 * (c + 1) as f32; }
+	let stereo = track.convert_channels::<2>().unwrap();
+	assert_eq!(stereo.data[0][0], 0.1 + M3 * 0.3 + M3 * 0.5);
+	assert_eq!(stereo.data[1][0], 0.2 + M3 * 0.3 + M3 * 0.6);
+	assert_eq!(stereo.data[0][1], 0.0);
+}
@@ -0,0 +1,65 @@
+//! Helpers shared by the integration tests. Each test crate only uses some of them.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use sfx_daw::*;
+
+
+/// Path for a test file in the temp directory, prefixed with the process id so parallel runs don't collide
+pub fn temp_path(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join("sfx_daw_tests");
+	std::fs::create_dir_all(&dir).unwrap();
+	dir.join(format!("{}-{name}", std::process::id()))
+}
+
+/// Empty directory at `temp_path(name)`
+pub fn temp_dir(name: &str) -> PathBuf {
+	let dir = temp_path(name);
+	let _ = std::fs::remove_dir_all(&dir);
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// Names of the files in a directory, sorted
+pub fn files_in(dir: &Path) -> Vec<String> {
+	let mut files = std::fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect::<Vec<_>>();
+	files.sort();
+	files
+}
+
+
+/// Deterministic noise from -0.5 to 0.5, a linear congruential generator
+pub struct Noise(u32);
+
+impl Noise {
+	pub fn new() -> Self {
+		Self(1)
+	}
+	
+	pub fn next(&mut self) -> f32 {
+		self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+		(self.0 >> 8) as f32 / (1 << 24) as f32 - 0.5
+	}
+}
+
+/// Partials and a little noise at the project rate, rounded to values `format` holds exactly unless it's float,
+/// so exports with `Dither::None` have to read back bit-exact
+pub fn test_track(frames: usize, format: ExportFormat) -> AudioTrack<2> {
+	let round = |sample: f32| match format {
+		ExportFormat::Float32 => sample,
+		_ => {
+			let scale = (1 << (format.bits() - 1)) as f32;
+			(sample * scale).round() / scale
+		}
+	};
+	let mut track = AudioTrack::new(frames, ProjectSettings::default().rate());
+	let mut noise = Noise::new();
+	for i in 0..frames {
+		let noise = noise.next();
+		let tone = 0.6 * (i as f32 * 0.01).sin() + 0.2 * (i as f32 * 0.173).sin();
+		track.data[0][i] = round(tone + 0.01 * noise);
+		track.data[1][i] = round(0.9 * tone - 0.003 * noise);
+	}
+	track
+}
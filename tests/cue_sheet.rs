@@ -0,0 +1,69 @@
+use sfx_daw::*;
+
+mod common;
+use common::*;
+
+
+fn tag<'a>(loaded: &'a LoadedTrack, key: &str) -> Option<&'a str> {
+	loaded.metadata.tags.iter().find(|tag| tag.key == key).map(|tag| tag.value.as_str())
+}
+
+
+#[test]
+fn sheet_without_usable_tracks_is_an_error() {
+	let path = temp_path("cue_empty.wav");
+	export_wav(&test_track(48000, ExportFormat::Pcm24), &path, &ProjectSettings::default(), &WavExportOptions::default()).unwrap();
+	
+	let empty = CueSheet::default();
+	let result = load_audio_split(AudioSource::from(path.as_path()), &LoadOptions::default(), Some(&empty), &ProjectSettings::default());
+	assert!(matches!(result, Err(LoadError::CueSheet(_))));
+	
+	let negative = CueSheet { tracks: vec![CueTrack { number: 1, start: -1.0, ..Default::default() }], ..Default::default() };
+	let result = load_audio_split(AudioSource::from(path.as_path()), &LoadOptions::default(), Some(&negative), &ProjectSettings::default());
+	assert!(matches!(result, Err(LoadError::CueSheet(_))));
+	
+	std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn file_named_twice_loads_once_in_sheet_order() {
+	let dir = temp_dir("cue_files");
+	let settings = ProjectSettings::default();
+	export_wav(&test_track(3 * 48000, ExportFormat::Pcm24), dir.join("a.wav"), &settings, &WavExportOptions::default()).unwrap();
+	export_wav(&test_track(48000, ExportFormat::Pcm24), dir.join("b.wav"), &settings, &WavExportOptions::default()).unwrap();
+	std::fs::write(dir.join("album.cue"), concat!(
+		"FILE \"a.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n",
+		"FILE \"b.wav\" WAVE\n  TRACK 02 AUDIO\n    INDEX 01 00:00:00\n",
+		"FILE \"a.wav\" WAVE\n  TRACK 03 AUDIO\n    INDEX 01 00:02:00\n",
+	)).unwrap();
+	
+	let loaded = load_cue_sheet(dir.join("album.cue"), &LoadOptions::default(), &settings).unwrap();
+	assert_eq!(loaded.iter().map(|loaded| tag(loaded, "TRACKNUMBER")).collect::<Vec<_>>(), [Some("1"), Some("2"), Some("3")]);
+	assert_eq!(loaded.iter().map(|loaded| loaded.track.length()).collect::<Vec<_>>(), [2 * 48000, 48000, 48000]);
+	
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn vorbis_chapters_split_the_file() {
+	let path = temp_path("chapters.flac");
+	let settings = ProjectSettings::default();
+	let comments = [("CHAPTER002", "00:00:01.500"), ("CHAPTER002NAME", "Impact"), ("CHAPTER001", "00:00:00.000"), ("CHAPTER001NAME", "Whoosh")];
+	let options = FlacExportOptions { comments: comments.map(|(key, value)| (key.into(), value.into())).to_vec(), ..Default::default() };
+	export_flac(&test_track(3 * 48000, ExportFormat::Pcm24), &path, &settings, &options).unwrap();
+	
+	let loaded = load_audio_split(AudioSource::from(path.as_path()), &LoadOptions::default(), None, &settings).unwrap();
+	assert_eq!(loaded.iter().map(|loaded| loaded.track.length()).collect::<Vec<_>>(), [72000, 72000]);
+	assert_eq!(loaded.iter().map(|loaded| tag(loaded, "TITLE")).collect::<Vec<_>>(), [Some("Whoosh"), Some("Impact")]);
+	
+	std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn chapter_times() {
+	let tags = [("CHAPTER1", "1:02:03.5"), ("chapter2", "2:30"), ("CHAPTER3", "7.25"), ("CHAPTER4", "soon"), ("CHAPTERS", "0")]
+		.map(|(key, value)| MetadataTag { key: key.into(), std_key: None, value: value.into() });
+	let sheet = CueSheet::from_chapters(&tags).unwrap();
+	assert_eq!(sheet.tracks.iter().map(|track| (track.number, track.start)).collect::<Vec<_>>(), [(1, 7.25), (2, 150.0), (3, 3723.5)]);
+	assert!(CueSheet::from_chapters(&[]).is_none());
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use sfx_daw::*;
+
+mod common;
+use common::*;
+
+
+/// FLAC file of a few seconds of noise, with a bad subframe header in its eleventh frame
+fn damaged_flac(name: &str) -> PathBuf {
+	let settings = ProjectSettings::default();
+	let mut track = AudioTrack::<2>::new(3 * settings.rate() as usize, settings.rate());
+	let mut noise = Noise::new();
+	for channel in &mut track.data {
+		for sample in channel.iter_mut() { *sample = noise.next(); }
+	}
+	let path = temp_path(name);
+	export_flac(&track, &path, &settings, &FlacExportOptions { format: ExportFormat::Pcm16, dither: Dither::None, ..Default::default() }).unwrap();
+	
+	// Frame headers are the sync code, 4096 frames at 48 kHz, the channel layout and 16 bits, and the frame number
+	let mut bytes = std::fs::read(&path).unwrap();
+	let find_frame = |bytes: &[u8], number: u8| bytes.windows(5).position(|header| header[0..3] == [0xff, 0xf8, 0xca] && header[3] & 0x0f == 0x08 && header[4] == number).unwrap();
+	let (start, end) = (find_frame(&bytes, 10), find_frame(&bytes, 11));
+	// The padding bit before the subframe type has to be zero. The frame's CRC is fixed up so the reader doesn't drop it first.
+	bytes[start + 6] |= 0x80;
+	let crc = crc16(&bytes[start..(end - 2)]);
+	bytes[(end - 2)..end].copy_from_slice(&crc.to_be_bytes());
+	std::fs::write(&path, bytes).unwrap();
+	path
+}
+
+/// CRC-16 with polynomial 0x8005, the one ending FLAC frames
+fn crc16(bytes: &[u8]) -> u16 {
+	let mut crc = 0u16;
+	for &byte in bytes {
+		crc ^= (byte as u16) << 8;
+		for _ in 0..8 { crc = if crc & 0x8000 != 0 {(crc << 1) ^ 0x8005} else {crc << 1}; }
+	}
+	crc
+}
+
+
+#[test]
+fn failing_load_reports_the_packet_position() {
+	let path = damaged_flac("fail.flac");
+	let options = LoadOptions { decode_errors: DecodeErrorPolicy::Fail, ..Default::default() };
+	match load_audio_with_options(&[(&path, options)], &ProjectSettings::default()) {
+		Err(LoadError::Decode { timestamp, seconds, .. }) => {
+			assert_eq!(timestamp, Some(10 * 4096));
+			assert!((seconds - 10.0 * 4096.0 / 48000.0).abs() < 1e-9, "{seconds}");
+		}
+		Err(error) => panic!("{error}"),
+		Ok(_) => panic!("Damaged file loaded"),
+	}
+	std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn lenient_load_records_issues() {
+	let path = damaged_flac("skip.flac");
+	let options = LoadOptions { decode_errors: DecodeErrorPolicy::ZeroFill, ..Default::default() };
+	let loaded = load_audio_with_options(&[(&path, options)], &ProjectSettings::default()).unwrap().remove(0);
+	assert_eq!(loaded.decode_issues.len(), 1);
+	assert_eq!(loaded.decode_issues[0].timestamp, Some(10 * 4096));
+	assert_eq!(loaded.decode_issues[0].frame, 10 * 4096);
+	assert_eq!(loaded.decode_issues[0].filled_frames, 4096);
+	assert_eq!(loaded.track.length(), 3 * 48000);
+	std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,95 @@
+use std::path::PathBuf;
+
+use sfx_daw::*;
+
+mod common;
+use common::*;
+
+
+/// Mono track holding `bytes` as little-endian samples, which is what the STREAMINFO MD5 is taken over
+fn track_of_bytes(bytes: &[u8], format: ExportFormat) -> AudioTrack<1> {
+	let width = format.bits() as usize / 8;
+	let scale = (1 << (format.bits() - 1)) as f32;
+	let samples = bytes.chunks_exact(width).map(|sample| {
+		let mut value = [0; 4];
+		value[(4 - width)..].copy_from_slice(sample);
+		(i32::from_le_bytes(value) >> (32 - 8 * width)) as f32 / scale
+	}).collect::<Vec<_>>();
+	AudioTrack { data: [TrackChannel::from(samples)], sample_rate: ProjectSettings::default().rate() }
+}
+
+fn streaminfo_md5(path: &PathBuf) -> String {
+	let bytes = std::fs::read(path).unwrap();
+	// fLaC, the block header, then the MD5 as the last 16 bytes of the 34 byte STREAMINFO
+	bytes[26..42].iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+
+#[test]
+fn round_trip_every_depth_and_level() {
+	let settings = ProjectSettings::default();
+	let path = temp_path("round_trip.flac");
+	
+	// Longer than a block with a short last one, shorter than one block, a block size exactly, and frames too few for any predictor
+	for frames in [3 * 4096 + 1237, 1000, 4096, 5, 1] {
+		for format in [ExportFormat::Pcm16, ExportFormat::Pcm24] {
+			let track = test_track(frames, format);
+			for compression_level in 0..=8 {
+				let options = FlacExportOptions { format, compression_level, dither: Dither::None, ..Default::default() };
+				export_flac(&track, &path, &settings, &options).unwrap();
+				let loaded = load_audio(&[&path], &settings).unwrap().remove(0);
+				assert!(loaded.data == track.data, "{format:?} at level {compression_level}, {frames} frames");
+			}
+		}
+	}
+	
+	std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn round_trip_extremes() {
+	let settings = ProjectSettings::default();
+	let path = temp_path("extremes.flac");
+	
+	for format in [ExportFormat::Pcm16, ExportFormat::Pcm24] {
+		let scale = (1 << (format.bits() - 1)) as f32;
+		// Full scale square waves and silence, the widest residuals and constant subframes
+		let mut track = AudioTrack::new(9000, settings.rate());
+		for i in 0..6000 {
+			track.data[0][i] = if i / 7 % 2 == 0 {-1.0} else {(scale - 1.0) / scale};
+			track.data[1][i] = if i % 2 == 0 {-1.0} else {(scale - 1.0) / scale};
+		}
+		for compression_level in [0, 5, 8] {
+			export_flac(&track, &path, &settings, &FlacExportOptions { format, compression_level, dither: Dither::None, ..Default::default() }).unwrap();
+			let loaded = load_audio(&[&path], &settings).unwrap().remove(0);
+			assert!(loaded.data == track.data, "{format:?} at level {compression_level}");
+		}
+	}
+	
+	std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn streaminfo_md5_matches_known_vectors() {
+	let settings = ProjectSettings::default();
+	let path = temp_path("md5.flac");
+	
+	let vectors: [(&[u8], ExportFormat, &str); 5] = [
+		(b"", ExportFormat::Pcm16, "d41d8cd98f00b204e9800998ecf8427e"),
+		(b"abc", ExportFormat::Pcm24, "900150983cd24fb0d6963f7d28e17f72"),
+		(b"message digest", ExportFormat::Pcm16, "f96b697d7cb7938d525a2f31aaf161d0"),
+		(b"abcdefghijklmnopqrstuvwxyz", ExportFormat::Pcm16, "c3fcd3d76192e4007dfb496cca67e13b"),
+		(b"12345678901234567890123456789012345678901234567890123456789012345678901234567890", ExportFormat::Pcm16, "57edf4a22be3c955ac49da2e2107b67a"),
+	];
+	for (bytes, format, md5) in vectors {
+		export_flac(&track_of_bytes(bytes, format), &path, &settings, &FlacExportOptions { format, dither: Dither::None, ..Default::default() }).unwrap();
+		assert_eq!(streaminfo_md5(&path), md5, "{:?}", String::from_utf8_lossy(bytes));
+	}
+	
+	// A million bytes, through many MD5 blocks and FLAC frames
+	let million = vec![b'a'; 1_000_000];
+	export_flac(&track_of_bytes(&million, ExportFormat::Pcm16), &path, &settings, &FlacExportOptions { format: ExportFormat::Pcm16, dither: Dither::None, ..Default::default() }).unwrap();
+	assert_eq!(streaminfo_md5(&path), "7707d6ae4e027c70eea2a935c2296f21");
+	
+	std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use sfx_daw::*;
+
+mod common;
+use common::*;
+
+
+/// Two seconds of a sweep with a marker, exported at `rate`
+fn source(name: &str, rate: ProjectRate) -> PathBuf {
+	let settings = ProjectSettings { sample_rate: rate, ..Default::default() };
+	let frames = 2 * rate.hz() as usize;
+	let mut track = AudioTrack::<2>::new(frames, rate.hz());
+	for i in 0..frames {
+		let t = i as f32 / rate.hz() as f32;
+		track.data[0][i] = 0.5 * (std::f32::consts::TAU * (200.0 + 2000.0 * t) * t).sin();
+		track.data[1][i] = 0.25 * (std::f32::consts::TAU * 3000.0 * t).sin();
+	}
+	let metadata = TrackMetadata {
+		markers: vec![Marker { cue_id: 1, frame: rate.hz() as usize, label: None, length: None }],
+		..Default::default()
+	};
+	
+	let path = temp_path(name);
+	export_wav(&track, &path, &settings, &WavExportOptions { format: ExportFormat::Float32, metadata, ..Default::default() }).unwrap();
+	path
+}
+
+/// Loads `region` on its own and as part of the whole file, returning both
+fn region_and_cut(path: &PathBuf, region: std::ops::Range<f64>, settings: &ProjectSettings) -> (LoadedTrack, AudioTrack<2>) {
+	let loaded = load_audio_with_options(&[(path, LoadOptions { region: Some(region.clone()), ..Default::default() })], settings).unwrap().remove(0);
+	let full = load_audio(&[path], settings).unwrap().remove(0);
+	let frames = settings.seconds_to_frames(region.start)..settings.seconds_to_frames(region.end).min(full.length());
+	(loaded, AudioTrack::clone_range(&full, frames))
+}
+
+
+#[test]
+fn region_at_project_rate_matches_full_load() {
+	let settings = ProjectSettings::default();
+	let path = source("region_48k.wav", ProjectRate::Hz48000);
+	
+	let (loaded, cut) = region_and_cut(&path, 0.4..1.3, &settings);
+	assert!(loaded.track.data == cut.data);
+	assert_eq!(loaded.metadata.markers[0].frame, 48000 - 19200);
+	
+	std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn resampled_region_matches_full_load() {
+	let path = source("region_44k.wav", ProjectRate::Hz44100);
+	
+	for resampler in [ResamplerQuality::Draft, ResamplerQuality::HighQuality] {
+		let settings = ProjectSettings { resampler, ..Default::default() };
+		for region in [0.4..1.3, 0.0..0.25, 1.5..5.0] {
+			let (loaded, cut) = region_and_cut(&path, region.clone(), &settings);
+			assert_eq!(loaded.track.length(), cut.length(), "{resampler:?} {region:?}");
+			
+			// Same frames, but the resampler works through them in other blocks, so the sums round differently
+			let max_difference = loaded.track.data.iter().zip(&cut.data)
+				.flat_map(|(loaded, cut)| loaded.iter().zip(cut.iter()).map(|(a, b)| (a - b).abs()))
+				.fold(0.0, f32::max);
+			assert!(max_difference < 1e-6, "{resampler:?} {region:?}: {max_difference}");
+		}
+	}
+	
+	std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,98 @@
+use sfx_daw::*;
+
+
+#[test]
+fn seconds_round_to_the_nearest_frame() {
+	for rate in ProjectRate::ALL {
+		let settings = ProjectSettings { sample_rate: rate, ..Default::default() };
+		let track = AudioTrack::<1>::new(0, rate.hz());
+		for seconds in [0.0, 0.29, 0.57, 1.1, 2.5 / rate.hz() as f64, 10.0 / 3.0, -1.0] {
+			assert_eq!(settings.seconds_to_frames(seconds), track.seconds_to_frames(seconds), "{seconds} at {}", rate.hz());
+		}
+	}
+	
+	let settings = ProjectSettings::default();
+	// 13919.999… frames
+	assert_eq!(settings.seconds_to_frames(0.29), 13920);
+	assert_eq!(settings.seconds_to_frames(0.4 / 48000.0), 0);
+	assert_eq!(settings.seconds_to_frames(0.6 / 48000.0), 1);
+	assert_eq!(settings.seconds_to_frames(-0.5), 0);
+	
+	let track = AudioTrack::<1>::new(0, 44100);
+	for frames in [0, 1, 22050, 44100 * 60 + 7] {
+		assert_eq!(track.seconds_to_frames(track.frames_to_seconds(frames)), frames);
+	}
+}
+
+#[test]
+fn musical_time() {
+	let tempo = Tempo::default();
+	let time = MusicalTime::from_beats(5.5, &tempo);
+	assert_eq!(time, MusicalTime { bar: 1, beat: 1, tick: 480 });
+	assert_eq!(time.to_string(), "2.2.480");
+	assert_eq!(time.to_beats(&tempo), 5.5);
+	assert_eq!(time.to_seconds(&tempo), 2.75);
+	assert_eq!(MusicalTime::from_seconds(2.75, &tempo), time);
+	
+	assert_eq!(MusicalTime::from_beats(-1.0, &tempo), MusicalTime::default());
+	assert_eq!(MusicalTime::default().to_string(), "1.1.000");
+	// Rounds to the nearest tick
+	assert_eq!(MusicalTime::from_beats(1.0 - 0.4 / TICKS_PER_BEAT as f64, &tempo), MusicalTime { bar: 0, beat: 1, tick: 0 });
+	
+	let waltz = Tempo { bpm: 90.0, beats_per_bar: 3 };
+	assert_eq!(MusicalTime::from_beats(7.0, &waltz), MusicalTime { bar: 2, beat: 1, tick: 0 });
+	assert_eq!(MusicalTime::from_seconds(2.0, &waltz), MusicalTime { bar: 1, beat: 0, tick: 0 });
+	
+	let track = AudioTrack::<1>::new(0, 48000);
+	assert_eq!(track.frames_to_musical(132000, &tempo), time);
+	assert_eq!(track.musical_to_frames(&time, &tempo), 132000);
+}
+
+#[test]
+fn drop_frame_timecode() {
+	let rate = TimecodeRate::Fps29_97Drop;
+	let timecode = |hours, minutes, seconds, frames| Timecode { hours, minutes, seconds, frames };
+	// Frame numbers 0 and 1 are skipped at each minute, except every tenth
+	for (count, expected) in [
+		(0, timecode(0, 0, 0, 0)),
+		(1799, timecode(0, 0, 59, 29)),
+		(1800, timecode(0, 1, 0, 2)),
+		(3597, timecode(0, 1, 59, 29)),
+		(3598, timecode(0, 2, 0, 2)),
+		(17981, timecode(0, 9, 59, 29)),
+		(17982, timecode(0, 10, 0, 0)),
+		(17982 + 1800, timecode(0, 11, 0, 2)),
+		(107892, timecode(1, 0, 0, 0)),
+	] {
+		assert_eq!(Timecode::from_frame_count(count, rate), expected, "{count}");
+		assert_eq!(expected.frame_count(rate), count, "{expected}");
+	}
+	for count in 0..40000 {
+		assert_eq!(Timecode::from_frame_count(count, rate).frame_count(rate), count);
+	}
+	
+	// An hour of drop frame timecode is an hour of real time, to within a few milliseconds
+	assert!((timecode(1, 0, 0, 0).to_seconds(rate) - 3600.0).abs() < 0.005);
+	assert_eq!(Timecode::from_seconds(timecode(0, 1, 0, 2).to_seconds(rate), rate), timecode(0, 1, 0, 2));
+}
+
+#[test]
+fn timecode() {
+	let timecode = Timecode { hours: 1, minutes: 2, seconds: 3, frames: 4 };
+	assert_eq!(timecode.to_string(), "01:02:03:04");
+	assert_eq!(Timecode::parse("01:02:03:04"), Some(timecode));
+	assert_eq!(Timecode::parse("01:02:03;04"), Some(timecode));
+	assert_eq!(Timecode::parse("01:60:03:04"), None);
+	assert_eq!(Timecode::parse("01:02:03"), None);
+	
+	assert_eq!(timecode.frame_count(TimecodeRate::Fps25), 3723 * 25 + 4);
+	assert_eq!(timecode.to_seconds(TimecodeRate::Fps25), 3723.16);
+	// Times fall in the video frame that has started
+	assert_eq!(Timecode::from_seconds(0.079, TimecodeRate::Fps25), Timecode { frames: 1, ..Default::default() });
+	assert_eq!(Timecode::from_seconds(0.08, TimecodeRate::Fps25), Timecode { frames: 2, ..Default::default() });
+	
+	let track = AudioTrack::<1>::new(0, 48000);
+	assert_eq!(track.frames_to_timecode(48000 * 3723 + 7680, TimecodeRate::Fps25), timecode);
+	assert_eq!(track.timecode_to_frames(&timecode, TimecodeRate::Fps25), 48000 * 3723 + 7680);
+	assert_eq!(track.timecode_to_frames(&timecode, TimecodeRate::Fps24), 48000 * 3723 + 8000);
+}
@@ -0,0 +1,93 @@
+use sfx_daw::*;
+
+
+fn ramp(frames: usize) -> AudioTrack<1> {
+	AudioTrack { data: [(0..frames).map(|i| i as f32).collect()], sample_rate: 48000 }
+}
+
+fn samples(track: &AudioTrack<1>) -> Vec<f32> {
+	track.data[0].iter().copied().collect()
+}
+
+fn at_44100(track: AudioTrack<1>) -> AudioTrack<1> {
+	AudioTrack { sample_rate: 44100, ..track }
+}
+
+const RATE_MISMATCH: TrackError = TrackError::RateMismatch { expected: 48000, found: 44100 };
+
+
+#[test]
+fn concat() {
+	assert_eq!(samples(&ramp(3).concat(&ramp(2)).unwrap()), [0.0, 1.0, 2.0, 0.0, 1.0]);
+	assert_eq!(samples(&ramp(3).concat(&ramp(0)).unwrap()), [0.0, 1.0, 2.0]);
+	assert_eq!(samples(&ramp(0).concat(&ramp(2)).unwrap()), [0.0, 1.0]);
+	assert_eq!(ramp(3).concat(&at_44100(ramp(2))).err(), Some(RATE_MISMATCH));
+}
+
+#[test]
+fn insert() {
+	let track = ramp(3);
+	let other = AudioTrack { data: [vec![-1.0; 2].into()], sample_rate: 48000 };
+	assert_eq!(samples(&track.insert(0, &other).unwrap()), [-1.0, -1.0, 0.0, 1.0, 2.0]);
+	assert_eq!(samples(&track.insert(1, &other).unwrap()), [0.0, -1.0, -1.0, 1.0, 2.0]);
+	assert_eq!(samples(&track.insert(3, &other).unwrap()), [0.0, 1.0, 2.0, -1.0, -1.0]);
+	assert!(matches!(track.insert(4, &other), Err(TrackError::OutOfRange { length: 3, .. })));
+	assert_eq!(track.insert(0, &at_44100(other)).err(), Some(RATE_MISMATCH));
+}
+
+#[test]
+fn delete() {
+	let track = ramp(4);
+	assert_eq!(samples(&track.delete(0..1).unwrap()), [1.0, 2.0, 3.0]);
+	assert_eq!(samples(&track.delete(1..3).unwrap()), [0.0, 3.0]);
+	assert_eq!(samples(&track.delete(3..4).unwrap()), [0.0, 1.0, 2.0]);
+	assert_eq!(samples(&track.delete(2..2).unwrap()), samples(&track));
+	assert_eq!(track.delete(0..4).unwrap().length(), 0);
+	assert!(matches!(track.delete(2..5), Err(TrackError::OutOfRange { length: 4, .. })));
+	assert!(matches!(track.delete(5..5), Err(TrackError::OutOfRange { length: 4, .. })));
+}
+
+#[test]
+fn reverse_range() {
+	let track = ramp(4);
+	assert_eq!(samples(&track.reverse_range(0..4).unwrap()), [3.0, 2.0, 1.0, 0.0]);
+	assert_eq!(samples(&track.reverse_range(0..2).unwrap()), [1.0, 0.0, 2.0, 3.0]);
+	assert_eq!(samples(&track.reverse_range(2..4).unwrap()), [0.0, 1.0, 3.0, 2.0]);
+	assert_eq!(samples(&track.reverse_range(4..4).unwrap()), samples(&track));
+	assert!(matches!(track.reverse_range(3..5), Err(TrackError::OutOfRange { length: 4, .. })));
+	// Reversing a range leaves the track it came from alone
+	assert_eq!(samples(&track), [0.0, 1.0, 2.0, 3.0]);
+}
+
+#[test]
+fn overwrite() {
+	let track = ramp(4);
+	let other = AudioTrack { data: [vec![-1.0; 2].into()], sample_rate: 48000 };
+	assert_eq!(samples(&track.overwrite(0, &other).unwrap()), [-1.0, -1.0, 2.0, 3.0]);
+	assert_eq!(samples(&track.overwrite(2, &other).unwrap()), [0.0, 1.0, -1.0, -1.0]);
+	// Running past the end makes the track longer
+	assert_eq!(samples(&track.overwrite(3, &other).unwrap()), [0.0, 1.0, 2.0, -1.0, -1.0]);
+	assert_eq!(samples(&track.overwrite(4, &other).unwrap()), [0.0, 1.0, 2.0, 3.0, -1.0, -1.0]);
+	assert_eq!(samples(&track.overwrite(1, &ramp(0)).unwrap()), samples(&track));
+	assert!(matches!(track.overwrite(5, &other), Err(TrackError::OutOfRange { length: 4, .. })));
+	assert_eq!(track.overwrite(0, &at_44100(other)).err(), Some(RATE_MISMATCH));
+}
+
+#[test]
+fn repeat() {
+	let track = ramp(2);
+	assert_eq!(samples(&track.repeat(3)), [0.0, 1.0, 0.0, 1.0, 0.0, 1.0]);
+	assert_eq!(samples(&track.repeat(1)), samples(&track));
+	assert_eq!(track.repeat(0).length(), 0);
+	assert_eq!(track.repeat(0).sample_rate, 48000);
+	assert_eq!(ramp(0).repeat(5).length(), 0);
+}
+
+#[test]
+fn single_ranges_are_views() {
+	let track = ramp(4);
+	assert!(track.trim(1..3).unwrap().data[0].shares_samples(&track.data[0].slice(1..3)));
+	assert!(track.delete(0..2).unwrap().data[0].shares_samples(&track.data[0].slice(2..4)));
+	assert!(track.concat(&ramp(0)).unwrap().data[0].shares_samples(&track.data[0]));
+	assert!(track.repeat(1).data[0].shares_samples(&track.data[0]));
+}
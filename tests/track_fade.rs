@@ -0,0 +1,97 @@
+use sfx_daw::*;
+
+
+fn constant(frames: usize, value: f32) -> AudioTrack<1> {
+	AudioTrack { data: [vec![value; frames].into()], sample_rate: 48000 }
+}
+
+fn assert_near(found: f32, expected: f32) {
+	assert!((found - expected).abs() < 1e-6, "{found} is not {expected}");
+}
+
+const CURVES: [FadeCurve; 4] = [FadeCurve::Linear, FadeCurve::EqualPower, FadeCurve::Logarithmic, FadeCurve::SCurve];
+
+
+#[test]
+fn fades_reach_silence_and_full_gain() {
+	let track = constant(100, 1.0);
+	for curve in CURVES {
+		let faded = track.fade_in(10, &curve).unwrap();
+		assert_eq!(faded.length(), 100, "{curve:?}");
+		assert_eq!(faded.data[0][0], 0.0, "{curve:?}");
+		assert_eq!(faded.data[0][9], 1.0, "{curve:?}");
+		assert!(faded.data[0][1..9].iter().all(|&sample| sample > 0.0 && sample < 1.0), "{curve:?}");
+		assert!(faded.data[0][10..] == track.data[0][10..], "{curve:?}");
+		
+		let faded = track.fade_out(10, &curve).unwrap();
+		assert_eq!(faded.length(), 100, "{curve:?}");
+		assert!(faded.data[0][..90] == track.data[0][..90], "{curve:?}");
+		assert_eq!(faded.data[0][90], 1.0, "{curve:?}");
+		assert!(faded.data[0][91..99].iter().all(|&sample| sample > 0.0 && sample < 1.0), "{curve:?}");
+		assert_eq!(faded.data[0][99], 0.0, "{curve:?}");
+	}
+}
+
+#[test]
+fn linear_fades_are_even() {
+	let faded = constant(5, 1.0).fade_in(5, &FadeCurve::Linear).unwrap();
+	assert_eq!(faded.data[0][..], [0.0, 0.25, 0.5, 0.75, 1.0]);
+	let faded = constant(5, 1.0).fade_out(5, &FadeCurve::Linear).unwrap();
+	assert_eq!(faded.data[0][..], [1.0, 0.75, 0.5, 0.25, 0.0]);
+}
+
+#[test]
+fn short_fades() {
+	let track = constant(4, 1.0);
+	assert!(track.fade_in(0, &FadeCurve::Linear).unwrap().data == track.data);
+	assert!(track.fade_out(0, &FadeCurve::Linear).unwrap().data == track.data);
+	assert_eq!(track.fade_in(1, &FadeCurve::Linear).unwrap().data[0][..], [0.0, 1.0, 1.0, 1.0]);
+	assert_eq!(track.fade_out(1, &FadeCurve::Linear).unwrap().data[0][..], [1.0, 1.0, 1.0, 0.0]);
+	assert!(matches!(track.fade_in(5, &FadeCurve::Linear), Err(TrackError::OutOfRange { .. })));
+	assert!(matches!(track.fade_out(5, &FadeCurve::Linear), Err(TrackError::OutOfRange { .. })));
+	assert!(matches!(track.fade_in(2, &FadeCurve::Table(vec![1.0])), Err(TrackError::FadeTable { points: 1 })));
+}
+
+#[test]
+fn crossfade_overlaps() {
+	let track = constant(100, 1.0);
+	let other = constant(50, 2.0);
+	let faded = track.crossfade(&other, 10, &FadeCurve::Linear).unwrap();
+	assert_eq!(faded.length(), 140);
+	assert!(faded.data[0][..90] == track.data[0][..90]);
+	assert!(faded.data[0][100..] == other.data[0][10..]);
+	// Gains are taken at the middle of each frame
+	assert_near(faded.data[0][90], 1.0 * 0.95 + 2.0 * 0.05);
+	assert_near(faded.data[0][99], 1.0 * 0.05 + 2.0 * 0.95);
+	
+	// Linear crossfades keep the amplitude of the same material constant
+	let faded = track.crossfade(&track, 100, &FadeCurve::Linear).unwrap();
+	assert_eq!(faded.length(), 100);
+	for &sample in faded.data[0].iter() { assert_near(sample, 1.0); }
+	
+	assert_eq!(track.crossfade(&other, 0, &FadeCurve::Linear).unwrap().length(), 150);
+	assert!(matches!(track.crossfade(&other, 51, &FadeCurve::Linear), Err(TrackError::OutOfRange { .. })));
+	assert!(matches!(track.crossfade(&AudioTrack { sample_rate: 44100, ..other }, 10, &FadeCurve::Linear), Err(TrackError::RateMismatch { expected: 48000, found: 44100 })));
+}
+
+#[test]
+fn splice_keeps_hard_cut_length() {
+	let track = constant(100, 1.0);
+	let other = constant(30, 2.0);
+	let spliced = track.splice(40..60, &other, 5, &FadeCurve::Linear).unwrap();
+	assert_eq!(spliced.length(), 100 - 20 + 30);
+	assert!(spliced.data[0][..40] == track.data[0][..40]);
+	// Fading into the other track, its middle, and fading back
+	assert_near(spliced.data[0][40], 1.0 * 0.9 + 2.0 * 0.1);
+	assert_near(spliced.data[0][44], 1.0 * 0.1 + 2.0 * 0.9);
+	assert!(spliced.data[0][45..65].iter().all(|&sample| sample == 2.0));
+	assert_near(spliced.data[0][65], 2.0 * 0.9 + 1.0 * 0.1);
+	assert_near(spliced.data[0][69], 2.0 * 0.1 + 1.0 * 0.9);
+	assert!(spliced.data[0][70..] == track.data[0][60..]);
+	
+	assert_eq!(track.splice(40..60, &other, 0, &FadeCurve::Linear).unwrap().length(), 110);
+	// The other track needs room for both fades, and the fades need frames of this track on both sides
+	assert!(matches!(track.splice(40..60, &other, 16, &FadeCurve::Linear), Err(TrackError::OutOfRange { .. })));
+	assert!(matches!(track.splice(0..4, &other, 5, &FadeCurve::Linear), Err(TrackError::OutOfRange { .. })));
+	assert!(matches!(track.splice(98..100, &other, 5, &FadeCurve::Linear), Err(TrackError::OutOfRange { .. })));
+}
@@ -0,0 +1,39 @@
+use sfx_daw::*;
+
+
+fn ramp(frames: usize) -> AudioTrack<1> {
+	AudioTrack { data: [(0..frames).map(|i| i as f32 / frames as f32).collect()], sample_rate: 48000 }
+}
+
+
+#[test]
+fn length_does_not_depend_on_gain() {
+	let track = ramp(100);
+	let other = ramp(50);
+	for gain in [0.0, 0.5, 1.0] {
+		assert_eq!(track.mix(&other, 80, gain).unwrap().length(), 130, "gain {gain}");
+		assert_eq!(track.mix(&other, 20, gain).unwrap().length(), 100, "gain {gain}");
+	}
+	
+	let silent = track.mix(&other, 80, 0.0).unwrap();
+	assert!(silent.data[0][..100] == track.data[0][..]);
+	assert!(silent.data[0][100..].iter().all(|&sample| sample == 0.0));
+}
+
+#[test]
+fn mixes_from_offset() {
+	let track = ramp(100);
+	let other = ramp(50);
+	let mixed = track.mix(&other, 80, 0.5).unwrap();
+	assert_eq!(mixed.data[0][79], track.data[0][79]);
+	assert_eq!(mixed.data[0][90], track.data[0][90] + 0.5 * other.data[0][10]);
+	assert_eq!(mixed.data[0][120], 0.5 * other.data[0][40]);
+}
+
+#[test]
+fn empty_track_mixes_at_the_end() {
+	let track = ramp(100);
+	assert!(track.mix(&ramp(0), 100, 1.0).unwrap().data == track.data);
+	assert!(track.mix(&ramp(0), 101, 1.0).is_err());
+	assert_eq!(ramp(0).mix(&track, 0, 0.0).unwrap().length(), 100);
+}
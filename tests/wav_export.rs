@@ -0,0 +1,96 @@
+use sfx_daw::*;
+
+mod common;
+use common::*;
+
+
+#[test]
+fn round_trip_every_format() {
+	let settings = ProjectSettings::default();
+	let path = temp_path("round_trip.wav");
+	
+	for format in [ExportFormat::Pcm16, ExportFormat::Pcm24, ExportFormat::Float32] {
+		let mut track = test_track(10_000, format);
+		// Full scale and silence, and for float samples past full scale, which it keeps
+		track.data[0][0] = -1.0;
+		track.data[1][0] = 0.0;
+		if format == ExportFormat::Float32 { track.data[0][1] = 1.5; }
+		
+		export_wav(&track, &path, &settings, &WavExportOptions { format, dither: Dither::None, ..Default::default() }).unwrap();
+		let loaded = load_audio(&[&path], &settings).unwrap().remove(0);
+		assert!(loaded.data == track.data, "{format:?}");
+	}
+	
+	std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn round_trip_rf64() {
+	let settings = ProjectSettings::default();
+	let path = temp_path("round_trip_rf64.wav");
+	let track = test_track(10_000, ExportFormat::Pcm24);
+	let metadata = TrackMetadata {
+		markers: vec![Marker { cue_id: 1, frame: 4321, label: Some("hit".into()), length: None }],
+		..Default::default()
+	};
+	
+	let options = WavExportOptions { dither: Dither::None, container: WavContainer::Rf64, metadata: metadata.clone(), ..Default::default() };
+	export_wav(&track, &path, &settings, &options).unwrap();
+	assert_eq!(&std::fs::read(&path).unwrap()[0..4], b"RF64");
+	
+	let loaded = load_audio_with_options(&[(&path, LoadOptions::default())], &settings).unwrap().remove(0);
+	assert!(loaded.track.data == track.data);
+	assert_eq!(loaded.metadata.markers, metadata.markers);
+	
+	std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn export_leaves_other_files_alone() {
+	let settings = ProjectSettings::default();
+	let dir = temp_dir("neighbours");
+	let track = test_track(1000, ExportFormat::Pcm24);
+	let options = WavExportOptions { dither: Dither::None, ..Default::default() };
+	
+	// A file with the name a temporary file used to have, exports sharing a stem, and a target that is itself a .tmp file
+	std::fs::write(dir.join("foo.tmp"), "keep").unwrap();
+	export_wav(&track, dir.join("foo.wav"), &settings, &options).unwrap();
+	export_flac(&track, dir.join("foo.flac"), &settings, &FlacExportOptions { dither: Dither::None, ..Default::default() }).unwrap();
+	export_wav(&track, dir.join("bar.tmp"), &settings, &options).unwrap();
+	
+	assert_eq!(std::fs::read_to_string(dir.join("foo.tmp")).unwrap(), "keep");
+	assert_eq!(files_in(&dir), ["bar.tmp", "foo.flac", "foo.tmp", "foo.wav"]);
+	
+	let options = LoadOptions { hint: SourceHint { extension: Some("wav".into()), ..Default::default() }, ..Default::default() };
+	let loaded = load_audio_with_options(&[(dir.join("bar.tmp"), options)], &settings).unwrap().remove(0);
+	assert!(loaded.track.data == track.data);
+	
+	std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rf64_oversized_chunks() {
+	let settings = ProjectSettings::default();
+	let path = temp_path("oversized.wav");
+	let track = test_track(1000, ExportFormat::Pcm24);
+	export_wav(&track, &path, &settings, &WavExportOptions { dither: Dither::None, container: WavContainer::Rf64, ..Default::default() }).unwrap();
+	let bytes = std::fs::read(&path).unwrap();
+	let data = bytes.windows(4).position(|id| id == b"data").unwrap();
+	let with_chunk = |chunk: &[u8]| [&bytes[..data], chunk, &bytes[data..]].concat();
+	
+	// Bigger than any metadata chunk, skipped without reading it
+	let mut junk = b"JUNK".to_vec();
+	junk.extend_from_slice(&(17u32 << 20).to_le_bytes());
+	junk.resize(8 + (17 << 20), 0);
+	std::fs::write(&path, with_chunk(&junk)).unwrap();
+	let loaded = load_audio(&[&path], &settings).unwrap().remove(0);
+	assert!(loaded.data == track.data);
+	
+	// A size far past the end of the file fails to load instead of allocating it
+	let mut bogus = b"JUNK".to_vec();
+	bogus.extend_from_slice(&(u32::MAX - 1).to_le_bytes());
+	std::fs::write(&path, with_chunk(&bogus)).unwrap();
+	assert!(load_audio(&[&path], &settings).is_err());
+	
+	std::fs::remove_file(path).unwrap();
+}